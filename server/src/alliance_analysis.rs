@@ -0,0 +1,343 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::analysis::TeamInfo;
+use crate::season::{
+	AUTO_DOCKED_POINTS, AUTO_ENGAGED_POINTS, AUTO_ROW_POINTS, CONE_NODES_PER_ROW,
+	CUBE_NODES_PER_ROW, HYBRID_NODES, NODES_PER_LINK, PARK_POINTS, SUPERCHARGE_POINTS,
+	TELEOP_DOCKED_POINTS, TELEOP_ENGAGED_POINTS, TELEOP_ROW_POINTS,
+};
+
+// Robots that would score less than this share of the alliance's teleop grid points are better off playing defence.
+const DEFENCE_SHARE: f32 = 0.15;
+
+// Gamepieces per match by row (hybrid, middle, high) and gamepiece (cone, cube).
+type Pieces = [[f32; 2]; 3];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AutoRole {
+	ChargeStation,
+	Scoring,
+	Mobility,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TeleopRole {
+	HighCones,
+	HighCubes,
+	MiddleCones,
+	MiddleCubes,
+	Hybrid,
+	Defence,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RoleAssignment {
+	pub team_number: u32,
+	pub auto: AutoRole,
+	pub teleop: TeleopRole,
+	// Points this robot is expected to add with the rest of the alliance on the field.
+	pub auto_points: f32,
+	pub teleop_points: f32,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AllianceAnalysis {
+	pub teams: Vec<u32>,
+	// Teams that haven't been scouted yet, these are estimated using the average team.
+	pub unscouted_teams: Vec<u32>,
+	// What the teams score on their own, added up.
+	pub unconstrained_score: f32,
+	pub estimated_score: f32,
+	pub mobility_score: f32,
+	pub auto_grid_score: f32,
+	pub auto_charge_station_score: f32,
+	pub teleop_grid_score: f32,
+	pub supercharge_score: f32,
+	pub endgame_score: f32,
+	// Gamepieces the teams would score on their own that don't fit in the grid.
+	pub lost_game_pieces: f32,
+	pub links: f32,
+	pub roles: Vec<RoleAssignment>,
+}
+
+// Nodes that are still empty.
+struct Grid {
+	hybrid: f32,
+	// Cone and cube nodes in the middle and high rows.
+	upper: [[f32; 2]; 2],
+}
+
+impl Grid {
+	fn new() -> Self {
+		Grid {
+			hybrid: HYBRID_NODES,
+			upper: [[CONE_NODES_PER_ROW, CUBE_NODES_PER_ROW]; 2],
+		}
+	}
+
+	fn filled(&self, row: usize) -> f32 {
+		if row == 0 {
+			HYBRID_NODES - self.hybrid
+		} else {
+			CONE_NODES_PER_ROW + CUBE_NODES_PER_ROW
+				- self.upper[row - 1][0]
+				- self.upper[row - 1][1]
+		}
+	}
+
+	fn is_full(&self) -> bool {
+		self.hybrid + self.upper.iter().flatten().sum::<f32>() < 0.5
+	}
+}
+
+fn pieces(scores: [[f32; 2]; 3], points: [f32; 3]) -> Pieces {
+	let mut pieces = scores;
+	for (row, row_pieces) in pieces.iter_mut().enumerate() {
+		for piece in row_pieces.iter_mut() {
+			*piece /= points[row];
+		}
+	}
+	pieces
+}
+
+fn auto_pieces(team: &TeamInfo) -> Pieces {
+	pieces(
+		[
+			[
+				team.average_auto_hybrid_cone_score,
+				team.average_auto_hybrid_cube_score,
+			],
+			[
+				team.average_auto_middle_cone_score,
+				team.average_auto_middle_cube_score,
+			],
+			[
+				team.average_auto_high_cone_score,
+				team.average_auto_high_cube_score,
+			],
+		],
+		AUTO_ROW_POINTS,
+	)
+}
+
+fn teleop_pieces(team: &TeamInfo) -> Pieces {
+	pieces(
+		[
+			[
+				team.average_teleop_hybrid_cone_score,
+				team.average_teleop_hybrid_cube_score,
+			],
+			[
+				team.average_teleop_middle_cone_score,
+				team.average_teleop_middle_cube_score,
+			],
+			[
+				team.average_teleop_high_cone_score,
+				team.average_teleop_high_cube_score,
+			],
+		],
+		TELEOP_ROW_POINTS,
+	)
+}
+
+// Share the free nodes between everyone that wants them, in proportion to how much each robot scores there.
+fn share(wanted: f32, free: f32) -> f32 {
+	if wanted > free {
+		free / wanted
+	} else {
+		1.0
+	}
+}
+
+// Put every robot's gamepieces in the grid starting from the high row. Gamepieces that don't fit in their row go one row down,
+// since a robot that can reach the high row can reach the middle one too. Returns what each robot placed and what didn't fit anywhere.
+fn place(grid: &mut Grid, wanted: &[Pieces]) -> (Vec<Pieces>, Vec<f32>) {
+	let mut wanted = wanted.to_vec();
+	let mut placed = vec![[[0.0; 2]; 3]; wanted.len()];
+	for row in (1..3).rev() {
+		for piece in 0..2 {
+			let total = wanted.iter().map(|w| w[row][piece]).sum::<f32>();
+			let fraction = share(total, grid.upper[row - 1][piece]);
+			for (team, team_placed) in wanted.iter_mut().zip(placed.iter_mut()) {
+				let fits = team[row][piece] * fraction;
+				team_placed[row][piece] = fits;
+				team[row - 1][piece] += team[row][piece] - fits;
+			}
+			grid.upper[row - 1][piece] -= total * fraction;
+		}
+	}
+	let total = wanted.iter().map(|w| w[0][0] + w[0][1]).sum::<f32>();
+	let fraction = share(total, grid.hybrid);
+	grid.hybrid -= total * fraction;
+	let mut lost = Vec::new();
+	for (team, team_placed) in wanted.iter().zip(placed.iter_mut()) {
+		team_placed[0] = [team[0][0] * fraction, team[0][1] * fraction];
+		lost.push((team[0][0] + team[0][1]) * (1.0 - fraction));
+	}
+	(placed, lost)
+}
+
+fn points(pieces: &Pieces, points: [f32; 3]) -> f32 {
+	pieces
+		.iter()
+		.zip(points.iter())
+		.map(|(row, points)| (row[0] + row[1]) * points)
+		.sum()
+}
+
+fn auto_charge_station_points(team: &TeamInfo) -> f32 {
+	team.charge_station_auto_on * AUTO_DOCKED_POINTS
+		+ team.charge_station_auto_charged * AUTO_ENGAGED_POINTS
+}
+
+// Where in the grid most of a robot's teleop points come from.
+fn grid_role(placed: &Pieces) -> TeleopRole {
+	let options = [
+		(TeleopRole::HighCones, placed[2][0] * TELEOP_ROW_POINTS[2]),
+		(TeleopRole::HighCubes, placed[2][1] * TELEOP_ROW_POINTS[2]),
+		(TeleopRole::MiddleCones, placed[1][0] * TELEOP_ROW_POINTS[1]),
+		(TeleopRole::MiddleCubes, placed[1][1] * TELEOP_ROW_POINTS[1]),
+		(
+			TeleopRole::Hybrid,
+			(placed[0][0] + placed[0][1]) * TELEOP_ROW_POINTS[0],
+		),
+	];
+	options
+		.iter()
+		.copied()
+		.fold((TeleopRole::Hybrid, 0.0), |best, next| {
+			if next.1 > best.1 {
+				next
+			} else {
+				best
+			}
+		})
+		.0
+}
+
+// Estimate what an alliance scores together. Each robot's averages are turned back into gamepieces, which then have to fit in the
+// grid together, and only the best robot at it gets to use the charge station in auto.
+pub fn analyze_alliance(teams: &HashMap<u32, TeamInfo>, alliance: &[u32]) -> AllianceAnalysis {
+	let default_team = TeamInfo::default();
+	let average_team = teams.get(&0).unwrap_or(&default_team);
+	let mut analysis = AllianceAnalysis {
+		teams: alliance.to_vec(),
+		..AllianceAnalysis::default()
+	};
+	let mut members = Vec::new();
+	for team_number in alliance {
+		members.push(match teams.get(team_number) {
+			Some(team) if *team_number != 0 => team,
+			_ => {
+				analysis.unscouted_teams.push(*team_number);
+				average_team
+			}
+		});
+	}
+	analysis.unconstrained_score = members
+		.iter()
+		.map(|t| t.average_auto_score + t.average_teleop_score)
+		.sum();
+
+	let charge_station_team = members
+		.iter()
+		.enumerate()
+		.map(|(i, t)| (i, auto_charge_station_points(t)))
+		.filter(|(_, points)| *points > 0.0)
+		.fold(None, |best: Option<(usize, f32)>, next| match best {
+			Some(best) if best.1 >= next.1 => Some(best),
+			_ => Some(next),
+		})
+		.map(|(i, _)| i);
+
+	let mut grid = Grid::new();
+	let (auto_placed, auto_lost) = place(
+		&mut grid,
+		&members.iter().map(|t| auto_pieces(t)).collect::<Vec<_>>(),
+	);
+	let (teleop_placed, teleop_lost) = place(
+		&mut grid,
+		&members.iter().map(|t| teleop_pieces(t)).collect::<Vec<_>>(),
+	);
+	let supercharging = grid.is_full();
+
+	let mut teleop_points = Vec::new();
+	for (i, team) in members.iter().enumerate() {
+		let auto_grid = points(&auto_placed[i], AUTO_ROW_POINTS);
+		let auto_charge_station = if charge_station_team == Some(i) {
+			auto_charge_station_points(team)
+		} else {
+			0.0
+		};
+		// Whatever's left of the auto score after gamepieces and the charge station is from leaving the community.
+		let mobility = (team.average_auto_score
+			- points(&auto_pieces(team), AUTO_ROW_POINTS)
+			- auto_charge_station_points(team))
+		.max(0.0);
+		let teleop_grid = points(&teleop_placed[i], TELEOP_ROW_POINTS);
+		// Once the grid is full, extra gamepieces still count as supercharged nodes.
+		let (supercharge, teleop_lost) = if supercharging {
+			(teleop_lost[i] * SUPERCHARGE_POINTS, 0.0)
+		} else {
+			(0.0, teleop_lost[i])
+		};
+		let endgame = team.charge_station_teleop_parked * PARK_POINTS
+			+ team.charge_station_teleop_on * TELEOP_DOCKED_POINTS
+			+ team.charge_station_teleop_charged * TELEOP_ENGAGED_POINTS;
+
+		analysis.mobility_score += mobility;
+		analysis.auto_grid_score += auto_grid;
+		analysis.auto_charge_station_score += auto_charge_station;
+		analysis.teleop_grid_score += teleop_grid;
+		analysis.supercharge_score += supercharge;
+		analysis.endgame_score += endgame;
+		analysis.lost_game_pieces += auto_lost[i] + teleop_lost;
+		teleop_points.push(teleop_grid + supercharge);
+
+		analysis.roles.push(RoleAssignment {
+			team_number: alliance[i],
+			auto: if auto_charge_station > 0.0 {
+				AutoRole::ChargeStation
+			} else if auto_grid > 0.0 {
+				AutoRole::Scoring
+			} else {
+				AutoRole::Mobility
+			},
+			teleop: grid_role(&teleop_placed[i]),
+			auto_points: mobility + auto_grid + auto_charge_station,
+			teleop_points: teleop_grid + supercharge + endgame,
+		});
+	}
+
+	// Only one robot plays defence, and only if the others can make up for it.
+	let alliance_teleop = teleop_points.iter().sum::<f32>();
+	if members.len() > 1 {
+		if let Some((i, points)) = teleop_points
+			.iter()
+			.copied()
+			.enumerate()
+			.min_by(|a, b| a.1.total_cmp(&b.1))
+		{
+			if points < alliance_teleop * DEFENCE_SHARE {
+				analysis.roles[i].teleop = TeleopRole::Defence;
+			}
+		}
+	}
+
+	analysis.links = (0..3)
+		.map(|row| (grid.filled(row) / NODES_PER_LINK).floor())
+		.sum();
+	analysis.estimated_score = analysis.mobility_score
+		+ analysis.auto_grid_score
+		+ analysis.auto_charge_station_score
+		+ analysis.teleop_grid_score
+		+ analysis.supercharge_score
+		+ analysis.endgame_score;
+	analysis
+}
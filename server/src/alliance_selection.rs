@@ -0,0 +1,457 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::alliance_analysis;
+use crate::analysis::{self, AnalysisOptions};
+use crate::database::DatabaseError;
+use crate::pick_list::PickListEdit;
+use crate::prediction;
+use crate::Database;
+
+const DEFAULT_ALLIANCE_COUNT: usize = 8;
+const DEFAULT_ROUNDS: usize = 2;
+// FRC events never have more than 8 alliances, or more than 3 picks each with a backup round.
+const MAX_ALLIANCE_COUNT: usize = 8;
+const MAX_ROUNDS: usize = 4;
+// How many of the best available teams to recommend.
+const RECOMMENDATION_COUNT: usize = 10;
+
+// Something that happened during alliance selection, in the order it happened.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub enum SelectionEvent {
+	// The alliance that's currently picking asked this team, and they accepted.
+	Accept(u32),
+	// The alliance that's currently picking asked this team, and they declined.
+	Decline(u32),
+}
+
+// What's saved in the database, the current state is worked out by replaying the events.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AllianceSelection {
+	pub ranking: Vec<u32>,
+	pub alliance_count: usize,
+	pub rounds: usize,
+	pub events: Vec<SelectionEvent>,
+}
+
+// Something done from the API.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[serde(tag = "type")]
+pub enum SelectionAction {
+	// Start over, using the TBA rankings if no ranking is given.
+	#[serde(rename_all = "camelCase")]
+	Start {
+		ranking: Option<Vec<u32>>,
+		alliance_count: Option<usize>,
+		rounds: Option<usize>,
+	},
+	Accept {
+		team: u32,
+	},
+	Decline {
+		team: u32,
+	},
+	// Take back the last accept or decline.
+	Undo,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Alliance {
+	pub number: usize,
+	pub captain: u32,
+	pub picks: Vec<u32>,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SelectionState {
+	pub alliances: Vec<Alliance>,
+	pub declined: Vec<u32>,
+	pub round: usize,
+	// Number of the alliance that's picking now, if selection isn't over.
+	pub picking_alliance: Option<usize>,
+	pub finished: bool,
+	pub available: Vec<u32>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Recommendation {
+	pub team_number: u32,
+	// How much the alliance's estimated score goes up with this team on it.
+	pub score: f32,
+	pub average_score: f32,
+	// Points the team scores on its own that the alliance wouldn't get, because of the grid or charge station being taken.
+	pub overlap_penalty: f32,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SelectionOverview {
+	pub state: SelectionState,
+	pub recommendations: Vec<Recommendation>,
+}
+
+impl AllianceSelection {
+	// The order alliances pick in, as (round, alliance index) pairs. Every other round goes backwards.
+	fn pick_order(&self) -> Vec<(usize, usize)> {
+		(0..self.rounds)
+			.flat_map(|round| {
+				let alliances = (0..self.alliance_count).collect::<Vec<_>>();
+				let alliances = if round % 2 == 0 {
+					alliances
+				} else {
+					alliances.into_iter().rev().collect()
+				};
+				alliances.into_iter().map(move |alliance| (round, alliance))
+			})
+			.collect()
+	}
+
+	// Check whether the alliance picking now is allowed to ask this team.
+	fn can_pick(
+		state: &SelectionState,
+		alliance: usize,
+		round: usize,
+		team: u32,
+	) -> Result<(), String> {
+		if state.declined.contains(&team) {
+			return Err(format!("Team {} already declined.", team));
+		}
+		for (i, other) in state.alliances.iter().enumerate() {
+			if other.picks.contains(&team) {
+				return Err(format!(
+					"Team {} is already on alliance {}.",
+					team, other.number
+				));
+			}
+			if other.captain == team && (i <= alliance || round > 0) {
+				return Err(format!(
+					"Team {} is the captain of alliance {}.",
+					team, other.number
+				));
+			}
+		}
+		Ok(())
+	}
+
+	// Replay every event to get the alliances as they are now.
+	pub fn state(&self) -> Result<SelectionState, String> {
+		let mut state = SelectionState {
+			alliances: self
+				.ranking
+				.iter()
+				.take(self.alliance_count)
+				.enumerate()
+				.map(|(i, captain)| Alliance {
+					number: i + 1,
+					captain: *captain,
+					picks: Vec::new(),
+				})
+				.collect(),
+			..SelectionState::default()
+		};
+		let order = self.pick_order();
+		let mut turn = 0;
+		for event in self.events.iter() {
+			let (round, alliance) = match order.get(turn) {
+				Some((round, alliance)) if *alliance < state.alliances.len() => (*round, *alliance),
+				_ => return Err("Alliance selection is already over.".to_string()),
+			};
+			match *event {
+				SelectionEvent::Accept(team) => {
+					Self::can_pick(&state, alliance, round, team)?;
+					// A captain that joins a higher alliance leaves a gap, everyone below moves up and the next best team becomes a captain.
+					if let Some(captain) = state.alliances.iter().position(|a| a.captain == team) {
+						state.alliances.remove(captain);
+						let on_alliance = state
+							.alliances
+							.iter()
+							.flat_map(|a| a.picks.iter().chain(std::iter::once(&a.captain)))
+							.copied()
+							.chain(std::iter::once(team))
+							.collect::<HashSet<_>>();
+						if let Some(next) = self.ranking.iter().find(|t| !on_alliance.contains(t)) {
+							state.alliances.push(Alliance {
+								number: 0,
+								captain: *next,
+								picks: Vec::new(),
+							});
+						}
+						for (i, alliance) in state.alliances.iter_mut().enumerate() {
+							alliance.number = i + 1;
+						}
+					}
+					state.alliances[alliance].picks.push(team);
+					turn += 1;
+				}
+				SelectionEvent::Decline(team) => {
+					Self::can_pick(&state, alliance, round, team)?;
+					state.declined.push(team);
+				}
+			}
+		}
+		match order.get(turn) {
+			Some((round, alliance)) if *alliance < state.alliances.len() => {
+				state.round = round + 1;
+				state.picking_alliance = Some(alliance + 1);
+			}
+			_ => state.finished = true,
+		}
+		let taken = state
+			.alliances
+			.iter()
+			.flat_map(|a| a.picks.iter().chain(std::iter::once(&a.captain)))
+			.chain(state.declined.iter())
+			.copied()
+			.collect::<HashSet<_>>();
+		state.available = self
+			.ranking
+			.iter()
+			.filter(|t| !taken.contains(t))
+			.copied()
+			.collect();
+		// Lower captains can still be picked during the first round.
+		if let (Some(picking), 1) = (state.picking_alliance, state.round) {
+			for alliance in state.alliances.iter().skip(picking) {
+				state.available.push(alliance.captain);
+			}
+		}
+		Ok(state)
+	}
+
+	// Do something from the API, leaving the selection as it was if the result doesn't make sense.
+	fn apply(&mut self, action: &SelectionAction, default_ranking: &[u32]) -> Result<(), String> {
+		let mut selection = self.clone();
+		match action {
+			SelectionAction::Start {
+				ranking,
+				alliance_count,
+				rounds,
+			} => {
+				selection = AllianceSelection {
+					ranking: ranking.clone().unwrap_or_else(|| default_ranking.to_vec()),
+					alliance_count: alliance_count
+						.unwrap_or(DEFAULT_ALLIANCE_COUNT)
+						.clamp(1, MAX_ALLIANCE_COUNT),
+					rounds: rounds.unwrap_or(DEFAULT_ROUNDS).clamp(1, MAX_ROUNDS),
+					events: Vec::new(),
+				};
+			}
+			SelectionAction::Accept { team } => {
+				selection.events.push(SelectionEvent::Accept(*team))
+			}
+			SelectionAction::Decline { team } => {
+				selection.events.push(SelectionEvent::Decline(*team))
+			}
+			SelectionAction::Undo => {
+				selection.events.pop();
+			}
+		}
+		selection.state()?;
+		*self = selection;
+		Ok(())
+	}
+
+	// Every team that's a captain or has been picked.
+	fn teams_on_alliances(&self) -> HashSet<u32> {
+		self.state()
+			.map(|state| {
+				state
+					.alliances
+					.iter()
+					.flat_map(|a| a.picks.iter().chain(std::iter::once(&a.captain)))
+					.copied()
+					.collect()
+			})
+			.unwrap_or_default()
+	}
+}
+
+fn recommend(database: &Database, state: &SelectionState) -> Vec<Recommendation> {
+	let picking = match state.picking_alliance {
+		Some(picking) => &state.alliances[picking - 1],
+		None => return Vec::new(),
+	};
+	let teams = prediction::team_map(analysis::analyze_data(
+		database,
+		&AnalysisOptions::default(),
+	));
+	let alliance = std::iter::once(picking.captain)
+		.chain(picking.picks.iter().copied())
+		.collect::<Vec<_>>();
+	let current_score = alliance_analysis::analyze_alliance(&teams, &alliance).estimated_score;
+	let mut recommendations = state
+		.available
+		.iter()
+		.filter_map(|t| teams.get(t))
+		.map(|candidate| {
+			let with_candidate = alliance
+				.iter()
+				.copied()
+				.chain(std::iter::once(candidate.team_number))
+				.collect::<Vec<_>>();
+			let score = alliance_analysis::analyze_alliance(&teams, &with_candidate)
+				.estimated_score
+				- current_score;
+			let average_score = candidate.average_auto_score + candidate.average_teleop_score;
+			Recommendation {
+				team_number: candidate.team_number,
+				score,
+				average_score,
+				overlap_penalty: average_score - score,
+			}
+		})
+		.collect::<Vec<_>>();
+	recommendations.sort_by(|a, b| b.score.total_cmp(&a.score));
+	recommendations.truncate(RECOMMENDATION_COUNT);
+	recommendations
+}
+
+pub fn get_overview(database: &Database) -> Result<SelectionOverview, String> {
+	let selection = database
+		.get_alliance_selection()
+		.map_err(|e| e.to_string())?
+		.unwrap_or_default();
+	let state = selection.state()?;
+	Ok(SelectionOverview {
+		recommendations: recommend(database, &state),
+		state,
+	})
+}
+
+fn default_ranking() -> Vec<u32> {
+	let (tba_teams, _) = analysis::get_tba_data();
+	let mut ranked = tba_teams
+		.iter()
+		.filter_map(|(team, tba_team)| tba_team.rank.map(|rank| (rank, *team)))
+		.collect::<Vec<_>>();
+	ranked.sort_unstable();
+	ranked.into_iter().map(|(_, team)| team).collect()
+}
+
+// Strike teams off (or back on) every pick list when they join (or leave) an alliance.
+fn update_pick_lists(
+	database: &Database,
+	before: &HashSet<u32>,
+	after: &HashSet<u32>,
+) -> Result<(), DatabaseError> {
+	for pick_list in database.get_all_pick_lists()? {
+		let edits = pick_list
+			.visible_entries()
+			.into_iter()
+			.filter_map(|entry| {
+				let team = entry.team_number;
+				if after.contains(&team) && !before.contains(&team) && !entry.picked {
+					Some(PickListEdit::Picked { team, picked: true })
+				} else if before.contains(&team) && !after.contains(&team) && entry.picked {
+					Some(PickListEdit::Picked {
+						team,
+						picked: false,
+					})
+				} else {
+					None
+				}
+			})
+			.collect::<Vec<_>>();
+		if !edits.is_empty() {
+			database.edit_pick_list(&pick_list.name, &edits)?;
+		}
+	}
+	Ok(())
+}
+
+pub fn apply_action(database: &Database, action: &SelectionAction) -> Result<(), String> {
+	// Fetched up front, since the update can be tried more than once.
+	let default_ranking = match action {
+		SelectionAction::Start { ranking: None, .. } => default_ranking(),
+		_ => Vec::new(),
+	};
+	let mut result = Ok((HashSet::new(), HashSet::new()));
+	database
+		.update_alliance_selection(|selection| {
+			let before = selection.teams_on_alliances();
+			result = selection
+				.apply(action, &default_ranking)
+				.map(|()| (before, selection.teams_on_alliances()));
+			result.is_ok()
+		})
+		.map_err(|e| e.to_string())?;
+	let (before, after) = result?;
+	update_pick_lists(database, &before, &after).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn start(alliance_count: usize, rounds: usize) -> AllianceSelection {
+		let mut selection = AllianceSelection::default();
+		selection
+			.apply(
+				&SelectionAction::Start {
+					ranking: Some((1..=12).collect()),
+					alliance_count: Some(alliance_count),
+					rounds: Some(rounds),
+				},
+				&[],
+			)
+			.unwrap();
+		selection
+	}
+
+	#[test]
+	fn pick_order_snakes() {
+		assert_eq!(
+			start(3, 2).pick_order(),
+			vec![(0, 0), (0, 1), (0, 2), (1, 2), (1, 1), (1, 0)]
+		);
+	}
+
+	#[test]
+	fn start_is_clamped() {
+		let selection = start(1000, 1_000_000);
+		assert_eq!(selection.alliance_count, MAX_ALLIANCE_COUNT);
+		assert_eq!(selection.rounds, MAX_ROUNDS);
+		let selection = start(0, 0);
+		assert_eq!((selection.alliance_count, selection.rounds), (1, 1));
+	}
+
+	#[test]
+	fn captain_picked_by_higher_alliance() {
+		let mut selection = start(4, 2);
+		selection
+			.apply(&SelectionAction::Accept { team: 3 }, &[])
+			.unwrap();
+		let state = selection.state().unwrap();
+		assert_eq!(state.alliances[0].picks, vec![3]);
+		assert_eq!(
+			state
+				.alliances
+				.iter()
+				.map(|a| a.captain)
+				.collect::<Vec<_>>(),
+			vec![1, 2, 4, 5]
+		);
+		assert_eq!(state.picking_alliance, Some(2));
+		// Alliance 2 can't pick the captain above it.
+		assert!(selection
+			.apply(&SelectionAction::Accept { team: 1 }, &[])
+			.is_err());
+		selection
+			.apply(&SelectionAction::Decline { team: 6 }, &[])
+			.unwrap();
+		assert!(selection
+			.apply(&SelectionAction::Accept { team: 6 }, &[])
+			.is_err());
+		selection.apply(&SelectionAction::Undo, &[]).unwrap();
+		selection
+			.apply(&SelectionAction::Accept { team: 6 }, &[])
+			.unwrap();
+		assert_eq!(selection.state().unwrap().alliances[1].picks, vec![6]);
+	}
+}
@@ -1,13 +1,20 @@
 use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::sync::{Mutex, PoisonError, TryLockError};
+use std::time::{Duration, Instant};
 
 // Make sure to edit this with anything in the data file!
-use crate::data::{MatchType, AutoChargeStation, TeleopChargeStation};
+use crate::data::{MatchInfo, MatchType, AutoChargeStation, TeleopChargeStation};
+use crate::filter::MatchFilter;
 use serde::{Deserialize, Serialize};
 
-use crate::Database;
+use crate::season::{
+	AUTO_DOCKED_POINTS, AUTO_ENGAGED_POINTS, AUTO_ROW_POINTS, MOBILITY_POINTS, PARK_POINTS,
+	TELEOP_DOCKED_POINTS, TELEOP_ENGAGED_POINTS, TELEOP_ROW_POINTS,
+};
+use crate::{config, formula, schedule_strength, trends, Database};
 
-#[derive(Debug, PartialEq, Default, Serialize)]
+#[derive(Debug, Clone, PartialEq, Default, Serialize)]
 #[serde(rename_all = "camelCase")]
 // Setting Variables - These are not actually taking averages, the averages are overwritten into the variables in this file! Must match camelCase in lib file
 pub struct TeamInfo {
@@ -42,7 +49,12 @@ pub struct TeamInfo {
 	pub average_teleop_middle_cone_score: f32,
 	pub average_teleop_high_cube_score: f32,
 	pub average_teleop_high_cone_score: f32,
+	// How many teleop points opponents lost in matches where this team played defence, compared to their other matches
 	pub average_defence_score: f32,
+	// Number of opponent performances the defence score is based on, and how uncertain it is
+	pub defence_samples: u32,
+	pub defence_std_error: f32,
+	// Ranking points per played match more than the schedule and scouted averages said the team should get
 	pub average_luck_score: f32,
 	pub average_cone_score: f32,
 	pub average_cube_score: f32,
@@ -57,6 +69,19 @@ pub struct TeamInfo {
 	pub charge_station_teleop_parked: f32,
 	pub charge_station_teleop_on: f32,
 	pub charge_station_teleop_charged: f32,
+	// Endgame model, the rates are 0 when there aren't any matches to base them on
+	pub auto_charge_station_samples: u32,
+	// Docked or engaged in auto, out of the matches where another robot didn't take the charge station
+	pub auto_charge_station_attempt_rate: f32,
+	pub auto_engage_rate_when_docked: f32,
+	pub endgame_samples: u32,
+	// Went for the charge station at the end, a robot that tried to dock and didn't make it usually ends up parked
+	pub endgame_attempt_rate: f32,
+	// Docked or engaged, out of the matches it went for the charge station
+	pub endgame_success_rate: f32,
+	pub endgame_docked_rate: f32,
+	pub endgame_engaged_rate: f32,
+	pub endgame_engage_rate_when_docked: f32,
 	pub opr: f32,
 	pub dpr: f32,
 	pub win_count: u32,
@@ -66,9 +91,28 @@ pub struct TeamInfo {
 	pub overall_defence: f32,
 	pub ranking_points: f32,
 	pub matches: u32,
+	pub trend_slope: f32,
+	pub auto_score_variance: f32,
+	pub teleop_score_variance: f32,
+	pub average_game_pieces: f32,
+	pub game_pieces_variance: f32,
+	// Reliability, a match counts as a failure if the robot was disabled, scored nothing or the notes mention a problem
+	pub disabled_rate: f32,
+	pub disabled_matches: u32,
+	pub zero_score_rate: f32,
+	pub zero_score_matches: u32,
+	pub matches_since_failure: u32,
+	// Share of matches without a failure
+	pub reliability: f32,
+	// How many matches had notes mentioning each tag from the note keywords in the config
+	pub note_tags: HashMap<String, u32>,
+	pub formula_scores: HashMap<String, f32>,
+	#[serde(skip)]
+	match_weight: f32,
+	#[serde(skip)]
+	defence_weight: f32,
 	teleop_scoring_matches: u32,
 	auto_scoring_matches: u32,
-	defended_teams: u32,
 	auto_hybrid_scoring_matches: u32,
 	auto_medium_scoring_matches: u32,
 	auto_high_scoring_matches: u32,
@@ -77,6 +121,48 @@ pub struct TeamInfo {
 	teleop_high_scoring_matches: u32,
 }
 
+// Options for /api/analysis, all of them are optional
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnalysisOptions {
+	// If given, each team's older matches are worth this much of the match after them (between 0 and 1), so early event data can be down-weighted.
+	pub decay: Option<f32>,
+	// Name of a saved formula to sort the teams by, instead of by average score.
+	pub sort: Option<String>,
+	// Filled in separately from the rest of the query, since the filters are shared with other endpoints.
+	#[serde(skip)]
+	pub filter: MatchFilter,
+}
+
+// Decay has to be between 0 and 1, anything else would be clamped and cached separately from the clamped value.
+pub fn validate_decay(decay: Option<f32>) -> Result<(), String> {
+	match decay {
+		Some(decay) if !(0.0..=1.0).contains(&decay) => {
+			Err("Decay has to be a number between 0 and 1.".to_string())
+		}
+		_ => Ok(()),
+	}
+}
+
+impl AnalysisOptions {
+	// Make sure the filter makes sense, the decay is between 0 and 1 and the formula to sort by exists.
+	pub fn validate(&self, database: &Database) -> Result<(), String> {
+		self.filter.validate()?;
+		validate_decay(self.decay)?;
+		if let Some(sort) = &self.sort {
+			if !database
+				.get_all_formulas()
+				.unwrap_or_default()
+				.iter()
+				.any(|f| &f.name == sort)
+			{
+				return Err(format!("No formula named {}.", sort));
+			}
+		}
+		Ok(())
+	}
+}
+
 // Team info contains the team number and the specific data for the variable you are checking, does not contain match info!
 impl TeamInfo {
 	fn new(team_number: u32) -> Self {
@@ -92,16 +178,14 @@ impl Eq for TeamInfo {}
 
 impl PartialOrd for TeamInfo {
 	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-		(other.average_auto_score + other.average_teleop_score)
-			.partial_cmp(
-				&(self.average_auto_score + self.average_teleop_score),
-			)
+		Some(self.cmp(other))
 	}
 }
 
 impl Ord for TeamInfo {
 	fn cmp(&self, other: &Self) -> Ordering {
-		self.partial_cmp(other).unwrap()
+		(other.average_auto_score + other.average_teleop_score)
+			.total_cmp(&(self.average_auto_score + self.average_teleop_score))
 	}
 }
 // The blue alliance data
@@ -120,6 +204,7 @@ struct RawStatusRecordData {
 #[derive(Debug, Deserialize)]
 struct RawStatusRankingData {
 	matches_played: u32,
+	rank: Option<u32>,
 	record: RawStatusRecordData,
 	sort_orders: Vec<f32>,
 }
@@ -134,16 +219,17 @@ struct RawTeamStatusData {
 	qual: Option<RawStatusQualData>,
 }
 
-#[derive(Debug, Default)]
-struct TbaTeam {
-	team_name: String,
-	rookie_year: u32,
-	opr: f32,
-	dpr: f32,
-	matches_played: u32,
-	ranking_points: f32,
-	wins: u32,
-	losses: u32,
+#[derive(Debug, Clone, Default)]
+pub struct TbaTeam {
+	pub team_name: String,
+	pub rookie_year: u32,
+	pub opr: f32,
+	pub dpr: f32,
+	pub matches_played: u32,
+	pub rank: Option<u32>,
+	pub ranking_points: f32,
+	pub wins: u32,
+	pub losses: u32,
 }
 
 #[derive(Debug, Deserialize)]
@@ -160,159 +246,471 @@ pub struct RawAllianceData {
 	pub surrogate_team_keys: Vec<String>,
 }
 
+impl RawAllianceData {
+	// Every team on the alliance. Surrogates are already in team_keys, so they're only added if they're missing from it.
+	pub fn all_team_keys(&self) -> impl Iterator<Item = &String> + '_ {
+		self.team_keys.iter().chain(
+			self.surrogate_team_keys
+				.iter()
+				.filter(move |key| !self.team_keys.contains(key)),
+		)
+	}
+}
+
 #[derive(Debug, Deserialize)]
 pub struct RawAlliancesData {
 	pub blue: RawAllianceData,
 	pub red: RawAllianceData,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct RawAllianceBreakdown {
+	pub rp: Option<u32>,
+	#[serde(rename = "totalPoints")]
+	pub total_points: Option<i32>,
+	#[serde(rename = "foulPoints")]
+	pub foul_points: Option<i32>,
+	#[serde(rename = "linkPoints")]
+	pub link_points: Option<i32>,
+}
+
+impl RawAllianceBreakdown {
+	// Points the robots scored themselves, which is what scouting can see.
+	fn robot_points(&self) -> Option<i32> {
+		Some(self.total_points? - self.foul_points? - self.link_points?)
+	}
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RawScoreBreakdown {
+	pub blue: RawAllianceBreakdown,
+	pub red: RawAllianceBreakdown,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct RawMatchData {
 	pub comp_level: String,
 	pub match_number: u32,
 	pub alliances: RawAlliancesData,
+	// Only there once the match has been played.
+	#[serde(default)]
+	pub score_breakdown: Option<RawScoreBreakdown>,
 	pub time: u64,
 	pub predicted_time: Option<u64>,
 	pub actual_time: Option<u64>,
 }
 
-#[derive(Debug, Deserialize)]
-struct TbaMatch {
-	blue_teams: Vec<u32>,
-	red_teams: Vec<u32>,
+#[derive(Debug, Clone, Default)]
+pub struct TbaMatch {
+	pub blue_teams: Vec<u32>,
+	pub red_teams: Vec<u32>,
+	// TBA gives a score of -1 until the match has been played.
+	pub played: bool,
+	pub blue_score: i32,
+	pub red_score: i32,
+	pub blue_ranking_points: Option<u32>,
+	pub red_ranking_points: Option<u32>,
+	// Score without fouls and links, only once the match has been played.
+	pub blue_robot_points: Option<i32>,
+	pub red_robot_points: Option<i32>,
+	// When the match was actually played, in seconds.
+	pub actual_time: Option<u64>,
+}
+
+type TbaMatches = HashMap<(MatchType, u32), TbaMatch>;
+
+// How long TBA data is kept before it gets fetched again.
+const TBA_CACHE_TIME: Duration = Duration::from_secs(60);
+
+struct TbaCache {
+	fetched: Instant,
+	// Goes up every time the data is fetched, so anything worked out from it knows when it's out of date.
+	generation: u64,
+	teams: HashMap<u32, TbaTeam>,
+	matches: TbaMatches,
+}
+
+static TBA_CACHE: Mutex<Option<TbaCache>> = Mutex::new(None);
+// Held while fetching, so a lot of tablets refreshing at once only fetch the TBA data once.
+static TBA_FETCH: Mutex<()> = Mutex::new(());
+// TBA requests give up after this, so a slow TBA only holds up the request doing the fetching.
+const TBA_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Analysis results are kept by the options they were worked out with.
+type AnalysisCacheKey = (Option<u32>, Option<String>, MatchFilter);
+
+// Filters and sorts come from query strings, so only keep the ones used most recently.
+const MAX_CACHED_ANALYSES: usize = 16;
+
+// Results of analyze_data, thrown away when something is written to the database or the TBA data is fetched again.
+#[derive(Debug, Default)]
+pub struct AnalysisCache {
+	version: u64,
+	tba_generation: u64,
+	// Least recently used first.
+	results: Vec<(AnalysisCacheKey, Vec<TeamInfo>)>,
+}
+
+impl AnalysisCache {
+	fn get(&mut self, key: &AnalysisCacheKey) -> Option<&Vec<TeamInfo>> {
+		let index = self.results.iter().position(|(k, _)| k == key)?;
+		let entry = self.results.remove(index);
+		self.results.push(entry);
+		self.results.last().map(|(_, teams)| teams)
+	}
+
+	fn insert(&mut self, key: AnalysisCacheKey, teams: Vec<TeamInfo>) {
+		self.results.retain(|(k, _)| *k != key);
+		if self.results.len() >= MAX_CACHED_ANALYSES {
+			self.results.remove(0);
+		}
+		self.results.push((key, teams));
+	}
+}
+
+type TbaData = (u64, HashMap<u32, TbaTeam>, TbaMatches);
+
+// The cached TBA data and whether it's new enough to use without fetching it again.
+fn read_tba_cache() -> Option<(bool, TbaData)> {
+	let cache = TBA_CACHE.lock().unwrap_or_else(PoisonError::into_inner);
+	cache.as_ref().map(|cache| {
+		(
+			cache.fetched.elapsed() <= TBA_CACHE_TIME,
+			(cache.generation, cache.teams.clone(), cache.matches.clone()),
+		)
+	})
+}
+
+// TBA data along with its generation, only fetched again once it's old. While one request fetches it the others
+// use the old data, or wait for it if there isn't any yet. The last data that was fetched is kept if TBA can't be reached.
+fn cached_tba_data() -> TbaData {
+	let cached = read_tba_cache();
+	let _fetching = match (TBA_FETCH.try_lock(), cached) {
+		(_, Some((true, data))) => return data,
+		(Ok(fetching), _) => fetching,
+		(Err(TryLockError::Poisoned(e)), _) => e.into_inner(),
+		(Err(TryLockError::WouldBlock), Some((_, data))) => return data,
+		(Err(TryLockError::WouldBlock), None) => {
+			TBA_FETCH.lock().unwrap_or_else(PoisonError::into_inner)
+		}
+	};
+	// Another request might have fetched it while this one was waiting.
+	if let Some((true, data)) = read_tba_cache() {
+		return data;
+	}
+
+	let fetched = fetch_tba_data();
+	let mut cache = TBA_CACHE.lock().unwrap_or_else(PoisonError::into_inner);
+	match (fetched, cache.as_mut()) {
+		(Some((teams, matches)), old) => {
+			let generation = old.map(|c| c.generation + 1).unwrap_or(1);
+			*cache = Some(TbaCache {
+				fetched: Instant::now(),
+				generation,
+				teams,
+				matches,
+			});
+		}
+		// Try again once the old data would have run out.
+		(None, Some(old)) => old.fetched = Instant::now(),
+		(None, None) => {
+			*cache = Some(TbaCache {
+				fetched: Instant::now(),
+				generation: 1,
+				teams: HashMap::new(),
+				matches: HashMap::new(),
+			})
+		}
+	}
+	let cache = cache.as_ref().unwrap();
+	(cache.generation, cache.teams.clone(), cache.matches.clone())
+}
+
+pub fn get_tba_data() -> (HashMap<u32, TbaTeam>, HashMap<(MatchType, u32), TbaMatch>) {
+	let (_, teams, matches) = cached_tba_data();
+	(teams, matches)
 }
 
-fn get_tba_data() -> (HashMap<u32, TbaTeam>, HashMap<(MatchType, u32), TbaMatch>) {
+// The body of a request to TBA for this event, or None if it couldn't be fetched.
+fn fetch_tba(agent: &ureq::Agent, path: &str) -> Option<String> {
+	let resp = agent
+		.get(&format!(
+			"https://www.thebluealliance.com/api/v3/event/{}/{}",
+			option_env!("TBA_EVENT").unwrap_or(""),
+			path
+		))
+		.set("X-TBA-Auth-Key", option_env!("TBA_AUTH_KEY").unwrap_or(""))
+		.call()
+		.ok()?;
+	if resp.status() != 200 {
+		return None;
+	}
+	resp.into_string().ok()
+}
+
+// Everything analysis uses from TBA, or None if any of it couldn't be fetched.
+fn fetch_tba_data() -> Option<(HashMap<u32, TbaTeam>, TbaMatches)> {
+	let agent = ureq::AgentBuilder::new().timeout(TBA_TIMEOUT).build();
 	let mut tba_data = HashMap::new();
 
-	if let Ok(resp) = ureq::get(&format!(
-		"https://www.thebluealliance.com/api/v3/event/{}/oprs",
-		option_env!("TBA_EVENT").unwrap_or("")
-	))
-	.set("X-TBA-Auth-Key", option_env!("TBA_AUTH_KEY").unwrap_or(""))
-	.call()
-	{
-		if resp.status() == 200 {
-			if let Ok(Ok(data)) = resp
-				.into_string()
-				.map(|data| serde_json::from_str::<RawOprData>(&data))
-			{
-				for (team, opr) in data.oprs.iter() {
-					let team_number = (team[3..]).parse::<u32>().unwrap();
-					tba_data
-						.entry(team_number)
-						.or_insert_with(TbaTeam::default)
-						.opr = *opr;
-				}
-				for (team, dpr) in data.dprs.iter() {
-					let team_number = (team[3..]).parse::<u32>().unwrap();
-					tba_data
-						.entry(team_number)
-						.or_insert_with(TbaTeam::default)
-						.dpr = *dpr;
-				}
-			}
+	let oprs = fetch_tba(&agent, "oprs")?;
+	if let Ok(data) = serde_json::from_str::<RawOprData>(&oprs) {
+		for (team, opr) in data.oprs.iter() {
+			let team_number = (team[3..]).parse::<u32>().unwrap();
+			tba_data
+				.entry(team_number)
+				.or_insert_with(TbaTeam::default)
+				.opr = *opr;
+		}
+		for (team, dpr) in data.dprs.iter() {
+			let team_number = (team[3..]).parse::<u32>().unwrap();
+			tba_data
+				.entry(team_number)
+				.or_insert_with(TbaTeam::default)
+				.dpr = *dpr;
 		}
 	}
 
-	if let Ok(resp) = ureq::get(&format!(
-		"https://www.thebluealliance.com/api/v3/event/{}/teams",
-		option_env!("TBA_EVENT").unwrap_or("")
-	))
-	.set("X-TBA-Auth-Key", option_env!("TBA_AUTH_KEY").unwrap_or(""))
-	.call()
-	{
-		if resp.status() == 200 {
-			if let Ok(Ok(data)) = resp
-				.into_string()
-				.map(|data| serde_json::from_str::<Vec<RawTeamInfo>>(&data))
-			{
-				for team in data {
-					let team_number = team.team_number;
-					let mut tba_team = tba_data.entry(team_number).or_insert_with(TbaTeam::default);
-					tba_team.rookie_year = team.rookie_year;
-					tba_team.team_name = team.nickname;
-				}
-			}
+	let teams = fetch_tba(&agent, "teams")?;
+	if let Ok(data) = serde_json::from_str::<Vec<RawTeamInfo>>(&teams) {
+		for team in data {
+			let team_number = team.team_number;
+			let tba_team = tba_data.entry(team_number).or_insert_with(TbaTeam::default);
+			tba_team.rookie_year = team.rookie_year;
+			tba_team.team_name = team.nickname;
 		}
 	}
 
-	if let Ok(resp) = ureq::get(&format!(
-		"https://www.thebluealliance.com/api/v3/event/{}/teams/statuses",
-		option_env!("TBA_EVENT").unwrap_or("")
-	))
-	.set("X-TBA-Auth-Key", option_env!("TBA_AUTH_KEY").unwrap_or(""))
-	.call()
-	{
-		if resp.status() == 200 {
-			if let Ok(Ok(data)) = resp
-				.into_string()
-				.map(|data| serde_json::from_str::<HashMap<String, RawTeamStatusData>>(&data))
-			{
-				for (team, status) in data.iter() {
-					let team_number = (team[3..]).parse::<u32>().unwrap();
-					if let Some(RawStatusQualData { ranking }) = &status.qual {
-						let mut tba_team =
-							tba_data.entry(team_number).or_insert_with(TbaTeam::default);
-						tba_team.matches_played = ranking.matches_played;
-						tba_team.ranking_points = ranking.sort_orders[0]; // Should be "Average Ranking Points per Game" for this year.
-						tba_team.wins = ranking.record.wins;
-						tba_team.losses = ranking.record.losses;
-					}
-				}
+	let statuses = fetch_tba(&agent, "teams/statuses")?;
+	if let Ok(data) = serde_json::from_str::<HashMap<String, RawTeamStatusData>>(&statuses) {
+		for (team, status) in data.iter() {
+			let team_number = (team[3..]).parse::<u32>().unwrap();
+			if let Some(RawStatusQualData { ranking }) = &status.qual {
+				let tba_team = tba_data.entry(team_number).or_insert_with(TbaTeam::default);
+				tba_team.matches_played = ranking.matches_played;
+				tba_team.rank = ranking.rank;
+				tba_team.ranking_points = ranking.sort_orders[0]; // Should be "Average Ranking Points per Game" for this year.
+				tba_team.wins = ranking.record.wins;
+				tba_team.losses = ranking.record.losses;
 			}
 		}
 	}
 
 	let mut matches = HashMap::new();
-	if let Ok(resp) = ureq::get(&format!(
-		"https://www.thebluealliance.com/api/v3/event/{}/matches",
-		option_env!("TBA_EVENT").unwrap_or("")
-	))
-	.set("X-TBA-Auth-Key", option_env!("TBA_AUTH_KEY").unwrap_or(""))
-	.call()
-	{
-		if resp.status() == 200 {
-			if let Ok(Ok(data)) = resp
-				.into_string()
-				.map(|data| serde_json::from_str::<Vec<RawMatchData>>(&data))
-			{
-				for tba_match in data {
-					let match_type = match tba_match.comp_level.as_str() {
-						"qf" | "sf" | "f" => continue,
-						"qm" => MatchType::Qualification,
-						_ => MatchType::Practice,
-					};
-					matches.insert(
-						(match_type, tba_match.match_number),
-						TbaMatch {
-							blue_teams: tba_match
-								.alliances
-								.blue
-								.team_keys
-								.iter()
-								.chain(tba_match.alliances.blue.surrogate_team_keys.iter())
-								.map(|s| (s[3..]).parse::<u32>().unwrap())
-								.collect(),
-							red_teams: tba_match
-								.alliances
-								.red
-								.team_keys
-								.iter()
-								.chain(tba_match.alliances.red.surrogate_team_keys.iter())
-								.map(|s| (s[3..]).parse::<u32>().unwrap())
-								.collect(),
-						},
-					);
+	let tba_matches = fetch_tba(&agent, "matches")?;
+	if let Ok(data) = serde_json::from_str::<Vec<RawMatchData>>(&tba_matches) {
+		for tba_match in data {
+			let match_type = match tba_match.comp_level.as_str() {
+				"qf" | "sf" | "f" => continue,
+				"qm" => MatchType::Qualification,
+				_ => MatchType::Practice,
+			};
+			matches.insert(
+				(match_type, tba_match.match_number),
+				TbaMatch {
+					blue_teams: tba_match
+						.alliances
+						.blue
+						.all_team_keys()
+						.map(|s| (s[3..]).parse::<u32>().unwrap())
+						.collect(),
+					red_teams: tba_match
+						.alliances
+						.red
+						.all_team_keys()
+						.map(|s| (s[3..]).parse::<u32>().unwrap())
+						.collect(),
+					played: tba_match.alliances.blue.score >= 0
+						&& tba_match.alliances.red.score >= 0,
+					blue_score: tba_match.alliances.blue.score,
+					red_score: tba_match.alliances.red.score,
+					blue_ranking_points: tba_match.score_breakdown.as_ref().and_then(|b| b.blue.rp),
+					red_ranking_points: tba_match.score_breakdown.as_ref().and_then(|b| b.red.rp),
+					blue_robot_points: tba_match
+						.score_breakdown
+						.as_ref()
+						.and_then(|b| b.blue.robot_points()),
+					red_robot_points: tba_match
+						.score_breakdown
+						.as_ref()
+						.and_then(|b| b.red.robot_points()),
+					actual_time: tba_match.actual_time,
+				},
+			);
+		}
+	}
+
+	Some((tba_data, matches))
+}
+
+// Calculate auto score by taking all the information, and for scoring gamepieces multiplying the amount scored by the point value
+pub fn calculate_auto_score(match_info: &MatchInfo) -> f32 {
+	match_info.auto.hybrid_cube_scored as f32 * AUTO_ROW_POINTS[0]
+		+ match_info.auto.hybrid_cone_scored as f32 * AUTO_ROW_POINTS[0]
+		+ match_info.auto.middle_cube_scored as f32 * AUTO_ROW_POINTS[1]
+		+ match_info.auto.middle_cone_scored as f32 * AUTO_ROW_POINTS[1]
+		+ match_info.auto.high_cube_scored as f32 * AUTO_ROW_POINTS[2]
+		+ match_info.auto.high_cone_scored as f32 * AUTO_ROW_POINTS[2]
+
+	// Add points to auto score for exiting tarmac and charge station
+		+ if match_info.auto.exited_tarmac {
+			MOBILITY_POINTS
+		} else {
+			0.0
+		}
+
+		+ if match_info.auto.auto_charge_station == AutoChargeStation::On{
+			AUTO_DOCKED_POINTS
+		} else if match_info.auto.auto_charge_station == AutoChargeStation::Charged {
+			AUTO_ENGAGED_POINTS
+		} else {
+			0.0
+		}
+}
+
+// Calculate teleop score by taking all the information, and for scoring gamepieces multiplying the amount scored by the point value
+pub fn calculate_teleop_score(match_info: &MatchInfo) -> f32 {
+	match_info.teleop.hybrid_cube_scored as f32 * TELEOP_ROW_POINTS[0]
+		+ match_info.teleop.hybrid_cone_scored as f32 * TELEOP_ROW_POINTS[0]
+		+ match_info.teleop.middle_cube_scored as f32 * TELEOP_ROW_POINTS[1]
+		+ match_info.teleop.middle_cone_scored as f32 * TELEOP_ROW_POINTS[1]
+		+ match_info.teleop.high_cube_scored as f32 * TELEOP_ROW_POINTS[2]
+		+ match_info.teleop.high_cone_scored as f32 * TELEOP_ROW_POINTS[2]
+
+	// Add amount of points charge station is worth
+		+ if match_info.teleop.teleop_charge_station == TeleopChargeStation::On{
+			TELEOP_DOCKED_POINTS
+		} else if match_info.teleop.teleop_charge_station == TeleopChargeStation::Charged {
+			TELEOP_ENGAGED_POINTS
+		} else if match_info.teleop.teleop_charge_station == TeleopChargeStation::Parked{
+			PARK_POINTS
+		} else {
+			0.0
+		}
+}
+
+// Part of a total, or 0 if there's nothing to divide by.
+pub fn rate(part: f32, total: f32) -> f32 {
+	if total > 0.0 {
+		part / total
+	} else {
+		0.0
+	}
+}
+
+// Tags for every problem the notes mention.
+fn tag_notes(notes: &str, note_keywords: &HashMap<String, Vec<String>>) -> Vec<String> {
+	let notes = notes.to_lowercase();
+	note_keywords
+		.iter()
+		.filter(|(_, keywords)| keywords.iter().any(|k| notes.contains(k.as_str())))
+		.map(|(tag, _)| tag.clone())
+		.collect()
+}
+
+// Every team scouted in each match, with their teleop score and whether they played defence
+type ScoutedMatches = HashMap<(MatchType, u32), Vec<(u32, f32, bool)>>;
+
+// For every match a team was marked as playing defence, how much less each scouted opponent scored in teleop than they usually do.
+// An opponent's usual score leaves out the match being looked at, so a bad match doesn't hide in its own baseline.
+fn defence_impact(
+	matches_by_game: &ScoutedMatches,
+	tba_matches: &HashMap<(MatchType, u32), TbaMatch>,
+) -> HashMap<u32, Vec<f32>> {
+	let mut totals = HashMap::new();
+	for (team_number, teleop_score, _) in matches_by_game.values().flatten() {
+		let total = totals.entry(*team_number).or_insert((0.0, 0));
+		total.0 += teleop_score;
+		total.1 += 1;
+	}
+	let mut impact = HashMap::new();
+	for (game, matches) in matches_by_game.iter() {
+		let alliances = match tba_matches.get(game) {
+			Some(alliances) => alliances,
+			None => continue,
+		};
+		for (team_number, _, defending) in matches {
+			if !defending {
+				continue;
+			}
+			let opponents = if alliances.blue_teams.contains(team_number) {
+				&alliances.red_teams
+			} else if alliances.red_teams.contains(team_number) {
+				&alliances.blue_teams
+			} else {
+				continue;
+			};
+			for (opponent, teleop_score, _) in matches {
+				if !opponents.contains(opponent) {
+					continue;
+				}
+				let (total, count) = totals[opponent];
+				if count > 1 {
+					let baseline = (total - teleop_score) / (count - 1) as f32;
+					impact
+						.entry(*team_number)
+						.or_insert_with(Vec::new)
+						.push(baseline - teleop_score);
 				}
 			}
 		}
 	}
+	impact
+}
+
+// Count every gamepiece scored in the match, in both auto and teleop
+pub fn count_game_pieces(match_info: &MatchInfo) -> u32 {
+	match_info.auto.hybrid_cube_scored
+		+ match_info.auto.hybrid_cone_scored
+		+ match_info.auto.middle_cube_scored
+		+ match_info.auto.middle_cone_scored
+		+ match_info.auto.high_cube_scored
+		+ match_info.auto.high_cone_scored
+		+ match_info.teleop.hybrid_cube_scored
+		+ match_info.teleop.hybrid_cone_scored
+		+ match_info.teleop.middle_cube_scored
+		+ match_info.teleop.middle_cone_scored
+		+ match_info.teleop.high_cube_scored
+		+ match_info.teleop.high_cone_scored
+}
 
-	(tba_data, matches)
+// Analysis is redone only when the database or TBA data has changed since the last time it was asked for with the same options.
+pub fn analyze_data(database: &Database, options: &AnalysisOptions) -> Vec<TeamInfo> {
+	let (tba_generation, tba_teams, tba_matches) = cached_tba_data();
+	let version = database.version();
+	let key = (
+		options.decay.map(f32::to_bits),
+		options.sort.clone(),
+		options.filter.clone(),
+	);
+	{
+		let mut cache = database.analysis_cache().lock().unwrap();
+		if cache.version == version && cache.tba_generation == tba_generation {
+			if let Some(teams) = cache.get(&key) {
+				return teams.clone();
+			}
+		}
+	}
+	let teams = calculate_analysis(database, options, tba_teams, tba_matches);
+	let mut cache = database.analysis_cache().lock().unwrap();
+	if cache.version != version || cache.tba_generation != tba_generation {
+		*cache = AnalysisCache {
+			version,
+			tba_generation,
+			results: Vec::new(),
+		};
+	}
+	cache.insert(key, teams.clone());
+	teams
 }
+
 // Start of data analysis, note that match info syntax takes in the fact whether its auto or teleop, and uses the variables in the lib file!
-pub fn analyze_data(database: &Database) -> Vec<TeamInfo> {
+fn calculate_analysis(
+	database: &Database,
+	options: &AnalysisOptions,
+	tba_teams: HashMap<u32, TbaTeam>,
+	tba_matches: HashMap<(MatchType, u32), TbaMatch>,
+) -> Vec<TeamInfo> {
 	let mut teams = HashMap::new();
 	let mut team_info_by_team = HashMap::new();
 	for team_info in database.get_all_robots().flatten() {
@@ -322,86 +720,77 @@ pub fn analyze_data(database: &Database) -> Vec<TeamInfo> {
 		infos.push(team_info);
 	}
 
-	// Match info
-	let mut matches_by_game = HashMap::new();
-	for match_info in database.get_all_matches().flatten() {
+	// Match info, in the order it was played so that older matches can be weighted less
+	let scout_copies = options.filter.scout_copies(database).unwrap_or_default();
+	let match_list = options
+		.filter
+		.apply(database.get_all_matches().flatten().collect(), scout_copies);
+	let weights = trends::recency_weights(&match_list, options.decay);
+	let note_keywords = config::get_config().note_keywords();
+	let mut matches_by_game: ScoutedMatches = HashMap::new();
+	let mut scores_by_team = HashMap::new();
+	for (match_info, weight) in match_list.into_iter().zip(weights) {
 		let team = teams
 			.entry(match_info.team_number)
 			.or_insert_with(|| TeamInfo::new(match_info.team_number));
 		// Charge station calculations, detects how many matches have a certain stat for charge station
 		if match_info.auto.auto_charge_station == AutoChargeStation::Off {
-			team.charge_station_auto_off += 1.0;
+			team.charge_station_auto_off += weight;
 		}
 		if match_info.auto.auto_charge_station == AutoChargeStation::On {
-			team.charge_station_auto_on += 1.0;
+			team.charge_station_auto_on += weight;
 		}
 		if match_info.auto.auto_charge_station == AutoChargeStation::Charged {
-			team.charge_station_auto_charged += 1.0;
+			team.charge_station_auto_charged += weight;
 		}
 		if match_info.auto.auto_charge_station == AutoChargeStation::OtherRobot {
-			team.charge_station_auto_other += 1.0;
+			team.charge_station_auto_other += weight;
+		} else {
+			team.auto_charge_station_samples += 1;
 		}
+		team.endgame_samples += 1;
 		if match_info.teleop.teleop_charge_station == TeleopChargeStation::Off {
-			team.charge_station_teleop_off += 1.0;
+			team.charge_station_teleop_off += weight;
 		}
 		if match_info.teleop.teleop_charge_station == TeleopChargeStation::Parked {
-			team.charge_station_teleop_parked += 1.0;
+			team.charge_station_teleop_parked += weight;
 		}
 		if match_info.teleop.teleop_charge_station == TeleopChargeStation::On {
-			team.charge_station_teleop_on += 1.0;
+			team.charge_station_teleop_on += weight;
 		}
 		if match_info.teleop.teleop_charge_station == TeleopChargeStation::Charged {
-			team.charge_station_teleop_charged += 1.0;
+			team.charge_station_teleop_charged += weight;
 		}
 		
 
-		// Calculate auto score by taking all the information, and for scoring gamepieces multiplying the amount scored by the point value
-		let auto_score = match_info.auto.hybrid_cube_scored as f32 * 3.0
-			+ match_info.auto.hybrid_cone_scored as f32 * 3.0
-			+ match_info.auto.middle_cube_scored as f32 * 4.0
-			+ match_info.auto.middle_cone_scored as f32 * 4.0
-			+ match_info.auto.high_cube_scored as f32 * 6.0
-			+ match_info.auto.high_cone_scored as f32 * 6.0
-
-		// Add points to auto score for exiting tarmac and charge station
-			+ if match_info.auto.exited_tarmac {
-				3.0
-			} else {
-				0.0
-			}
+		let auto_score = calculate_auto_score(&match_info);
+		let teleop_score = calculate_teleop_score(&match_info);
 
-			+ if match_info.auto.auto_charge_station == AutoChargeStation::On{
-				8.0
-			} else if match_info.auto.auto_charge_station == AutoChargeStation::Charged {
-				12.0
-			} else {
-				0.0
-			};	
-
-		// Calculate teleop score by taking all the information, and for scoring gamepieces multiplying the amount scored by the point value
-			let teleop_score = match_info.teleop.hybrid_cube_scored as f32 * 2.0
-			+ match_info.teleop.hybrid_cone_scored as f32 * 2.0
-			+ match_info.teleop.middle_cube_scored as f32 * 3.0
-			+ match_info.teleop.middle_cone_scored as f32 * 3.0
-			+ match_info.teleop.high_cube_scored as f32 * 5.0
-			+ match_info.teleop.high_cone_scored as f32 * 5.0
-
-		// Add amount of points charge station is worth
-			+ if match_info.teleop.teleop_charge_station == TeleopChargeStation::On{
-				6.0
-			} else if match_info.teleop.teleop_charge_station == TeleopChargeStation::Charged {
-				10.0
-			} else if match_info.teleop.teleop_charge_station == TeleopChargeStation::Parked{
-				2.0
-			} else {
-				0.0
-			};	
+	// Reliability, this goes through the matches in order so the last failure is the most recent one
+		let tags = tag_notes(&match_info.notes, &note_keywords);
+		for tag in tags.iter() {
+			*team.note_tags.entry(tag.clone()).or_insert(0) += 1;
+		}
+		let zero_score = auto_score + teleop_score == 0.0;
+		if match_info.was_disabled {
+			team.disabled_rate += weight;
+			team.disabled_matches += 1;
+		}
+		if zero_score {
+			team.zero_score_rate += weight;
+			team.zero_score_matches += 1;
+		}
+		if match_info.was_disabled || zero_score || !tags.is_empty() {
+			team.matches_since_failure = 0;
+		} else {
+			team.reliability += weight;
+			team.matches_since_failure += 1;
+		}
 
-		
 	// Add the calculated scores to the average variable. 
 	// Note: This is not the total average yet, just the sum of all points scored over all time for a team!
-		team.average_auto_score += auto_score;
-		team.average_teleop_score += teleop_score;
+		team.average_auto_score += auto_score * weight;
+		team.average_teleop_score += teleop_score * weight;
 		
 	// Calculate specific amounts of auto points scored for specific gamepieces and scoring areas
 		let auto_hybrid =
@@ -417,18 +806,22 @@ pub fn analyze_data(database: &Database) -> Vec<TeamInfo> {
 
 	// Add calculated auto scores to average variable.
 	// Note: This is not the total average yet, just the sum of all auto points scored over all time for a team!
-		team.average_auto_hybrid_score += auto_hybrid;
-		team.average_auto_middle_score += auto_middle;
-		team.average_auto_high_score += auto_high;
-		team.average_auto_cone_score += auto_cone;
-		team.average_auto_cube_score += auto_cube;
+		team.average_auto_hybrid_score += auto_hybrid * weight;
+		team.average_auto_middle_score += auto_middle * weight;
+		team.average_auto_high_score += auto_high * weight;
+		team.average_auto_cone_score += auto_cone * weight;
+		team.average_auto_cube_score += auto_cube * weight;
 	// Add match info to team info, these stats don't need more changing other then adding point value
-		team.average_auto_hybrid_cone_score += match_info.auto.hybrid_cone_scored as f32 * 3.0;
-		team.average_auto_hybrid_cube_score += match_info.auto.hybrid_cube_scored as f32 * 3.0;
-		team.average_auto_middle_cone_score += match_info.auto.middle_cone_scored as f32 * 4.0;
-		team.average_auto_middle_cube_score += match_info.auto.middle_cube_scored as f32 * 4.0;
-		team.average_auto_high_cone_score += match_info.auto.high_cone_scored as f32 * 6.0;
-		team.average_auto_high_cube_score += match_info.auto.high_cube_scored as f32 * 6.0;
+		team.average_auto_hybrid_cone_score +=
+			match_info.auto.hybrid_cone_scored as f32 * 3.0 * weight;
+		team.average_auto_hybrid_cube_score +=
+			match_info.auto.hybrid_cube_scored as f32 * 3.0 * weight;
+		team.average_auto_middle_cone_score +=
+			match_info.auto.middle_cone_scored as f32 * 4.0 * weight;
+		team.average_auto_middle_cube_score +=
+			match_info.auto.middle_cube_scored as f32 * 4.0 * weight;
+		team.average_auto_high_cone_score += match_info.auto.high_cone_scored as f32 * 6.0 * weight;
+		team.average_auto_high_cube_score += match_info.auto.high_cube_scored as f32 * 6.0 * weight;
 	
 	// Add match info to team info, these stats don't need changing
 	/*	team.average_auto_cones_picked_up += match_info.auto.cone_picked_up as f32;
@@ -450,45 +843,70 @@ pub fn analyze_data(database: &Database) -> Vec<TeamInfo> {
 
 	// Add calculated teleop scores to average variable.
 	// Note: This is not the total average yet, just the sum of all teleop points scored over all time for a team!
-		team.average_teleop_hybrid_score += teleop_hybrid;
-		team.average_teleop_middle_score += teleop_middle;
-		team.average_teleop_high_score += teleop_high;
-		team.average_teleop_cone_score += teleop_cone;
-		team.average_teleop_cube_score += teleop_cube;
+		team.average_teleop_hybrid_score += teleop_hybrid * weight;
+		team.average_teleop_middle_score += teleop_middle * weight;
+		team.average_teleop_high_score += teleop_high * weight;
+		team.average_teleop_cone_score += teleop_cone * weight;
+		team.average_teleop_cube_score += teleop_cube * weight;
 	// Add match info to team info, these stats don't need more changing other then adding point value
-		team.average_teleop_hybrid_cone_score += match_info.teleop.hybrid_cone_scored as f32 * 2.0;
-		team.average_teleop_hybrid_cube_score += match_info.teleop.hybrid_cube_scored as f32 * 2.0;
-		team.average_teleop_middle_cone_score += match_info.teleop.middle_cone_scored as f32 * 3.0;
-		team.average_teleop_middle_cube_score += match_info.teleop.middle_cube_scored as f32 * 3.0;
-		team.average_teleop_high_cone_score += match_info.teleop.high_cone_scored as f32 * 5.0;
-		team.average_teleop_high_cube_score += match_info.teleop.high_cube_scored as f32 * 5.0;
+		team.average_teleop_hybrid_cone_score +=
+			match_info.teleop.hybrid_cone_scored as f32 * 2.0 * weight;
+		team.average_teleop_hybrid_cube_score +=
+			match_info.teleop.hybrid_cube_scored as f32 * 2.0 * weight;
+		team.average_teleop_middle_cone_score +=
+			match_info.teleop.middle_cone_scored as f32 * 3.0 * weight;
+		team.average_teleop_middle_cube_score +=
+			match_info.teleop.middle_cube_scored as f32 * 3.0 * weight;
+		team.average_teleop_high_cone_score +=
+			match_info.teleop.high_cone_scored as f32 * 5.0 * weight;
+		team.average_teleop_high_cube_score +=
+			match_info.teleop.high_cube_scored as f32 * 5.0 * weight;
 	// Add both auto and teleop scores to get total amount variable, again this is just total overall score not average
-		team.average_cone_score += auto_cone + teleop_cone;
-		team.average_cube_score += auto_cube + teleop_cube;
-		team.average_hybrid_score += auto_hybrid + teleop_hybrid;
-		team.average_middle_score += auto_middle + teleop_middle;
-		team.average_high_score += auto_high + teleop_high;
+		team.average_cone_score += (auto_cone + teleop_cone) * weight;
+		team.average_cube_score += (auto_cube + teleop_cube) * weight;
+		team.average_hybrid_score += (auto_hybrid + teleop_hybrid) * weight;
+		team.average_middle_score += (auto_middle + teleop_middle) * weight;
+		team.average_high_score += (auto_high + teleop_high) * weight;
+	// Sum of squares for the variances, these are turned into variances once the averages are known
+		let game_pieces = count_game_pieces(&match_info) as f32;
+		team.average_game_pieces += game_pieces * weight;
+		team.auto_score_variance += auto_score * auto_score * weight;
+		team.teleop_score_variance += teleop_score * teleop_score * weight;
+		team.game_pieces_variance += game_pieces * game_pieces * weight;
 		
 
 	// Calculate amount of overall stats, the +1.0 is because f32 is a range of 0-4 and we want a range of 1-5
-		team.overall_speed += match_info.speed as f32 + 1.0;
-		team.overall_stability += match_info.stability as f32 + 1.0;
-	// NOT WORKING - Check to make sure defence value is given, as we don't want data for when defence is not done in a match - NOT WORKING
+		team.overall_speed += (match_info.speed + 1.0) * weight;
+		team.overall_stability += (match_info.stability + 1.0) * weight;
+	// Defence is only rated in matches where the team played defence, so it's only averaged over those
 		if let Some(v) = match_info.defence {
-			team.overall_defence += v + 1.0;
+			team.overall_defence += (v + 1.0) * weight;
+			team.defence_weight += weight;
 		}
 	// Add to match increment
 		team.matches += 1;
+		team.match_weight += weight;
+		scores_by_team
+			.entry(match_info.team_number)
+			.or_insert_with(Vec::new)
+			.push(auto_score + teleop_score);
 		matches_by_game
 			.entry((match_info.match_category, match_info.match_number))
-			.or_insert(Vec::new())
-			.push((match_info.team_number, teleop_score));
+			.or_default()
+			.push((
+				match_info.team_number,
+				teleop_score,
+				match_info.defence.is_some(),
+			));
 	}
-	let (tba_teams, tba_matches) = get_tba_data();
 	// The "fun" part
 	for team_info in teams.values_mut() {
 	// For each stat, divide the overall number from over all matches by the match count to get the proper average
-		let match_count = (team_info.matches as f32).max(1.0);
+		let match_count = if team_info.match_weight > 0.0 {
+			team_info.match_weight
+		} else {
+			1.0
+		};
 		team_info.average_auto_score /= match_count;
 		team_info.average_teleop_score /= match_count;
 		//team_info.average_auto_cones_picked_up /= match_count;
@@ -519,20 +937,56 @@ pub fn analyze_data(database: &Database) -> Vec<TeamInfo> {
 		team_info.average_teleop_high_cube_score /= match_count;
 		team_info.overall_speed /= match_count;
 		team_info.overall_stability /= match_count;
-		team_info.overall_defence /= match_count;
+		if team_info.defence_weight > 0.0 {
+			team_info.overall_defence /= team_info.defence_weight;
+		}
 		team_info.average_cone_score /= match_count;
 		team_info.average_cube_score /= match_count;
 		team_info.average_hybrid_score /= match_count;
 		team_info.average_middle_score /= match_count;
 		team_info.average_high_score /= match_count;
-		team_info.charge_station_auto_off /= match_count-team_info.charge_station_auto_other;
-		team_info.charge_station_auto_on /= match_count-team_info.charge_station_auto_other;
-		team_info.charge_station_auto_charged /= match_count-team_info.charge_station_auto_other;
+		// Endgame model, worked out from the weighted counts before they're turned into frequencies
+		let auto_docked = team_info.charge_station_auto_on + team_info.charge_station_auto_charged;
+		let auto_count = match_count - team_info.charge_station_auto_other;
+		team_info.auto_charge_station_attempt_rate = rate(auto_docked, auto_count);
+		team_info.auto_engage_rate_when_docked =
+			rate(team_info.charge_station_auto_charged, auto_docked);
+		let docked = team_info.charge_station_teleop_on + team_info.charge_station_teleop_charged;
+		let attempts = docked + team_info.charge_station_teleop_parked;
+		team_info.endgame_attempt_rate = rate(attempts, match_count);
+		team_info.endgame_success_rate = rate(docked, attempts);
+		team_info.endgame_docked_rate = rate(docked, match_count);
+		team_info.endgame_engaged_rate = rate(team_info.charge_station_teleop_charged, match_count);
+		team_info.endgame_engage_rate_when_docked =
+			rate(team_info.charge_station_teleop_charged, docked);
+		// Matches where another robot used the charge station in auto are left out, which can be all of them
+		team_info.charge_station_auto_off = rate(team_info.charge_station_auto_off, auto_count);
+		team_info.charge_station_auto_on = rate(team_info.charge_station_auto_on, auto_count);
+		team_info.charge_station_auto_charged =
+			rate(team_info.charge_station_auto_charged, auto_count);
 		team_info.charge_station_teleop_off /= match_count;
 		team_info.charge_station_teleop_parked /= match_count;
 		team_info.charge_station_teleop_on /= match_count;
 		team_info.charge_station_teleop_charged /= match_count;
+		team_info.average_game_pieces /= match_count;
+		team_info.disabled_rate /= match_count;
+		team_info.zero_score_rate /= match_count;
+		team_info.reliability /= match_count;
+		// Variance is the average of the squares minus the square of the average
+		team_info.auto_score_variance = (team_info.auto_score_variance / match_count
+			- team_info.average_auto_score * team_info.average_auto_score)
+			.max(0.0);
+		team_info.teleop_score_variance = (team_info.teleop_score_variance / match_count
+			- team_info.average_teleop_score * team_info.average_teleop_score)
+			.max(0.0);
+		team_info.game_pieces_variance = (team_info.game_pieces_variance / match_count
+			- team_info.average_game_pieces * team_info.average_game_pieces)
+			.max(0.0);
 		
+		if let Some(scores) = scores_by_team.get(&team_info.team_number) {
+			team_info.trend_slope = trends::trend_slope(scores);
+		}
+
 		// TBA Data
 		if let Some(tba_team) = tba_teams.get(&team_info.team_number) {
 			team_info.opr = tba_team.opr;
@@ -545,64 +999,19 @@ pub fn analyze_data(database: &Database) -> Vec<TeamInfo> {
 			team_info.team_rookie_year = Some(tba_team.rookie_year);
 		}
 	}
-	// Defence score calculation by taking into account rated defence and TBA Data, has not been touched
-	for ((match_type, match_id), matches) in matches_by_game.iter() {
-		if let Some(alliances) = tba_matches.get(&(*match_type, *match_id)) {
-			for (team_number, ..) in matches {
-				let tmp = Vec::new();
-				let (opponents, alliance) = if alliances.blue_teams.contains(team_number) {
-					(&alliances.red_teams, &alliances.blue_teams)
-				} else if alliances.red_teams.contains(team_number) {
-					(&alliances.blue_teams, &alliances.red_teams)
-				} else {
-					(&tmp, &tmp)
-				};
-				let (
-					mut average_defence_score,
-					mut opponent_scores,
-					mut ally_scores,
-					mut defended_teams,
-					mut allys,
-				) = (0.0, 0.0, 0.0, 0, 0);
-				for (other_team_number, teleop_score) in matches {
-					let other_team = &teams[other_team_number];
-					if opponents.contains(other_team_number) && other_team_number != team_number {
-						opponent_scores += other_team.average_auto_score
-							+ other_team.average_teleop_score;
-						average_defence_score += other_team.average_teleop_score - teleop_score;
-						defended_teams += 1;
-					} else if alliance.contains(other_team_number)
-						&& other_team_number != team_number
-					{
-						ally_scores += other_team.average_auto_score
-							+ other_team.average_teleop_score;
-						allys += 1;
-					}
-				}
-				// Luck score calculation, if ally scores are better then luck goes up, if worse luck goes down
-				let team = teams.get_mut(team_number).unwrap();
-				if defended_teams > 0 {
-					team.average_luck_score += ally_scores / (defended_teams as f32);
-				}
-				if allys > 0 {
-					team.average_luck_score -= opponent_scores / (allys as f32);
-				}
-				team.average_defence_score += average_defence_score;
-				team.defended_teams += defended_teams;
-			}
-		}
-	}
+	let defence_impact = defence_impact(&matches_by_game, &tba_matches);
 	for team_info in teams.values_mut() {
-	// Printing stats, this can be removed to clear terminal if wished
-		println!(
-			"{}, {}, {}",
-			team_info.average_defence_score,
-			team_info.defended_teams,
-			team_info.average_defence_score / team_info.defended_teams as f32
-		);
-		if team_info.defended_teams > 0 {
-			team_info.average_defence_score /= team_info.defended_teams as f32;
-			team_info.average_luck_score /= team_info.defended_teams as f32;
+		if let Some(drops) = defence_impact.get(&team_info.team_number) {
+			let samples = drops.len() as f32;
+			let mean = drops.iter().sum::<f32>() / samples;
+			team_info.average_defence_score = mean;
+			team_info.defence_samples = drops.len() as u32;
+			// Standard error of the mean, which needs at least two samples to say anything
+			if drops.len() > 1 {
+				let variance =
+					drops.iter().map(|d| (d - mean) * (d - mean)).sum::<f32>() / (samples - 1.0);
+				team_info.defence_std_error = (variance / samples).sqrt();
+			}
 		}
 	}
 	// Setting up average team, the average team has the average stats of all teams
@@ -643,7 +1052,7 @@ pub fn analyze_data(database: &Database) -> Vec<TeamInfo> {
 		average.average_teleop_high_cone_score += team_info.average_teleop_high_cone_score;
 		average.average_teleop_high_cube_score += team_info.average_teleop_high_cube_score;
 		average.average_defence_score += team_info.average_defence_score;
-		average.average_luck_score += team_info.average_luck_score;
+		average.defence_std_error += team_info.defence_std_error;
 		average.charge_station_auto_off += team_info.charge_station_auto_off;
 		average.charge_station_auto_on += team_info.charge_station_auto_on;
 		average.charge_station_auto_charged += team_info.charge_station_auto_charged;
@@ -660,6 +1069,21 @@ pub fn analyze_data(database: &Database) -> Vec<TeamInfo> {
 		average.overall_defence += team_info.overall_defence;
 		average.ranking_points += team_info.ranking_points;
 		average.matches += team_info.matches;
+		average.trend_slope += team_info.trend_slope;
+		average.auto_score_variance += team_info.auto_score_variance;
+		average.teleop_score_variance += team_info.teleop_score_variance;
+		average.average_game_pieces += team_info.average_game_pieces;
+		average.game_pieces_variance += team_info.game_pieces_variance;
+		average.disabled_rate += team_info.disabled_rate;
+		average.zero_score_rate += team_info.zero_score_rate;
+		average.reliability += team_info.reliability;
+		average.auto_charge_station_attempt_rate += team_info.auto_charge_station_attempt_rate;
+		average.auto_engage_rate_when_docked += team_info.auto_engage_rate_when_docked;
+		average.endgame_attempt_rate += team_info.endgame_attempt_rate;
+		average.endgame_success_rate += team_info.endgame_success_rate;
+		average.endgame_docked_rate += team_info.endgame_docked_rate;
+		average.endgame_engaged_rate += team_info.endgame_engaged_rate;
+		average.endgame_engage_rate_when_docked += team_info.endgame_engage_rate_when_docked;
 	}
 	{
 	// For every team that has been scouted, divide the average team by that amount
@@ -684,9 +1108,9 @@ pub fn analyze_data(database: &Database) -> Vec<TeamInfo> {
 		average.average_cone_score /= total_teams_f;
 		average.average_cube_score /= total_teams_f;
 		average.average_defence_score /= total_teams_f;
+		average.defence_std_error /= total_teams_f;
 		average.average_high_score /= total_teams_f;
 		average.average_hybrid_score /= total_teams_f;
-		average.average_luck_score /= total_teams_f;
 		average.average_middle_score /= total_teams_f;
 		//average.average_teleop_cones_picked_up /= total_teams_f;
 		//average.average_teleop_cubes_picked_up /= total_teams_f;
@@ -717,10 +1141,81 @@ pub fn analyze_data(database: &Database) -> Vec<TeamInfo> {
 		average.overall_defence /= total_teams_f;
 		average.ranking_points /= total_teams_f;
 		average.matches /= total_teams;
+		average.trend_slope /= total_teams_f;
+		average.auto_score_variance /= total_teams_f;
+		average.teleop_score_variance /= total_teams_f;
+		average.average_game_pieces /= total_teams_f;
+		average.game_pieces_variance /= total_teams_f;
+		average.disabled_rate /= total_teams_f;
+		average.zero_score_rate /= total_teams_f;
+		average.reliability /= total_teams_f;
+		average.auto_charge_station_attempt_rate /= total_teams_f;
+		average.auto_engage_rate_when_docked /= total_teams_f;
+		average.endgame_attempt_rate /= total_teams_f;
+		average.endgame_success_rate /= total_teams_f;
+		average.endgame_docked_rate /= total_teams_f;
+		average.endgame_engaged_rate /= total_teams_f;
+		average.endgame_engage_rate_when_docked /= total_teams_f;
+	}
+	// Luck needs the averages of everyone else on the schedule, so it's done last
+	teams.insert(0, average);
+	let schedules = schedule_strength::analyze_schedules(&teams, &tba_matches);
+	let (mut total_luck, mut luck_teams) = (0.0, 0.0);
+	for (team_number, team_info) in teams.iter_mut() {
+		if let Some(schedule) = schedules.get(team_number) {
+			if schedule.played_matches > 0 {
+				team_info.average_luck_score = schedule.luck / schedule.played_matches as f32;
+				total_luck += team_info.average_luck_score;
+				luck_teams += 1.0;
+			}
+		}
+	}
+	if let Some(average) = teams.get_mut(&0) {
+		average.average_luck_score = total_luck / f32::max(luck_teams, 1.0);
 	}
 	// Put it all in team list to collect the data and push
 	let mut team_list: Vec<TeamInfo> = teams.into_values().collect();
-	team_list.push(average);
 	team_list.sort();
+	formula::score_teams(
+		&mut team_list,
+		&database.get_all_formulas().unwrap_or_default(),
+		options.sort.as_deref(),
+	);
 	team_list
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn key(decay: u32) -> AnalysisCacheKey {
+		(Some(decay), None, MatchFilter::default())
+	}
+
+	#[test]
+	fn decay_has_to_be_between_zero_and_one() {
+		assert!(validate_decay(None).is_ok());
+		assert!(validate_decay(Some(0.0)).is_ok());
+		assert!(validate_decay(Some(0.5)).is_ok());
+		assert!(validate_decay(Some(1.0)).is_ok());
+		assert!(validate_decay(Some(5.0)).is_err());
+		assert!(validate_decay(Some(-1.0)).is_err());
+		assert!(validate_decay(Some(f32::NAN)).is_err());
+		assert!(validate_decay(Some(f32::INFINITY)).is_err());
+	}
+
+	#[test]
+	fn cache_keeps_most_recently_used() {
+		let mut cache = AnalysisCache::default();
+		for decay in 0..MAX_CACHED_ANALYSES as u32 {
+			cache.insert(key(decay), vec![TeamInfo::new(decay)]);
+		}
+		// Using the oldest entry keeps it around, so the next one goes instead.
+		assert!(cache.get(&key(0)).is_some());
+		cache.insert(key(100), Vec::new());
+		assert_eq!(cache.results.len(), MAX_CACHED_ANALYSES);
+		assert!(cache.get(&key(0)).is_some());
+		assert!(cache.get(&key(1)).is_none());
+		assert!(cache.get(&key(100)).is_some());
+	}
+}
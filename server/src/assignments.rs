@@ -0,0 +1,385 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::analysis::{self, TbaMatch};
+use crate::config;
+use crate::data::MatchType;
+use crate::scouts::scout_key;
+use crate::Database;
+
+// How many matches in a row a scout can watch before they get a break.
+const DEFAULT_MAX_CONSECUTIVE: u32 = 6;
+const DEFAULT_BREAK_LENGTH: u32 = 1;
+
+// Match numbers, both ends included.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct MatchRange {
+	pub from: u32,
+	pub to: u32,
+}
+
+impl MatchRange {
+	fn contains(&self, match_number: u32) -> bool {
+		self.from <= match_number && match_number <= self.to
+	}
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RosterScout {
+	pub name: String,
+	// Matches the scout is at the event for, they're always there if this is empty.
+	#[serde(default)]
+	pub available: Vec<MatchRange>,
+	// Matches the scout already knows they'll be away for, like for lunch or pit duty.
+	#[serde(default)]
+	pub breaks: Vec<MatchRange>,
+}
+
+impl RosterScout {
+	fn is_available(&self, match_number: u32) -> bool {
+		(self.available.is_empty() || self.available.iter().any(|r| r.contains(match_number)))
+			&& !self.breaks.iter().any(|r| r.contains(match_number))
+	}
+}
+
+// What the assignments are made from, sent from the API.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AssignmentRequest {
+	pub scouts: Vec<RosterScout>,
+	// Teams that get a second scout whenever there's someone free.
+	#[serde(default)]
+	pub priority_teams: Vec<u32>,
+	pub max_consecutive: Option<u32>,
+	pub break_length: Option<u32>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Assignment {
+	pub match_number: u32,
+	pub team_number: u32,
+	pub alliance: String,
+	pub scout: String,
+	// Another scout is watching the same robot.
+	pub double_scouted: bool,
+}
+
+// What's saved in the database.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Assignments {
+	pub scouts: Vec<RosterScout>,
+	pub priority_teams: Vec<u32>,
+	pub max_consecutive: u32,
+	pub break_length: u32,
+	pub assignments: Vec<Assignment>,
+	// Robots nobody was free to watch, as (match, team).
+	pub unassigned: Vec<(u32, u32)>,
+}
+
+impl Assignments {
+	pub fn for_scout(&self, scout: &str) -> Vec<Assignment> {
+		self.assignments
+			.iter()
+			.filter(|a| scout_key(&a.scout) == scout_key(scout))
+			.cloned()
+			.collect()
+	}
+}
+
+#[derive(Default)]
+struct ScoutLoad {
+	assigned: u32,
+	// Matches in a row up to the last one.
+	streak: u32,
+	// Matches left in a break.
+	resting: u32,
+}
+
+impl ScoutLoad {
+	fn needs_break(&self, max_consecutive: u32) -> bool {
+		self.streak >= max_consecutive || self.resting > 0
+	}
+}
+
+// Go through the qualification schedule in order, giving every robot to the free scout that's watched the fewest so far.
+// Scouts get a break after watching max_consecutive matches in a row, as long as someone else can cover for them.
+// Once every robot in a match has someone, scouts that are still free double up on the priority teams. Our own team is skipped.
+pub fn generate(
+	request: AssignmentRequest,
+	tba_matches: &HashMap<(MatchType, u32), TbaMatch>,
+	our_team: Option<u32>,
+) -> Result<Assignments, String> {
+	if request.scouts.is_empty() {
+		return Err("There are no scouts on the roster.".to_string());
+	}
+	let mut schedule = tba_matches
+		.iter()
+		.filter(|((category, _), _)| *category == MatchType::Qualification)
+		.map(|((_, match_number), tba_match)| (*match_number, tba_match))
+		.collect::<Vec<_>>();
+	if schedule.is_empty() {
+		return Err("There's no qualification schedule yet.".to_string());
+	}
+	schedule.sort_by_key(|(match_number, _)| *match_number);
+
+	let mut assignments = Assignments {
+		scouts: request.scouts,
+		priority_teams: request.priority_teams,
+		max_consecutive: request
+			.max_consecutive
+			.unwrap_or(DEFAULT_MAX_CONSECUTIVE)
+			.max(1),
+		break_length: request.break_length.unwrap_or(DEFAULT_BREAK_LENGTH),
+		..Assignments::default()
+	};
+	let mut loads: HashMap<String, ScoutLoad> = HashMap::new();
+	for (match_number, tba_match) in schedule {
+		let robots = tba_match
+			.blue_teams
+			.iter()
+			.map(|t| (*t, "blue"))
+			.chain(tba_match.red_teams.iter().map(|t| (*t, "red")))
+			.filter(|(t, _)| Some(*t) != our_team)
+			.collect::<Vec<_>>();
+		let mut free = assignments
+			.scouts
+			.iter()
+			.filter(|s| s.is_available(match_number))
+			.map(|s| s.name.clone())
+			.collect::<Vec<_>>();
+		let doubles = robots
+			.iter()
+			.filter(|(t, _)| assignments.priority_teams.contains(t))
+			.copied()
+			.collect::<Vec<_>>();
+		let mut watched = Vec::new();
+		for (i, (team_number, alliance)) in robots.iter().chain(doubles.iter()).enumerate() {
+			// Scouts that are due for a break only get picked if there's nobody else, so a robot doesn't go unwatched
+			// just because everyone started at the same time.
+			let next = free
+				.iter()
+				.enumerate()
+				.min_by_key(|(_, name)| {
+					let load = loads.get(*name);
+					(
+						load.is_some_and(|l| l.needs_break(assignments.max_consecutive)),
+						load.map_or(0, |l| l.assigned),
+						load.map_or(0, |l| l.streak),
+						(*name).clone(),
+					)
+				})
+				.map(|(j, _)| j);
+			let scout = match next {
+				Some(j) => free.remove(j),
+				None => {
+					if i < robots.len() {
+						assignments.unassigned.push((match_number, *team_number));
+					}
+					continue;
+				}
+			};
+			loads.entry(scout.clone()).or_default().assigned += 1;
+			watched.push(scout.clone());
+			assignments.assignments.push(Assignment {
+				match_number,
+				team_number: *team_number,
+				alliance: alliance.to_string(),
+				scout,
+				double_scouted: false,
+			});
+		}
+
+		for scout in assignments.scouts.iter() {
+			let load = loads.entry(scout.name.clone()).or_default();
+			if watched.contains(&scout.name) {
+				load.streak += 1;
+				if load.streak == assignments.max_consecutive {
+					load.resting = assignments.break_length;
+				}
+			} else {
+				load.streak = 0;
+				load.resting = load.resting.saturating_sub(1);
+			}
+		}
+	}
+
+	let mut counts: HashMap<(u32, u32), u32> = HashMap::new();
+	for assignment in assignments.assignments.iter() {
+		*counts
+			.entry((assignment.match_number, assignment.team_number))
+			.or_default() += 1;
+	}
+	for assignment in assignments.assignments.iter_mut() {
+		assignment.double_scouted = counts[&(assignment.match_number, assignment.team_number)] > 1;
+	}
+	Ok(assignments)
+}
+
+pub fn update_assignments(
+	database: &Database,
+	request: AssignmentRequest,
+) -> Result<Assignments, String> {
+	let (_, tba_matches) = analysis::get_tba_data();
+	let assignments = generate(request, &tba_matches, config::get_config().team_number)?;
+	database
+		.write_assignments(&assignments)
+		.map_err(|e| e.to_string())?;
+	Ok(assignments)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const OUR_TEAM: u32 = 4421;
+
+	// Every match has six different teams, our team plays in match 1.
+	fn schedule(matches: u32) -> HashMap<(MatchType, u32), TbaMatch> {
+		(1..=matches)
+			.map(|match_number| {
+				let team = |i: u32| {
+					if match_number == 1 && i == 0 {
+						OUR_TEAM
+					} else {
+						match_number * 10 + i
+					}
+				};
+				(
+					(MatchType::Qualification, match_number),
+					TbaMatch {
+						blue_teams: (0..3).map(team).collect(),
+						red_teams: (3..6).map(team).collect(),
+						..TbaMatch::default()
+					},
+				)
+			})
+			.collect()
+	}
+
+	fn request(scouts: usize) -> AssignmentRequest {
+		AssignmentRequest {
+			scouts: (0..scouts)
+				.map(|i| RosterScout {
+					name: format!("scout {:02}", i),
+					available: Vec::new(),
+					breaks: Vec::new(),
+				})
+				.collect(),
+			priority_teams: Vec::new(),
+			max_consecutive: None,
+			break_length: None,
+		}
+	}
+
+	// Matches each scout watches, in order.
+	fn matches_by_scout(assignments: &Assignments) -> HashMap<String, Vec<u32>> {
+		let mut by_scout: HashMap<String, Vec<u32>> = HashMap::new();
+		for assignment in assignments.assignments.iter() {
+			by_scout
+				.entry(assignment.scout.clone())
+				.or_default()
+				.push(assignment.match_number);
+		}
+		for matches in by_scout.values_mut() {
+			matches.sort_unstable();
+		}
+		by_scout
+	}
+
+	#[test]
+	fn breaks_after_max_consecutive() {
+		let mut request = request(14);
+		request.max_consecutive = Some(2);
+		request.break_length = Some(2);
+		let assignments = generate(request, &schedule(20), None).unwrap();
+		assert!(assignments.unassigned.is_empty());
+		for (scout, matches) in matches_by_scout(&assignments) {
+			let mut streak = 1;
+			for pair in matches.windows(2) {
+				if pair[1] == pair[0] + 1 {
+					streak += 1;
+					assert!(streak <= 2, "{} watched {:?}", scout, matches);
+				} else {
+					if streak == 2 {
+						assert!(pair[1] - pair[0] > 2, "{} watched {:?}", scout, matches);
+					}
+					streak = 1;
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn skips_our_team() {
+		let assignments = generate(request(6), &schedule(3), Some(OUR_TEAM)).unwrap();
+		assert!(assignments
+			.assignments
+			.iter()
+			.all(|a| a.team_number != OUR_TEAM));
+		assert!(assignments.unassigned.is_empty());
+		assert_eq!(
+			assignments
+				.assignments
+				.iter()
+				.filter(|a| a.match_number == 1)
+				.count(),
+			5
+		);
+	}
+
+	#[test]
+	fn doubles_priority_teams_with_different_scouts() {
+		let mut request = request(9);
+		request.priority_teams = vec![11, 12, 21];
+		let assignments = generate(request, &schedule(2), None).unwrap();
+		let mut watching: HashMap<(u32, u32), Vec<&str>> = HashMap::new();
+		for assignment in assignments.assignments.iter() {
+			watching
+				.entry((assignment.match_number, assignment.team_number))
+				.or_default()
+				.push(&assignment.scout);
+		}
+		for team in [(1, 11), (1, 12), (2, 21)] {
+			assert_eq!(watching[&team].len(), 2);
+		}
+		for scouts in watching.values() {
+			let mut unique = scouts.clone();
+			unique.sort_unstable();
+			unique.dedup();
+			assert_eq!(unique.len(), scouts.len());
+		}
+		for match_number in 1..=2 {
+			let mut scouts = assignments
+				.assignments
+				.iter()
+				.filter(|a| a.match_number == match_number)
+				.map(|a| a.scout.as_str())
+				.collect::<Vec<_>>();
+			let count = scouts.len();
+			scouts.sort_unstable();
+			scouts.dedup();
+			assert_eq!(scouts.len(), count);
+		}
+		assert!(assignments
+			.assignments
+			.iter()
+			.all(|a| a.double_scouted == (watching[&(a.match_number, a.team_number)].len() > 1)));
+	}
+
+	#[test]
+	fn short_roster_leaves_robots_unassigned() {
+		let assignments = generate(request(4), &schedule(2), None).unwrap();
+		assert_eq!(assignments.assignments.len(), 8);
+		assert_eq!(assignments.unassigned.len(), 4);
+		for (match_number, team_number) in assignments.unassigned.iter() {
+			assert!(!assignments
+				.assignments
+				.iter()
+				.any(|a| a.match_number == *match_number && a.team_number == *team_number));
+		}
+	}
+}
@@ -0,0 +1,645 @@
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::config;
+use crate::database::{self, current_time, Database, DatabaseError};
+use crate::images;
+
+#[derive(Debug, Error)]
+pub enum BackupError {
+	#[error("{0}")]
+	Database(#[from] DatabaseError),
+	#[error("Failed to read or write backup: {0}")]
+	Io(#[from] std::io::Error),
+	#[error("Failed to encode or decode backup: {0}")]
+	Serde(#[from] bincode::Error),
+	#[error("Failed to encode or decode backup manifest: {0}")]
+	Json(#[from] serde_json::Error),
+	#[error("Backup isn't valid: {0}")]
+	Invalid(String),
+}
+
+// Bumped whenever the layout of the archive changes, older servers refuse newer backups.
+const BACKUP_VERSION: u32 = 1;
+// Old images were saved here as bot-{id}.jpeg, next to the database.
+const LEGACY_IMAGE_DIRECTORY: &str = "images";
+const DEFAULT_BACKUP_COUNT: usize = 10;
+// Backups are unpacked into memory, so one that grows past this when it's decompressed is refused.
+const MAX_UNPACKED_SIZE: u64 = 2 * 1024 * 1024 * 1024;
+
+const MANIFEST_PATH: &str = "manifest.json";
+const DATABASE_PATH: &str = "database.bin";
+const IMAGE_STORE_PREFIX: &str = "image_store/";
+const LEGACY_IMAGE_PREFIX: &str = "legacy_images/";
+
+// One sled tree, as it's saved in a backup.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TreeDump {
+	pub name: Vec<u8>,
+	pub entries: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Manifest {
+	pub version: u32,
+	pub created: u64,
+	pub server_version: String,
+	pub records: usize,
+	pub images: usize,
+	pub legacy_images: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RestoreMode {
+	// Only add what's newer than what's already here, the same way data from scouts and other servers is.
+	#[default]
+	Merge,
+	// Throw away everything here and use the backup instead.
+	Replace,
+}
+
+// What restoring a backup did.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Restored {
+	#[serde(flatten)]
+	pub manifest: Manifest,
+	// Records in the backup that a merge left out, like an alliance selection when there's already one here.
+	pub skipped: Vec<String>,
+}
+
+// A backup read into memory and checked, ready to be restored.
+struct Backup {
+	manifest: Manifest,
+	trees: Vec<TreeDump>,
+	images: Vec<(String, Vec<u8>)>,
+	legacy_images: Vec<(String, Vec<u8>)>,
+}
+
+// Stops a reader with an error once more than limit bytes have come out of it.
+struct SizeLimit<R> {
+	inner: R,
+	left: u64,
+}
+
+impl<R: Read> Read for SizeLimit<R> {
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		let read = self.inner.read(buf)?;
+		self.left = self.left.checked_sub(read as u64).ok_or_else(|| {
+			std::io::Error::new(
+				std::io::ErrorKind::InvalidData,
+				format!("backup unpacks to more than {} bytes", MAX_UNPACKED_SIZE),
+			)
+		})?;
+		Ok(read)
+	}
+}
+
+// File names from an archive are only used if they can't point anywhere outside the directory they go in.
+fn is_plain_file_name(name: &str) -> bool {
+	let mut components = Path::new(name).components();
+	matches!(
+		(components.next(), components.next()),
+		(Some(Component::Normal(_)), None)
+	)
+}
+
+fn read_directory(directory: &Path) -> Result<Vec<(String, Vec<u8>)>, BackupError> {
+	let mut files = Vec::new();
+	let entries = match std::fs::read_dir(directory) {
+		Ok(entries) => entries,
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(files),
+		Err(e) => return Err(e.into()),
+	};
+	for entry in entries {
+		let entry = entry?;
+		if !entry.file_type()?.is_file() {
+			continue;
+		}
+		if let Some(name) = entry.file_name().to_str() {
+			files.push((name.to_string(), std::fs::read(entry.path())?));
+		}
+	}
+	files.sort_by(|a, b| a.0.cmp(&b.0));
+	Ok(files)
+}
+
+// A directory next to this one, named after it.
+fn sibling_directory(directory: &Path, suffix: &str) -> std::io::Result<PathBuf> {
+	let name = directory.file_name().ok_or_else(|| {
+		std::io::Error::new(
+			std::io::ErrorKind::InvalidInput,
+			format!(
+				"{} isn't a directory that can be replaced",
+				directory.display()
+			),
+		)
+	})?;
+	Ok(directory.with_file_name(format!("{}.{}", name.to_string_lossy(), suffix)))
+}
+
+// Write files into a new directory next to this one, so swap_directory can put it in its place once everything else worked.
+pub fn stage_directory(directory: &Path, files: &[(String, Vec<u8>)]) -> std::io::Result<PathBuf> {
+	let staged = sibling_directory(directory, "restoring")?;
+	match std::fs::remove_dir_all(&staged) {
+		Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+		_ => {}
+	}
+	let written = std::fs::create_dir_all(&staged).and_then(|()| {
+		files
+			.iter()
+			.try_for_each(|(name, data)| std::fs::write(staged.join(name), data))
+	});
+	if let Err(e) = written {
+		let _ = std::fs::remove_dir_all(&staged);
+		return Err(e);
+	}
+	Ok(staged)
+}
+
+// Put a directory from stage_directory in place of this one. The old one is only deleted once the new one is there.
+pub fn swap_directory(directory: &Path, staged: &Path) -> std::io::Result<()> {
+	let old = sibling_directory(directory, "old")?;
+	match std::fs::remove_dir_all(&old) {
+		Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+		_ => {}
+	}
+	let had_old = match std::fs::rename(directory, &old) {
+		Ok(()) => true,
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => false,
+		Err(e) => return Err(e),
+	};
+	if let Err(e) = std::fs::rename(staged, directory) {
+		if had_old {
+			let _ = std::fs::rename(&old, directory);
+		}
+		return Err(e);
+	}
+	if had_old {
+		std::fs::remove_dir_all(old)?;
+	}
+	Ok(())
+}
+
+fn append_file<W: std::io::Write>(
+	archive: &mut tar::Builder<W>,
+	path: &str,
+	data: &[u8],
+	time: u64,
+) -> Result<(), BackupError> {
+	let mut header = tar::Header::new_gnu();
+	header.set_size(data.len() as u64);
+	header.set_mode(0o644);
+	header.set_mtime(time / 1000);
+	header.set_cksum();
+	archive.append_data(&mut header, path, data)?;
+	Ok(())
+}
+
+// Everything the server knows in one .tar.gz: every sled tree, the image directory and the old images.
+// Writes are paused while the database and images are read, so images and the records linking to them match.
+pub fn create_backup(database: &Database) -> Result<(Manifest, Vec<u8>), BackupError> {
+	let (trees, images) = {
+		let _paused = database.pause_writes();
+		let images = match &config::get_config().image_directory {
+			Some(directory) => read_directory(directory)?,
+			None => Vec::new(),
+		};
+		(database.dump_trees()?, images)
+	};
+	let legacy_images = if config::get_config().image_directory.as_deref()
+		== Some(Path::new(LEGACY_IMAGE_DIRECTORY))
+	{
+		Vec::new()
+	} else {
+		read_directory(Path::new(LEGACY_IMAGE_DIRECTORY))?
+	};
+	let created = current_time();
+	let manifest = Manifest {
+		version: BACKUP_VERSION,
+		created,
+		server_version: env!("CARGO_PKG_VERSION").to_string(),
+		records: trees.iter().map(|tree| tree.entries.len()).sum(),
+		images: images.len(),
+		legacy_images: legacy_images.len(),
+	};
+
+	let mut archive = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+	append_file(
+		&mut archive,
+		MANIFEST_PATH,
+		&serde_json::to_vec_pretty(&manifest)?,
+		created,
+	)?;
+	append_file(
+		&mut archive,
+		DATABASE_PATH,
+		&bincode::serialize(&trees)?,
+		created,
+	)?;
+	for (name, data) in images.iter() {
+		append_file(
+			&mut archive,
+			&format!("{}{}", IMAGE_STORE_PREFIX, name),
+			data,
+			created,
+		)?;
+	}
+	for (name, data) in legacy_images.iter() {
+		append_file(
+			&mut archive,
+			&format!("{}{}", LEGACY_IMAGE_PREFIX, name),
+			data,
+			created,
+		)?;
+	}
+	Ok((manifest, archive.into_inner()?.finish()?))
+}
+
+pub fn write_backup_file(database: &Database, path: &Path) -> Result<Manifest, BackupError> {
+	let (manifest, backup) = create_backup(database)?;
+	if let Some(parent) = path.parent() {
+		std::fs::create_dir_all(parent)?;
+	}
+	// Write next to the real file first, so a crash halfway through doesn't leave a broken backup behind.
+	let partial = path.with_extension("partial");
+	std::fs::write(&partial, &backup)?;
+	std::fs::rename(&partial, path)?;
+	Ok(manifest)
+}
+
+// Unpack a backup and make sure everything in it can be read, before anything gets changed.
+fn read_backup(archive: &[u8]) -> Result<Backup, BackupError> {
+	let mut manifest = None;
+	let mut trees = None;
+	let mut images = Vec::new();
+	let mut legacy_images = Vec::new();
+	let mut archive = tar::Archive::new(SizeLimit {
+		inner: GzDecoder::new(archive),
+		left: MAX_UNPACKED_SIZE,
+	});
+	for entry in archive.entries()? {
+		let mut entry = entry?;
+		if !entry.header().entry_type().is_file() {
+			continue;
+		}
+		let path = entry.path()?.to_string_lossy().into_owned();
+		let mut data = Vec::new();
+		entry.read_to_end(&mut data)?;
+		if path == MANIFEST_PATH {
+			manifest = Some(serde_json::from_slice::<Manifest>(&data)?);
+		} else if path == DATABASE_PATH {
+			trees = Some(bincode::deserialize::<Vec<TreeDump>>(&data)?);
+		} else if let Some(name) = path.strip_prefix(IMAGE_STORE_PREFIX) {
+			if !is_plain_file_name(name) {
+				return Err(BackupError::Invalid(format!("bad image name {}", path)));
+			}
+			images.push((name.to_string(), data));
+		} else if let Some(name) = path.strip_prefix(LEGACY_IMAGE_PREFIX) {
+			if !is_plain_file_name(name) {
+				return Err(BackupError::Invalid(format!("bad image name {}", path)));
+			}
+			legacy_images.push((name.to_string(), data));
+		} else {
+			return Err(BackupError::Invalid(format!("unexpected file {}", path)));
+		}
+	}
+	let manifest = manifest.ok_or_else(|| BackupError::Invalid("no manifest".to_string()))?;
+	if manifest.version > BACKUP_VERSION {
+		return Err(BackupError::Invalid(format!(
+			"made by a newer server (version {})",
+			manifest.version
+		)));
+	}
+	let trees = trees.ok_or_else(|| BackupError::Invalid("no database".to_string()))?;
+	let records = trees.iter().map(|tree| tree.entries.len()).sum::<usize>();
+	if records != manifest.records
+		|| images.len() != manifest.images
+		|| legacy_images.len() != manifest.legacy_images
+	{
+		return Err(BackupError::Invalid(
+			"doesn't have everything the manifest lists".to_string(),
+		));
+	}
+	for tree in trees.iter() {
+		for (key, value) in tree.entries.iter() {
+			database::check_record(key, value).map_err(|e| {
+				BackupError::Invalid(format!("{} ({})", e, String::from_utf8_lossy(key)))
+			})?;
+		}
+	}
+	// Originals are named by their hash, so they can be checked too. Thumbnails have the size after the hash.
+	for (name, data) in images.iter() {
+		if images::is_image_hash(name) && images::hash_image(data) != *name {
+			return Err(BackupError::Invalid(format!(
+				"image {} doesn't match its hash",
+				name
+			)));
+		}
+	}
+	Ok(Backup {
+		manifest,
+		trees,
+		images,
+		legacy_images,
+	})
+}
+
+// Add the old images that aren't here, they never change so there's nothing newer to keep.
+fn merge_legacy_images(images: &[(String, Vec<u8>)]) -> Result<(), BackupError> {
+	if images.is_empty() {
+		return Ok(());
+	}
+	std::fs::create_dir_all(LEGACY_IMAGE_DIRECTORY)?;
+	for (name, data) in images {
+		let path = Path::new(LEGACY_IMAGE_DIRECTORY).join(name);
+		if !path.exists() {
+			std::fs::write(path, data)?;
+		}
+	}
+	Ok(())
+}
+
+// Swap everything here for what's in the backup. Nothing is changed if the database or any of the files can't be written.
+fn replace_everything(database: &Database, backup: Backup) -> Result<(), BackupError> {
+	let mut images = backup.images;
+	let legacy_directory = Path::new(LEGACY_IMAGE_DIRECTORY);
+	// The old images are kept with the others when the image directory is where they used to be.
+	let legacy_staged = if config::get_config().image_directory.as_deref() == Some(legacy_directory)
+	{
+		images.extend(backup.legacy_images);
+		None
+	} else if !backup.legacy_images.is_empty() || legacy_directory.exists() {
+		Some(stage_directory(legacy_directory, &backup.legacy_images)?)
+	} else {
+		None
+	};
+	if let Err(e) = database.replace_trees(&backup.trees, &images) {
+		if let Some(staged) = legacy_staged {
+			let _ = std::fs::remove_dir_all(staged);
+		}
+		return Err(e.into());
+	}
+	if let Some(staged) = legacy_staged {
+		swap_directory(legacy_directory, &staged)?;
+	}
+	Ok(())
+}
+
+// Add what's newer in the backup, the same way data from scouts and other servers is added. The alliance selection
+// and scout assignments have nothing saying when they changed, so they're only taken if there aren't any here.
+// Returns the keys of records that were left out.
+fn merge_everything(database: &Database, backup: &Backup) -> Result<Vec<String>, BackupError> {
+	let mut infos = Vec::new();
+	let mut image_meta = Vec::new();
+	let mut image_data = std::collections::HashMap::new();
+	let mut skipped = Vec::new();
+	for tree in backup.trees.iter() {
+		if tree.name != b"__sled__default" {
+			skipped.extend(tree.entries.iter().map(|(key, _)| {
+				format!(
+					"{} in tree {}",
+					String::from_utf8_lossy(key),
+					String::from_utf8_lossy(&tree.name)
+				)
+			}));
+			continue;
+		}
+		for (key, value) in tree.entries.iter() {
+			if let Some(info) = database::decode_info(key, value)? {
+				infos.push(info);
+			} else if let Some(meta) = database::decode_image_record(key, value)? {
+				image_meta.push(meta);
+			} else if let Some(name) = key.strip_prefix(b"image_data_") {
+				image_data.insert(String::from_utf8_lossy(name).into_owned(), value.as_slice());
+			} else if key == b"alliance_selection" || key == b"assignments" {
+				if !database.insert_if_missing(key, value)? {
+					skipped.push(String::from_utf8_lossy(key).into_owned());
+				}
+			} else {
+				skipped.push(String::from_utf8_lossy(key).into_owned());
+			}
+		}
+	}
+	for (name, data) in backup.images.iter() {
+		image_data.insert(name.clone(), data.as_slice());
+	}
+	// Images go first, so pit visits that link to them don't get ahead of the images themselves.
+	for meta in image_meta {
+		match image_data.get(&meta.hash) {
+			Some(data) if !meta.deleted => database.write_image_with_meta(&meta, data)?,
+			_ => database.merge_image_meta(&meta)?,
+		}
+	}
+	database.merge_info(&infos)?;
+	merge_legacy_images(&backup.legacy_images)?;
+	Ok(skipped)
+}
+
+// Put a backup back into the database, after checking the whole thing first.
+pub fn restore_backup(
+	database: &Database,
+	archive: &[u8],
+	mode: RestoreMode,
+) -> Result<Restored, BackupError> {
+	let backup = read_backup(archive)?;
+	let manifest = backup.manifest.clone();
+	let skipped = match mode {
+		RestoreMode::Replace => {
+			replace_everything(database, backup)?;
+			Vec::new()
+		}
+		RestoreMode::Merge => merge_everything(database, &backup)?,
+	};
+	Ok(Restored { manifest, skipped })
+}
+
+fn backup_file_name(time: u64) -> String {
+	format!("backup-{}.tar.gz", time)
+}
+
+// Save a backup into the backup directory, then delete the oldest ones so only the configured number are kept.
+pub fn run_scheduled_backup(database: &Database, directory: &Path) -> Result<PathBuf, BackupError> {
+	let path = directory.join(backup_file_name(current_time()));
+	write_backup_file(database, &path)?;
+	let keep = config::get_config()
+		.backup_count
+		.unwrap_or(DEFAULT_BACKUP_COUNT)
+		.max(1);
+	let mut backups = std::fs::read_dir(directory)?
+		.filter_map(|entry| entry.ok())
+		.filter_map(|entry| {
+			let name = entry.file_name().to_str()?.to_string();
+			let time = name
+				.strip_prefix("backup-")?
+				.strip_suffix(".tar.gz")?
+				.parse::<u64>()
+				.ok()?;
+			Some((time, entry.path()))
+		})
+		.collect::<Vec<_>>();
+	backups.sort_by_key(|(time, _)| std::cmp::Reverse(*time));
+	for (_, old) in backups.into_iter().skip(keep) {
+		std::fs::remove_file(old)?;
+	}
+	Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::formula::Formula;
+
+	// A database of its own in the temp directory, deleted when the test is done with it.
+	struct TestDatabase {
+		path: PathBuf,
+		database: Database,
+	}
+
+	impl TestDatabase {
+		fn new(name: &str) -> Self {
+			let path = std::env::temp_dir().join(format!(
+				"automated-scout-backup-{}-{}",
+				name,
+				std::process::id()
+			));
+			let _ = std::fs::remove_dir_all(&path);
+			TestDatabase {
+				database: Database::open(&path),
+				path,
+			}
+		}
+		fn formula_names(&self) -> Vec<String> {
+			let mut names = self
+				.database
+				.get_all_formulas()
+				.unwrap()
+				.into_iter()
+				.map(|f| f.name)
+				.collect::<Vec<_>>();
+			names.sort();
+			names
+		}
+	}
+
+	impl Drop for TestDatabase {
+		fn drop(&mut self) {
+			let _ = std::fs::remove_dir_all(&self.path);
+		}
+	}
+
+	fn formula(name: &str) -> Formula {
+		Formula {
+			name: name.to_string(),
+			expression: "1 + 2".to_string(),
+			last_modified_time: current_time(),
+			deleted: false,
+		}
+	}
+
+	// A backup with one file added to it, written without the checks the tar crate makes on paths.
+	fn backup_with_file(path: &str) -> Vec<u8> {
+		let created = current_time();
+		let manifest = Manifest {
+			version: BACKUP_VERSION,
+			created,
+			server_version: String::new(),
+			records: 0,
+			images: 1,
+			legacy_images: 0,
+		};
+		let mut archive = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+		append_file(
+			&mut archive,
+			MANIFEST_PATH,
+			&serde_json::to_vec(&manifest).unwrap(),
+			created,
+		)
+		.unwrap();
+		append_file(
+			&mut archive,
+			DATABASE_PATH,
+			&bincode::serialize(&Vec::<TreeDump>::new()).unwrap(),
+			created,
+		)
+		.unwrap();
+		let mut header = tar::Header::new_gnu();
+		header.as_gnu_mut().unwrap().name[..path.len()].copy_from_slice(path.as_bytes());
+		header.set_size(4);
+		header.set_mode(0o644);
+		header.set_cksum();
+		archive.append(&header, &b"evil"[..]).unwrap();
+		archive.into_inner().unwrap().finish().unwrap()
+	}
+
+	#[test]
+	fn only_plain_file_names() {
+		assert!(is_plain_file_name("abc.jpeg"));
+		assert!(!is_plain_file_name("../abc.jpeg"));
+		assert!(!is_plain_file_name("a/b"));
+		assert!(!is_plain_file_name("/etc/passwd"));
+		assert!(!is_plain_file_name(".."));
+		assert!(!is_plain_file_name(""));
+	}
+
+	#[test]
+	fn refuses_images_outside_the_image_directory() {
+		assert!(read_backup(&backup_with_file("image_store/plain")).is_ok());
+		for path in [
+			"image_store/../evil",
+			"image_store/a/../../evil",
+			"legacy_images/../evil",
+		] {
+			assert!(
+				matches!(
+					read_backup(&backup_with_file(path)),
+					Err(BackupError::Invalid(e)) if e.starts_with("bad image name")
+				),
+				"{}",
+				path
+			);
+		}
+	}
+
+	#[test]
+	fn replace_restores_what_was_backed_up() {
+		let test = TestDatabase::new("replace");
+		test.database.write_formula(&formula("kept")).unwrap();
+		let (manifest, archive) = create_backup(&test.database).unwrap();
+		assert_eq!(manifest.records, 1);
+		test.database
+			.write_formula(&formula("added later"))
+			.unwrap();
+
+		let restored = restore_backup(&test.database, &archive, RestoreMode::Replace).unwrap();
+		assert!(restored.skipped.is_empty());
+		assert_eq!(test.formula_names(), vec!["kept"]);
+	}
+
+	#[test]
+	fn merge_keeps_what_is_here() {
+		let from = TestDatabase::new("merge-from");
+		from.database.write_formula(&formula("backed up")).unwrap();
+		from.database.update_alliance_selection(|_| true).unwrap();
+		let (_, archive) = create_backup(&from.database).unwrap();
+
+		let to = TestDatabase::new("merge-to");
+		to.database.write_formula(&formula("already here")).unwrap();
+		let restored = restore_backup(&to.database, &archive, RestoreMode::Merge).unwrap();
+		assert!(restored.skipped.is_empty());
+		assert_eq!(to.formula_names(), vec!["already here", "backed up"]);
+		assert!(to.database.get_alliance_selection().unwrap().is_some());
+
+		// There's an alliance selection now, so the one in the backup is left out.
+		let restored = restore_backup(&to.database, &archive, RestoreMode::Merge).unwrap();
+		assert_eq!(restored.skipped, vec!["alliance_selection"]);
+	}
+}
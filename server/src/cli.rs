@@ -0,0 +1,345 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use argh::FromArgs;
+
+use crate::analysis::{self, AnalysisOptions};
+use crate::backup::{self, RestoreMode};
+use crate::data::parse_infos;
+use crate::database::Database;
+use crate::export::{self, Table};
+use crate::scouts;
+
+#[derive(Debug, PartialEq, FromArgs)]
+#[argh(subcommand)]
+pub enum Command {
+	Serve(ServeCommand),
+	Import(ImportCommand),
+	Export(ExportCommand),
+	Backup(BackupCommand),
+	Restore(RestoreCommand),
+	Validate(ValidateCommand),
+	Stats(StatsCommand),
+	Reanalyze(ReanalyzeCommand),
+	Purge(PurgeCommand),
+}
+
+#[derive(Debug, PartialEq, FromArgs)]
+/// run the server, this is what happens when no command is given
+#[argh(subcommand, name = "serve")]
+pub struct ServeCommand {}
+
+#[derive(Debug, PartialEq, FromArgs)]
+/// add matches, pit visits and pick lists from a JSON file, in the same format as /api/push or /api/pull
+#[argh(subcommand, name = "import")]
+pub struct ImportCommand {
+	/// file to read
+	#[argh(positional)]
+	file: PathBuf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+	Csv,
+	Json,
+}
+
+impl FromStr for ExportFormat {
+	type Err = String;
+
+	fn from_str(format: &str) -> Result<Self, Self::Err> {
+		match format.to_lowercase().as_str() {
+			"csv" => Ok(ExportFormat::Csv),
+			"json" => Ok(ExportFormat::Json),
+			_ => Err(format!("{} isn't csv or json.", format)),
+		}
+	}
+}
+
+#[derive(Debug, PartialEq, FromArgs)]
+/// write out every match as CSV, or everything scouted as JSON that import can read back
+#[argh(subcommand, name = "export")]
+pub struct ExportCommand {
+	/// csv or json, csv if left out
+	#[argh(option, default = "ExportFormat::Csv")]
+	format: ExportFormat,
+	/// comma separated columns for csv, all of them if left out
+	#[argh(option)]
+	columns: Option<String>,
+	/// file to write to instead of the terminal
+	#[argh(option, short = 'o')]
+	output: Option<PathBuf>,
+}
+
+#[derive(Debug, PartialEq, FromArgs)]
+/// write a backup of the database and images to a file
+#[argh(subcommand, name = "backup")]
+pub struct BackupCommand {
+	/// file to write
+	#[argh(positional)]
+	file: PathBuf,
+}
+
+#[derive(Debug, PartialEq, FromArgs)]
+/// replace the database and images with a backup
+#[argh(subcommand, name = "restore")]
+pub struct RestoreCommand {
+	/// backup to restore
+	#[argh(positional)]
+	file: PathBuf,
+	/// only add what's newer than the data already here instead of replacing everything
+	#[argh(switch)]
+	merge: bool,
+}
+
+#[derive(Debug, PartialEq, FromArgs)]
+/// look through every record in the database and report the ones that can't be read or are being skipped
+#[argh(subcommand, name = "validate")]
+pub struct ValidateCommand {}
+
+#[derive(Debug, PartialEq, FromArgs)]
+/// show how much has been scouted
+#[argh(subcommand, name = "stats")]
+pub struct StatsCommand {}
+
+#[derive(Debug, PartialEq, FromArgs)]
+/// work out the team analysis again and show it
+#[argh(subcommand, name = "reanalyze")]
+pub struct ReanalyzeCommand {
+	/// name of a saved formula to sort the teams by
+	#[argh(option)]
+	sort: Option<String>,
+	/// write the whole analysis to this file as JSON
+	#[argh(option, short = 'o')]
+	output: Option<PathBuf>,
+}
+
+#[derive(Debug, PartialEq, FromArgs)]
+/// delete matches and pit visits last changed before a time, like ones from testing before the event
+#[argh(subcommand, name = "purge")]
+pub struct PurgeCommand {
+	/// time in milliseconds since 1970, the same as lastModifiedTime
+	#[argh(option)]
+	before: u64,
+	/// only show how many would be deleted
+	#[argh(switch)]
+	dry_run: bool,
+}
+
+fn write_output(output: &Option<PathBuf>, data: &str) -> Result<(), String> {
+	match output {
+		Some(path) => std::fs::write(path, data).map_err(|e| e.to_string()),
+		// Stop quietly if whatever's reading the output stops first, like head.
+		None => match std::io::stdout().write_all(data.as_bytes()) {
+			Err(e) if e.kind() != std::io::ErrorKind::BrokenPipe => Err(e.to_string()),
+			_ => Ok(()),
+		},
+	}
+}
+
+fn import(database: &Database, file: &Path) -> Result<(), String> {
+	let data = std::fs::read(file).map_err(|e| e.to_string())?;
+	let value: serde_json::Value = serde_json::from_slice(&data).map_err(|e| e.to_string())?;
+	// What /api/pull sends has the list under data.
+	let values = match value {
+		serde_json::Value::Array(values) => values,
+		serde_json::Value::Object(mut object) => match object.remove("data") {
+			Some(serde_json::Value::Array(values)) => values,
+			_ => return Err("File doesn't have a list of matches in it.".to_string()),
+		},
+		_ => return Err("File doesn't have a list of matches in it.".to_string()),
+	};
+	let total = values.len();
+	let infos = parse_infos(values);
+	database.merge_info(&infos).map_err(|e| e.to_string())?;
+	println!(
+		"Imported {} of {} entries from {}",
+		infos.len(),
+		total,
+		file.display()
+	);
+	Ok(())
+}
+
+fn export(database: &Database, command: &ExportCommand) -> Result<(), String> {
+	let data = match command.format {
+		ExportFormat::Csv => {
+			let matches = database
+				.get_all_matches()
+				.collect::<Result<Vec<_>, _>>()
+				.map_err(|e| e.to_string())?;
+			Table::new(export::MATCH_COLUMNS, &matches)
+				.select(command.columns.as_deref())
+				.and_then(|table| table.to_csv())
+				.map_err(|e| e.to_string())?
+		}
+		ExportFormat::Json => {
+			let infos = database.get_info_list().map_err(|e| e.to_string())?;
+			serde_json::to_string_pretty(&infos).map_err(|e| e.to_string())? + "\n"
+		}
+	};
+	write_output(&command.output, &data)
+}
+
+fn validate(database: &Database) -> Result<(), String> {
+	let problems = database.validate_records().map_err(|e| e.to_string())?;
+	for (key, problem) in problems.iter() {
+		println!("{}: {}", key, problem);
+	}
+	if problems.is_empty() {
+		println!("Every record is fine.");
+		Ok(())
+	} else {
+		Err(format!("{} records have problems.", problems.len()))
+	}
+}
+
+fn stats(database: &Database) -> Result<(), String> {
+	let matches = database
+		.get_all_matches()
+		.collect::<Result<Vec<_>, _>>()
+		.map_err(|e| e.to_string())?;
+	let robots = database
+		.get_all_robots()
+		.collect::<Result<Vec<_>, _>>()
+		.map_err(|e| e.to_string())?;
+	let scout_matches = database
+		.get_all_scout_matches()
+		.map_err(|e| e.to_string())?;
+	let images = database.get_all_image_meta().map_err(|e| e.to_string())?;
+	let mut teams = matches
+		.iter()
+		.map(|m| m.team_number)
+		.chain(robots.iter().map(|r| r.team_number))
+		.collect::<Vec<_>>();
+	teams.sort_unstable();
+	teams.dedup();
+	let mut scouts = scout_matches
+		.iter()
+		.map(|m| scouts::scout_key(&m.scout))
+		.collect::<Vec<_>>();
+	scouts.sort_unstable();
+	scouts.dedup();
+	let last_change = matches
+		.iter()
+		.map(|m| m.last_modified_time)
+		.chain(robots.iter().map(|r| r.last_modified_time))
+		.max();
+
+	println!("Matches:      {}", matches.len());
+	println!("Scout copies: {}", scout_matches.len());
+	println!("Pit visits:   {}", robots.len());
+	println!("Teams:        {}", teams.len());
+	println!("Scouts:       {}", scouts.len());
+	println!(
+		"Images:       {} ({} deleted)",
+		images.iter().filter(|i| !i.deleted).count(),
+		images.iter().filter(|i| i.deleted).count()
+	);
+	println!(
+		"Pick lists:   {}",
+		database
+			.get_all_pick_lists()
+			.map_err(|e| e.to_string())?
+			.len()
+	);
+	println!(
+		"Formulas:     {}",
+		database
+			.get_all_formulas()
+			.map_err(|e| e.to_string())?
+			.len()
+	);
+	if let Some(last_change) = last_change {
+		println!("Last change:  {}", last_change);
+	}
+	println!(
+		"Size on disk: {} bytes",
+		database.size_on_disk().map_err(|e| e.to_string())?
+	);
+	Ok(())
+}
+
+fn reanalyze(database: &Database, command: &ReanalyzeCommand) -> Result<(), String> {
+	let options = AnalysisOptions {
+		sort: command.sort.clone(),
+		..AnalysisOptions::default()
+	};
+	options.validate(database)?;
+	let teams = analysis::analyze_data(database, &options);
+	if let Some(path) = &command.output {
+		let data = serde_json::to_string_pretty(&teams).map_err(|e| e.to_string())?;
+		std::fs::write(path, data).map_err(|e| e.to_string())?;
+	}
+	println!("team  matches  auto    teleop  opr");
+	for team in teams.iter().filter(|t| t.team_number != 0) {
+		println!(
+			"{:<5} {:<8} {:<7.1} {:<7.1} {:.1}",
+			team.team_number,
+			team.matches,
+			team.average_auto_score,
+			team.average_teleop_score,
+			team.opr
+		);
+	}
+	Ok(())
+}
+
+fn purge(database: &Database, command: &PurgeCommand) -> Result<(), String> {
+	let purged = database
+		.purge_before(command.before, command.dry_run)
+		.map_err(|e| e.to_string())?;
+	if command.dry_run {
+		println!("Would delete {} records.", purged);
+	} else {
+		println!("Deleted {} records.", purged);
+		println!("Older records won't be taken from other servers now, but they keep theirs until purge is run there too.");
+	}
+	Ok(())
+}
+
+// Run a command that works on the database directly, without the server running. Serving is handled by main.
+pub fn run(command: &Command, database: &Database) -> Result<(), String> {
+	match command {
+		Command::Serve(_) => Ok(()),
+		Command::Import(command) => import(database, &command.file),
+		Command::Export(command) => export(database, command),
+		Command::Backup(command) => {
+			let manifest =
+				backup::write_backup_file(database, &command.file).map_err(|e| e.to_string())?;
+			println!(
+				"Backed up {} records and {} images to {}",
+				manifest.records,
+				manifest.images + manifest.legacy_images,
+				command.file.display()
+			);
+			Ok(())
+		}
+		Command::Restore(command) => {
+			let mode = if command.merge {
+				RestoreMode::Merge
+			} else {
+				RestoreMode::Replace
+			};
+			let restored = std::fs::read(&command.file)
+				.map_err(backup::BackupError::from)
+				.and_then(|archive| backup::restore_backup(database, &archive, mode))
+				.map_err(|e| e.to_string())?;
+			println!(
+				"Restored {} records and {} images from {}",
+				restored.manifest.records,
+				restored.manifest.images + restored.manifest.legacy_images,
+				command.file.display()
+			);
+			for key in restored.skipped.iter() {
+				println!("Skipped {}", key);
+			}
+			Ok(())
+		}
+		Command::Validate(_) => validate(database),
+		Command::Stats(_) => stats(database),
+		Command::Reanalyze(command) => reanalyze(database, command),
+		Command::Purge(command) => purge(database, command),
+	}
+}
@@ -1,10 +1,14 @@
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::BufReader;
 use std::path::PathBuf;
+use std::sync::OnceLock;
 
 use argh::FromArgs;
 use serde::{Deserialize, Serialize};
 
+use crate::cli::Command;
+
 #[derive(Debug, FromArgs)]
 /// Server configuration arguments
 struct Args {
@@ -14,15 +18,94 @@ struct Args {
 	/// if specified, try to load configuration from this file instead of the default config.yaml
 	#[argh(option, short = 'c')]
 	config_file: Option<PathBuf>,
+	#[argh(subcommand)]
+	command: Option<Command>,
 }
 
+// Tags given to matches whose notes mention any of the words after them, used when the config doesn't have its own.
+const DEFAULT_NOTE_KEYWORDS: [(&str, &[&str]); 6] = [
+	(
+		"tipped",
+		&["tipped", "tip over", "tipping", "fell over", "flipped"],
+	),
+	("brownout", &["brownout", "brown out", "browned out"]),
+	(
+		"no show",
+		&["no show", "didn't show", "did not show", "not on field"],
+	),
+	(
+		"disconnected",
+		&["disconnect", "lost comms", "no comms", "lost connection"],
+	),
+	(
+		"broken",
+		&["broke", "broken", "fell off", "mechanism failure"],
+	),
+	("stuck", &["stuck", "beached", "high centered"]),
+];
+
 #[derive(Debug, Default, Deserialize, Serialize)]
 pub struct Config {
 	pub leader_url: Option<String>,
 	pub sync_interval: f32,
+	// The team running the server, it doesn't get scouts assigned to it.
+	#[serde(default)]
+	pub team_number: Option<u32>,
+	// Where to keep uploaded images, they're kept in the database if this isn't set.
+	#[serde(default)]
+	pub image_directory: Option<PathBuf>,
+	#[serde(default)]
+	pub note_keywords: HashMap<String, Vec<String>>,
+	// Save a backup here every backup_interval minutes, keeping the newest backup_count of them.
+	#[serde(default)]
+	pub backup_directory: Option<PathBuf>,
+	#[serde(default)]
+	pub backup_interval: Option<u64>,
+	#[serde(default)]
+	pub backup_count: Option<usize>,
+	// Has to be sent as a bearer token to back up or restore over HTTP, which is turned off if it isn't set.
+	#[serde(default)]
+	pub admin_token: Option<String>,
+	// What to do instead of running the server, from the command line.
+	#[serde(skip)]
+	pub command: Option<Command>,
+}
+
+impl Config {
+	// Words to look for in match notes, by tag, all in lowercase.
+	pub fn note_keywords(&self) -> HashMap<String, Vec<String>> {
+		if self.note_keywords.is_empty() {
+			DEFAULT_NOTE_KEYWORDS
+				.iter()
+				.map(|(tag, keywords)| {
+					(
+						tag.to_string(),
+						keywords.iter().map(|k| k.to_string()).collect(),
+					)
+				})
+				.collect()
+		} else {
+			self.note_keywords
+				.iter()
+				.map(|(tag, keywords)| {
+					(
+						tag.clone(),
+						keywords.iter().map(|k| k.to_lowercase()).collect(),
+					)
+				})
+				.collect()
+		}
+	}
 }
 
-pub fn read_config() -> Config {
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+// The configuration the server was started with, or the defaults if it hasn't been read.
+pub fn get_config() -> &'static Config {
+	CONFIG.get_or_init(Config::default)
+}
+
+pub fn read_config() -> &'static Config {
 	let args: Args = argh::from_env();
 
 	let config_file_path = args.config_file.unwrap_or_else(|| "config.yml".into());
@@ -34,8 +117,17 @@ pub fn read_config() -> Config {
 		Config::default()
 	};
 
-	Config {
+	let config = Config {
 		leader_url: args.leader_url.or(config_file.leader_url),
 		sync_interval: config_file.sync_interval,
-	}
+		team_number: config_file.team_number,
+		image_directory: config_file.image_directory,
+		note_keywords: config_file.note_keywords,
+		backup_directory: config_file.backup_directory,
+		backup_interval: config_file.backup_interval,
+		backup_count: config_file.backup_count,
+		admin_token: config_file.admin_token,
+		command: args.command,
+	};
+	CONFIG.get_or_init(|| config)
 }
@@ -0,0 +1,151 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::Serialize;
+
+use crate::analysis;
+use crate::config;
+use crate::data::MatchType;
+use crate::database::{Database, DatabaseError};
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MatchCoverage {
+	pub match_category: MatchType,
+	pub match_number: u32,
+	pub robots: u32,
+	pub scouted: u32,
+	// Robots in the match that nobody sent a record for.
+	pub missing: Vec<u32>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TeamCoverage {
+	pub team_number: u32,
+	pub played_matches: u32,
+	pub scouted_matches: u32,
+	pub coverage: f32,
+	pub missing_matches: Vec<(MatchType, u32)>,
+	pub pit_scouted: bool,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CoverageReport {
+	pub played_matches: u32,
+	pub fully_scouted_matches: u32,
+	pub team_matches: u32,
+	pub scouted_team_matches: u32,
+	// Share of robots in played matches that have a record.
+	pub coverage: f32,
+	// Only the played matches that are missing something, in the order they were played.
+	pub matches: Vec<MatchCoverage>,
+	pub teams: Vec<TeamCoverage>,
+	pub pit_scouted_teams: u32,
+	pub pit_coverage: f32,
+	// Teams at the event that haven't had a pit visit.
+	pub missing_pit_teams: Vec<u32>,
+}
+
+// Line the TBA schedule up with what's been scouted, to find the robots and pits nobody has data for yet.
+// We don't scout ourselves, so our own team is left out.
+pub fn get_coverage(database: &Database) -> Result<CoverageReport, DatabaseError> {
+	let (tba_teams, tba_matches) = analysis::get_tba_data();
+	let our_team = config::get_config().team_number;
+	let mut scouted = HashSet::new();
+	for match_info in database.get_all_matches() {
+		let match_info = match_info?;
+		scouted.insert((
+			match_info.match_category,
+			match_info.match_number,
+			match_info.team_number,
+		));
+	}
+	let mut pit_scouted = HashSet::new();
+	for robot_info in database.get_all_robots() {
+		pit_scouted.insert(robot_info?.team_number);
+	}
+
+	let mut played = tba_matches
+		.iter()
+		.filter(|(_, tba_match)| tba_match.played)
+		.collect::<Vec<_>>();
+	played.sort_by_key(|((category, match_number), _)| {
+		(*category == MatchType::Qualification, *match_number)
+	});
+
+	let mut report = CoverageReport::default();
+	let mut teams: HashMap<u32, TeamCoverage> = HashMap::new();
+	for ((category, match_number), tba_match) in played {
+		let mut coverage = MatchCoverage {
+			match_category: *category,
+			match_number: *match_number,
+			robots: 0,
+			scouted: 0,
+			missing: Vec::new(),
+		};
+		for team_number in tba_match
+			.blue_teams
+			.iter()
+			.chain(tba_match.red_teams.iter())
+		{
+			if Some(*team_number) == our_team {
+				continue;
+			}
+			let team = teams.entry(*team_number).or_insert_with(|| TeamCoverage {
+				team_number: *team_number,
+				played_matches: 0,
+				scouted_matches: 0,
+				coverage: 0.0,
+				missing_matches: Vec::new(),
+				pit_scouted: pit_scouted.contains(team_number),
+			});
+			team.played_matches += 1;
+			coverage.robots += 1;
+			if scouted.contains(&(*category, *match_number, *team_number)) {
+				team.scouted_matches += 1;
+				coverage.scouted += 1;
+			} else {
+				team.missing_matches.push((*category, *match_number));
+				coverage.missing.push(*team_number);
+			}
+		}
+		report.played_matches += 1;
+		report.team_matches += coverage.robots;
+		report.scouted_team_matches += coverage.scouted;
+		if coverage.missing.is_empty() {
+			report.fully_scouted_matches += 1;
+		} else {
+			report.matches.push(coverage);
+		}
+	}
+	report.coverage = analysis::rate(
+		report.scouted_team_matches as f32,
+		report.team_matches as f32,
+	);
+
+	let mut event_teams = tba_teams
+		.keys()
+		.copied()
+		.filter(|team| Some(*team) != our_team)
+		.collect::<Vec<_>>();
+	event_teams.sort_unstable();
+	for team_number in event_teams.iter() {
+		if pit_scouted.contains(team_number) {
+			report.pit_scouted_teams += 1;
+		} else {
+			report.missing_pit_teams.push(*team_number);
+		}
+	}
+	report.pit_coverage = analysis::rate(report.pit_scouted_teams as f32, event_teams.len() as f32);
+
+	report.teams = teams
+		.into_values()
+		.map(|mut team| {
+			team.coverage = analysis::rate(team.scouted_matches as f32, team.played_matches as f32);
+			team
+		})
+		.collect();
+	report.teams.sort_by_key(|team| team.team_number);
+	Ok(report)
+}
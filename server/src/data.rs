@@ -1,20 +1,17 @@
+use crate::formula::Formula;
+use crate::pick_list::PickList;
 use serde::{Deserialize, Serialize};
 use std::fmt::{Display, Formatter};
 
 // Setting up MatchType
-#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq, Deserialize, Serialize)]
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub enum MatchType {
 	Qualification,
+	#[default]
 	Practice,
 }
 
-impl Default for MatchType {
-	fn default() -> Self {
-		MatchType::Practice
-	}
-}
-
 impl Display for MatchType {
 	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
 		match self {
@@ -24,21 +21,16 @@ impl Display for MatchType {
 	}
 }
 // Setting up AutoChargeStation
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub enum AutoChargeStation {
+	#[default]
 	Off,
 	On,
 	Charged,
 	OtherRobot,
 }
 
-impl Default for AutoChargeStation {
-	fn default() -> Self {
-		AutoChargeStation::Off
-	}
-}
-
 impl Display for AutoChargeStation {
 	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
 		match self {
@@ -50,21 +42,16 @@ impl Display for AutoChargeStation {
 	}
 }
 // Setting up TeleopChargeStation
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub enum TeleopChargeStation {
+	#[default]
 	Off,
 	Parked,
 	On,
 	Charged,
 }
 
-impl Default for TeleopChargeStation {
-	fn default() -> Self {
-		TeleopChargeStation::Off
-	}
-}
-
 impl Display for TeleopChargeStation {
 	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
 		match self {
@@ -76,23 +63,18 @@ impl Display for TeleopChargeStation {
 	}
 }
 // Setting up PreferredPlay
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 #[serde(from = "u32")]
 #[serde(into = "u32")]
 pub enum PreferredPlay {
 	Defence = 0,
 	PreferDefence = 1,
+	#[default]
 	PreferOffence = 2,
 	Offence = 3,
 }
 
-impl Default for PreferredPlay {
-	fn default() -> Self {
-		PreferredPlay::PreferOffence
-	}
-}
-
 impl From<u32> for PreferredPlay {
 	fn from(value: u32) -> Self {
 		match value {
@@ -115,24 +97,30 @@ impl From<PreferredPlay> for u32 {
 		}
 	}
 }
+
+impl Display for PreferredPlay {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			PreferredPlay::Defence => write!(f, "Defence"),
+			PreferredPlay::PreferDefence => write!(f, "Prefer Defence"),
+			PreferredPlay::PreferOffence => write!(f, "Prefer Offence"),
+			PreferredPlay::Offence => write!(f, "Offence"),
+		}
+	}
+}
 // Setting up HumanPickupRange
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 #[serde(from = "u32")]
 #[serde(into = "u32")]
 pub enum HumanPickupRange {
+	#[default]
 	None = 0,
 	Chute = 1,
 	SlideShelf = 2,
 	Both = 3,
 }
 
-impl Default for HumanPickupRange {
-	fn default() -> Self {
-		HumanPickupRange::None
-	}
-}
-
 impl From<u32> for HumanPickupRange {
 	fn from(value: u32) -> Self {
 		match value {
@@ -155,6 +143,17 @@ impl From<HumanPickupRange> for u32 {
 		}
 	}
 }
+
+impl Display for HumanPickupRange {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			HumanPickupRange::None => write!(f, "None"),
+			HumanPickupRange::Chute => write!(f, "Chute"),
+			HumanPickupRange::SlideShelf => write!(f, "Slide Shelf"),
+			HumanPickupRange::Both => write!(f, "Both"),
+		}
+	}
+}
 // Setting up StackType
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -189,24 +188,30 @@ impl From<StackType> for u32 {
 		}
 	}
 }
+
+impl Display for StackType {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			StackType::None => write!(f, "None"),
+			StackType::Cone => write!(f, "Cone"),
+			StackType::Cube => write!(f, "Cube"),
+			StackType::Both => write!(f, "Both"),
+		}
+	}
+}
 // Setting up PreferredStack
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 #[serde(from = "u32")]
 #[serde(into = "u32")]
 pub enum PreferredStack {
+	#[default]
 	None = 0,
 	Hybrid = 1,
 	Middle = 2,
 	High = 3,
 }
 
-impl Default for PreferredStack {
-	fn default() -> Self {
-		PreferredStack::None
-	}
-}
-
 impl From<u32> for PreferredStack {
 	fn from(value: u32) -> Self {
 		match value {
@@ -229,25 +234,31 @@ impl From<PreferredStack> for u32 {
 		}
 	}
 }
+
+impl Display for PreferredStack {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			PreferredStack::None => write!(f, "None"),
+			PreferredStack::Hybrid => write!(f, "Hybrid"),
+			PreferredStack::Middle => write!(f, "Middle"),
+			PreferredStack::High => write!(f, "High"),
+		}
+	}
+}
 // Setting up ConfidenceLevel
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 #[serde(from = "u32")]
 #[serde(into = "u32")]
 pub enum ConfidenceLevel {
 	HonestlyUnconfident = 0,
 	SemiUnconfident = 1,
+	#[default]
 	Middle = 2,
 	Confident = 3,
 	TooConfident = 4,
 }
 
-impl Default for ConfidenceLevel {
-	fn default() -> Self {
-		ConfidenceLevel::Middle
-	}
-}
-
 impl From<u32> for ConfidenceLevel {
 	fn from(value: u32) -> Self {
 		match value {
@@ -272,22 +283,29 @@ impl From<ConfidenceLevel> for u32 {
 		}
 	}
 }
+
+impl Display for ConfidenceLevel {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			ConfidenceLevel::HonestlyUnconfident => write!(f, "Honestly Unconfident"),
+			ConfidenceLevel::SemiUnconfident => write!(f, "Semi Unconfident"),
+			ConfidenceLevel::Middle => write!(f, "Middle"),
+			ConfidenceLevel::Confident => write!(f, "Confident"),
+			ConfidenceLevel::TooConfident => write!(f, "Too Confident"),
+		}
+	}
+}
 // Setting up ChargeBattery
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 #[serde(from = "u32")]
 #[serde(into = "u32")]
 pub enum ChargeBattery {
+	#[default]
 	No = 0,
 	Yes = 1,
 }
 
-impl Default for ChargeBattery {
-	fn default() -> Self {
-		ChargeBattery::No
-	}
-}
-
 impl From<u32> for ChargeBattery {
 	fn from(value: u32) -> Self {
 		match value {
@@ -306,24 +324,28 @@ impl From<ChargeBattery> for u32 {
 		}
 	}
 }
+
+impl Display for ChargeBattery {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			ChargeBattery::No => write!(f, "No"),
+			ChargeBattery::Yes => write!(f, "Yes"),
+		}
+	}
+}
 // Setting up VisionType
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 #[serde(from = "u32")]
 #[serde(into = "u32")]
 pub enum VisionType {
+	#[default]
 	None = 0,
 	Tape = 1,
 	AprilTags = 2,
 	Both = 3,
 }
 
-impl Default for VisionType {
-	fn default() -> Self {
-		VisionType::None
-	}
-}
-
 impl From<u32> for VisionType {
 	fn from(value: u32) -> Self {
 		match value {
@@ -346,23 +368,29 @@ impl From<VisionType> for u32 {
 		}
 	}
 }
+
+impl Display for VisionType {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			VisionType::None => write!(f, "None"),
+			VisionType::Tape => write!(f, "Tape"),
+			VisionType::AprilTags => write!(f, "April Tags"),
+			VisionType::Both => write!(f, "Both"),
+		}
+	}
+}
 // Setting up BumperType
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 #[serde(from = "u32")]
 #[serde(into = "u32")]
 pub enum BumperType {
+	#[default]
 	None = 0,
 	Swap = 1,
 	Reversable = 2,
 }
 
-impl Default for BumperType {
-	fn default() -> Self {
-		BumperType::None
-	}
-}
-
 impl From<u32> for BumperType {
 	fn from(value: u32) -> Self {
 		match value {
@@ -384,6 +412,16 @@ impl From<BumperType> for u32 {
 	}
 }
 
+impl Display for BumperType {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			BumperType::None => write!(f, "None"),
+			BumperType::Swap => write!(f, "Swap"),
+			BumperType::Reversable => write!(f, "Reversable"),
+		}
+	}
+}
+
 // Setting up Auto Structure, match info can pull from here
 #[derive(Debug, Clone, Default, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -436,6 +474,43 @@ pub struct MatchInfo {
 	pub was_disabled: bool,
 	pub notes: String,
 	pub last_modified_time: u64,
+	// Name of whoever scouted the match, must stay last so older saved matches can still be read
+	pub scout: String,
+}
+
+// Match info the way it was saved before the scout's name was added
+#[derive(Debug, Clone, Deserialize)]
+pub struct LegacyMatchInfo {
+	pub match_number: u32,
+	pub match_category: MatchType,
+	pub team_number: u32,
+	pub auto: Auto,
+	pub teleop: Teleop,
+	pub speed: f32,
+	pub stability: f32,
+	pub defence: Option<f32>,
+	pub was_disabled: bool,
+	pub notes: String,
+	pub last_modified_time: u64,
+}
+
+impl From<LegacyMatchInfo> for MatchInfo {
+	fn from(legacy: LegacyMatchInfo) -> Self {
+		MatchInfo {
+			match_number: legacy.match_number,
+			match_category: legacy.match_category,
+			team_number: legacy.team_number,
+			auto: legacy.auto,
+			teleop: legacy.teleop,
+			speed: legacy.speed,
+			stability: legacy.stability,
+			defence: legacy.defence,
+			was_disabled: legacy.was_disabled,
+			notes: legacy.notes,
+			last_modified_time: legacy.last_modified_time,
+			scout: String::new(),
+		}
+	}
 }
 
 // Setting up Pit (Pit Scouting) structure
@@ -483,6 +558,33 @@ pub struct RobotInfo {
 	pub robot: Robot,
 	pub images: Vec<String>,
 	pub last_modified_time: u64,
+	// Name of whoever did the pit visit, must stay last so older saved visits can still be read
+	pub scout: String,
+}
+
+// Robot info the way it was saved before the scout's name was added
+#[derive(Debug, Clone, Deserialize)]
+pub struct LegacyRobotInfo {
+	pub visit_number: u32,
+	pub team_number: u32,
+	pub pit: Pit,
+	pub robot: Robot,
+	pub images: Vec<String>,
+	pub last_modified_time: u64,
+}
+
+impl From<LegacyRobotInfo> for RobotInfo {
+	fn from(legacy: LegacyRobotInfo) -> Self {
+		RobotInfo {
+			visit_number: legacy.visit_number,
+			team_number: legacy.team_number,
+			pit: legacy.pit,
+			robot: legacy.robot,
+			images: legacy.images,
+			last_modified_time: legacy.last_modified_time,
+			scout: String::new(),
+		}
+	}
 }
 
 // Setting up general Info
@@ -492,6 +594,8 @@ pub struct RobotInfo {
 pub enum Info {
 	MatchInfo(MatchInfo),
 	RobotInfo(RobotInfo),
+	PickList(PickList),
+	Formula(Formula),
 }
 
 impl Default for Info {
@@ -500,46 +604,16 @@ impl Default for Info {
 	}
 }
 
-// Convert match info to string
-impl MatchInfo {
-	// Arden match data export
-	pub const HEADER: &'static str = "match_number,match_category,team,auto_exited_tarmac,auto_charge_station,auto_hybrid_cube_scored,auto_hybrid_cone_scored,auto_middle_cube_scored,auto_middle_cone_scored,auto_high_cube_scored,auto_high_cone_scored,teleop_hybrid_cube_scored,teleop_hybrid_cone_scored,teleop_middle_cube_scored,teleop_middle_cone_scored,teleop_high_cube_scored,teleop_high_cone_scored,speed,stability,defence,was_disabled,notes\n";
-	pub fn write_csv_line(&self) -> String {
-		vec![
-			self.match_number.to_string(),
-			self.match_category.to_string(),
-			self.team_number.to_string(),
-			
-			self.auto.exited_tarmac.to_string(),
-			self.auto.auto_charge_station.to_string(),
-			//self.auto.cone_picked_up.to_string(),
-			//self.auto.cube_picked_up.to_string(),
-			self.auto.hybrid_cube_scored.to_string(),
-			self.auto.hybrid_cone_scored.to_string(),
-			self.auto.middle_cube_scored.to_string(),
-			self.auto.middle_cone_scored.to_string(),
-			self.auto.high_cube_scored.to_string(),
-			self.auto.high_cone_scored.to_string(),
-			
-			//self.teleop.cone_picked_up.to_string(),
-			//self.teleop.cube_picked_up.to_string(),
-			self.teleop.hybrid_cube_scored.to_string(),
-			self.teleop.hybrid_cone_scored.to_string(),
-			self.teleop.middle_cube_scored.to_string(),
-			self.teleop.middle_cone_scored.to_string(),
-			self.teleop.high_cube_scored.to_string(),
-			self.teleop.high_cone_scored.to_string(),
-			self.teleop.parked.to_string(),
-			self.teleop.teleop_charge_station.to_string(),
-			
-			self.speed.to_string(),
-			self.stability.to_string(),
-			self.defence
-				.map(|v| v.to_string())
-				.unwrap_or_else(|| "N/A".to_string()),
-			self.was_disabled.to_string(),
-			"\"".to_string() + &self.notes.replace('\n', "  ") + "\"",
-		]
-		.join(",") + "\n"
+// Read what scouts send, skipping anything that isn't a match, pit visit, pick list or formula. Matches from older
+// versions of the app don't say what type they are.
+pub fn parse_infos(values: Vec<serde_json::Value>) -> Vec<Info> {
+	let mut infos = Vec::new();
+	for value in values {
+		if let Ok(info) = serde_json::from_value::<Info>(value.clone()) {
+			infos.push(info);
+		} else if let Ok(match_info) = serde_json::from_value::<MatchInfo>(value) {
+			infos.push(Info::MatchInfo(match_info));
+		}
 	}
+	infos
 }
@@ -1,9 +1,18 @@
-use crate::data::{MatchInfo, RobotInfo};
+use crate::alliance_selection::AllianceSelection;
+use crate::analysis::AnalysisCache;
+use crate::assignments::Assignments;
+use crate::backup::{self, TreeDump};
+use crate::config;
+use crate::data::{LegacyMatchInfo, LegacyRobotInfo, MatchInfo, RobotInfo};
+use crate::formula::{self, Formula};
+use crate::images::{self, ImageEdit, ImageMeta, ImageSize};
+use crate::pick_list::{PickList, PickListEdit};
 use crate::Info;
 use std::array::TryFromSliceError;
-use std::collections::hash_map::DefaultHasher;
-use std::hash::{Hash, Hasher};
 use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError, RwLock, RwLockWriteGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -14,11 +23,20 @@ pub enum DatabaseError {
 	Serde(#[from] bincode::Error),
 	#[error("Failed to decode data in database: {0}")]
 	TryFromSlice(#[from] TryFromSliceError),
+	#[error("Failed to read or write image: {0}")]
+	Io(#[from] std::io::Error),
+	#[error("Image data doesn't match its hash {0}")]
+	ImageHash(String),
 }
 
 #[derive(Debug, Clone)]
 pub struct Database {
 	backend: sled::Db,
+	// Goes up every time something that changes the analysis is written.
+	version: Arc<AtomicU64>,
+	analysis_cache: Arc<Mutex<AnalysisCache>>,
+	// Every write holds this for reading, backups and restores hold it for writing so they see the whole database at one moment.
+	writes: Arc<RwLock<()>>,
 }
 
 pub struct MatchIter {
@@ -42,6 +60,76 @@ impl RobotIter {
 }
 
 const COMP_START: u64 = 1650480000000;
+// Matches and pit visits changed before this time were purged, so they aren't taken back when syncing.
+const PURGE_HORIZON_KEY: &[u8] = b"purge_horizon";
+
+// The current time in milliseconds, in the same format as last_modified_time.
+pub fn current_time() -> u64 {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.unwrap()
+		.as_millis() as u64
+}
+
+// Read a saved match, falling back to how matches were saved before they had a scout.
+fn decode_match(data: &[u8]) -> Result<MatchInfo, bincode::Error> {
+	bincode::deserialize::<MatchInfo>(data)
+		.or_else(|_| bincode::deserialize::<LegacyMatchInfo>(data).map(MatchInfo::from))
+}
+
+// Read a saved pit visit, falling back to how they were saved before they had a scout.
+fn decode_robot(data: &[u8]) -> Result<RobotInfo, bincode::Error> {
+	bincode::deserialize::<RobotInfo>(data)
+		.or_else(|_| bincode::deserialize::<LegacyRobotInfo>(data).map(RobotInfo::from))
+}
+
+// Read a record from the main tree to make sure it can be, going by what its key says it is. Keys that aren't known are fine.
+pub fn check_record(key: &[u8], value: &[u8]) -> Result<(), DatabaseError> {
+	if key.starts_with(b"match_") || key.starts_with(b"scout_match_") {
+		decode_match(value)?;
+	} else if key.starts_with(b"robot_") {
+		decode_robot(value)?;
+	} else if key.starts_with(b"image_meta_") {
+		bincode::deserialize::<ImageMeta>(value)?;
+	} else if key.starts_with(b"pick_list_") {
+		bincode::deserialize::<PickList>(value)?;
+	} else if key.starts_with(b"formula_") {
+		bincode::deserialize::<Formula>(value)?;
+	} else if key == b"alliance_selection" {
+		bincode::deserialize::<AllianceSelection>(value)?;
+	} else if key == b"assignments" {
+		bincode::deserialize::<Assignments>(value)?;
+	} else if key == PURGE_HORIZON_KEY {
+		bincode::deserialize::<u64>(value)?;
+	}
+	Ok(())
+}
+
+// The match, scout's copy of a match, pit visit, pick list or formula in a record from the main tree, if it's one of those.
+pub fn decode_info(key: &[u8], value: &[u8]) -> Result<Option<Info>, DatabaseError> {
+	Ok(
+		if key.starts_with(b"match_") || key.starts_with(b"scout_match_") {
+			Some(Info::MatchInfo(decode_match(value)?))
+		} else if key.starts_with(b"robot_") {
+			Some(Info::RobotInfo(decode_robot(value)?))
+		} else if key.starts_with(b"pick_list_") {
+			Some(Info::PickList(bincode::deserialize(value)?))
+		} else if key.starts_with(b"formula_") {
+			Some(Info::Formula(bincode::deserialize(value)?))
+		} else {
+			None
+		},
+	)
+}
+
+// Read saved image details from a record in the main tree, if that's what it is.
+pub fn decode_image_record(key: &[u8], value: &[u8]) -> Result<Option<ImageMeta>, DatabaseError> {
+	if key.starts_with(b"image_meta_") {
+		Ok(Some(bincode::deserialize::<ImageMeta>(value)?))
+	} else {
+		Ok(None)
+	}
+}
 
 // Check whether the given match is a valid match (whether it was scouted after the start of the competition).
 fn is_match_valid(match_info: &MatchInfo) -> bool {
@@ -62,10 +150,14 @@ impl Iterator for MatchIter {
 
 	fn next(&mut self) -> Option<Self::Item> {
 		for next in self.inner.by_ref() {
-			let (_key, value) = next.unwrap();
-			let value: MatchInfo = bincode::deserialize(&value).unwrap();
-			if is_match_valid(&value) {
-				return Some(Ok(value));
+			let value = match next {
+				Ok((_key, value)) => decode_match(&value),
+				Err(e) => return Some(Err(e.into())),
+			};
+			match value {
+				Ok(value) if is_match_valid(&value) => return Some(Ok(value)),
+				Ok(_) => {}
+				Err(e) => return Some(Err(e.into())),
 			}
 		}
 		None
@@ -77,40 +169,77 @@ impl Iterator for RobotIter {
 
 	fn next(&mut self) -> Option<Self::Item> {
 		for next in self.inner.by_ref() {
-			let (_key, value) = next.unwrap();
-			let value: RobotInfo = bincode::deserialize(&value).unwrap();
-			if is_robot_valid(&value) {
-				return Some(Ok(value));
+			let value = match next {
+				Ok((_key, value)) => decode_robot(&value),
+				Err(e) => return Some(Err(e.into())),
+			};
+			match value {
+				Ok(value) if is_robot_valid(&value) => return Some(Ok(value)),
+				Ok(_) => {}
+				Err(e) => return Some(Err(e.into())),
 			}
 		}
 		None
 	}
 }
 
-fn fix_images(info: &RobotInfo) -> RobotInfo {
-	let mut info = info.clone();
-	std::fs::create_dir_all("images").unwrap();
-	for image in &mut info.images {
-		if &image[0..5] == "data:" {
-			let mut hasher = DefaultHasher::default();
-			image.hash(&mut hasher);
-			let id = hasher.finish();
-			let data = image.split(',').nth(1).unwrap();
-			let data = base64::decode(data).unwrap();
-			println!("Saving image {} with {} bytes of data.", id, data.len());
-			std::fs::write(&format!("images/bot-{}.jpeg", id,), data).unwrap();
-			*image = format!("{{AUTOSCOUT_URL}}/api/img?id={}", id);
-		}
-	}
-	info
-}
-
 impl Database {
 	pub fn open(file: &Path) -> Self {
 		Database {
 			backend: sled::open(file).unwrap(),
+			version: Arc::new(AtomicU64::new(1)),
+			analysis_cache: Arc::new(Mutex::new(AnalysisCache::default())),
+			writes: Arc::new(RwLock::new(())),
 		}
 	}
+	pub fn version(&self) -> u64 {
+		self.version.load(Ordering::SeqCst)
+	}
+	fn changed(&self) {
+		self.version.fetch_add(1, Ordering::SeqCst);
+	}
+	pub fn analysis_cache(&self) -> &Mutex<AnalysisCache> {
+		&self.analysis_cache
+	}
+	// Nothing can be written while this is held. Writes wait for it instead of failing.
+	pub fn pause_writes(&self) -> RwLockWriteGuard<'_, ()> {
+		self.writes.write().unwrap_or_else(PoisonError::into_inner)
+	}
+	// All writes go through these, so they can be paused. Each one is a single step, so they never wait on each other.
+	fn insert(
+		&self,
+		key: impl AsRef<[u8]>,
+		value: impl Into<sled::IVec>,
+	) -> Result<(), DatabaseError> {
+		let _writing = self.writes.read().unwrap_or_else(PoisonError::into_inner);
+		self.backend.insert(key, value)?;
+		Ok(())
+	}
+	fn remove(&self, key: impl AsRef<[u8]>) -> Result<(), DatabaseError> {
+		let _writing = self.writes.read().unwrap_or_else(PoisonError::into_inner);
+		self.backend.remove(key)?;
+		Ok(())
+	}
+	// Save a record only if there isn't one under its key yet, returning whether it was saved.
+	pub fn insert_if_missing(&self, key: &[u8], value: &[u8]) -> Result<bool, DatabaseError> {
+		let _writing = self.writes.read().unwrap_or_else(PoisonError::into_inner);
+		let inserted = self
+			.backend
+			.compare_and_swap(key, None as Option<&[u8]>, Some(value))?
+			.is_ok();
+		if inserted {
+			self.changed();
+		}
+		Ok(inserted)
+	}
+	fn update_and_fetch(
+		&self,
+		key: impl AsRef<[u8]>,
+		update: impl FnMut(Option<&[u8]>) -> Option<Vec<u8>>,
+	) -> Result<Option<sled::IVec>, DatabaseError> {
+		let _writing = self.writes.read().unwrap_or_else(PoisonError::into_inner);
+		Ok(self.backend.update_and_fetch(key, update)?)
+	}
 	pub fn get_match_id(match_info: &MatchInfo) -> Vec<u8> {
 		Vec::from(format!(
 			"match_{}_{:?}_{}",
@@ -123,13 +252,40 @@ impl Database {
 			robot_info.team_number, robot_info.visit_number,
 		))
 	}
+	// Every scout's own copy of a match is kept too, so that scouts watching the same robot can be compared.
+	pub fn get_scout_match_id(match_info: &MatchInfo) -> Vec<u8> {
+		Vec::from(format!(
+			"scout_match_{}_{:?}_{}_{}",
+			match_info.match_number,
+			match_info.match_category,
+			match_info.team_number,
+			match_info.scout,
+		))
+	}
+	fn write_scout_match(&self, match_info: &MatchInfo) -> Result<(), DatabaseError> {
+		let id = Self::get_scout_match_id(match_info);
+		if let Some(data) = self.backend.get(&id)? {
+			if let Ok(old_match_info) = decode_match(&data) {
+				if old_match_info.last_modified_time >= match_info.last_modified_time {
+					return Ok(());
+				}
+			}
+		}
+		self.insert(id, bincode::serialize(match_info)?)?;
+		// Analyses that leave scouts out use these copies.
+		self.changed();
+		Ok(())
+	}
 	pub fn write_match(&self, match_info: &MatchInfo) -> Result<(), DatabaseError> {
-		if !is_match_valid(match_info) {
+		if !is_match_valid(match_info) || match_info.last_modified_time < self.purge_horizon()? {
 			return Ok(());
 		}
+		if !match_info.scout.is_empty() {
+			self.write_scout_match(match_info)?;
+		}
 		let id = Self::get_match_id(match_info);
 		if let Some(data) = self.backend.get(&id)? {
-			if let Ok(old_match_info) = bincode::deserialize::<MatchInfo>(&data) {
+			if let Ok(old_match_info) = decode_match(&data) {
 				if old_match_info.last_modified_time >= match_info.last_modified_time {
 					// Don't replace newer things.
 					return Ok(());
@@ -137,35 +293,446 @@ impl Database {
 			}
 		}
 		let data = bincode::serialize(match_info)?;
-		self.backend.insert(id, data)?;
+		self.insert(id, data)?;
+		self.changed();
 		Ok(())
 	}
 	pub fn write_robot(&self, robot_info: &RobotInfo) -> Result<(), DatabaseError> {
-		if !is_robot_valid(robot_info) {
+		if !is_robot_valid(robot_info) || robot_info.last_modified_time < self.purge_horizon()? {
 			return Ok(());
 		}
 		let id = Self::get_robot_id(robot_info);
 		if let Some(data) = self.backend.get(&id)? {
-			if let Ok(old_robot_info) = bincode::deserialize::<RobotInfo>(&data) {
+			if let Ok(old_robot_info) = decode_robot(&data) {
 				if old_robot_info.last_modified_time >= robot_info.last_modified_time {
 					// Don't replace newer things.
 					return Ok(());
 				}
 			}
 		}
-		let data = bincode::serialize(&fix_images(robot_info))?;
-		self.backend.insert(id, data)?;
+		let data = bincode::serialize(&self.store_images(robot_info))?;
+		self.insert(id, data)?;
+		self.changed();
+		Ok(())
+	}
+	// Move any images sent as data URLs into the image store, and link to them instead. Images that can't be read are dropped.
+	fn store_images(&self, info: &RobotInfo) -> RobotInfo {
+		let mut info = info.clone();
+		let mut images = Vec::new();
+		for image in info.images.iter() {
+			if !image.starts_with("data:") {
+				images.push(image.clone());
+				continue;
+			}
+			let stored = images::decode_data_url(image)
+				.map_err(|e| e.to_string())
+				.and_then(|(data, mime)| {
+					self.write_image(&data, mime, &info)
+						.map_err(|e| e.to_string())
+				});
+			match stored {
+				Ok(hash) if !images.contains(&images::image_url(&hash)) => {
+					images.push(images::image_url(&hash))
+				}
+				Ok(_) => {}
+				Err(e) => eprintln!("Dropping image for team {}: {}", info.team_number, e),
+			}
+		}
+		info.images = images;
+		info
+	}
+	fn get_image_meta_id(hash: &str) -> Vec<u8> {
+		Vec::from(format!("image_meta_{}", hash))
+	}
+	fn get_image_data_id(name: &str) -> Vec<u8> {
+		Vec::from(format!("image_data_{}", name))
+	}
+	// Images and their thumbnails go in the image directory if there is one, otherwise in the database.
+	fn write_image_file(&self, name: &str, data: &[u8]) -> Result<(), DatabaseError> {
+		match &config::get_config().image_directory {
+			Some(directory) => {
+				let _writing = self.writes.read().unwrap_or_else(PoisonError::into_inner);
+				std::fs::create_dir_all(directory)?;
+				std::fs::write(directory.join(name), data)?;
+			}
+			None => {
+				self.insert(Self::get_image_data_id(name), data)?;
+			}
+		}
+		Ok(())
+	}
+	fn remove_image_file(&self, name: &str) -> Result<(), DatabaseError> {
+		let _writing = self.writes.read().unwrap_or_else(PoisonError::into_inner);
+		self.backend.remove(Self::get_image_data_id(name))?;
+		if let Some(directory) = &config::get_config().image_directory {
+			match std::fs::remove_file(directory.join(name)) {
+				Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+				_ => {}
+			}
+		}
+		Ok(())
+	}
+	fn read_image_file(&self, name: &str) -> Result<Option<Vec<u8>>, DatabaseError> {
+		if let Some(data) = self.backend.get(Self::get_image_data_id(name))? {
+			return Ok(Some(data.to_vec()));
+		}
+		match &config::get_config().image_directory {
+			Some(directory) => match std::fs::read(directory.join(name)) {
+				Ok(data) => Ok(Some(data)),
+				Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+				Err(e) => Err(e.into()),
+			},
+			None => Ok(None),
+		}
+	}
+	fn thumbnail_name(hash: &str, size: ImageSize) -> String {
+		format!("{}_{}", hash, size.name())
+	}
+	fn write_thumbnail(&self, hash: &str, data: &[u8], size: ImageSize) -> Option<Vec<u8>> {
+		let thumbnail = match images::make_thumbnail(data, size) {
+			Ok(thumbnail) => thumbnail,
+			Err(e) => {
+				eprintln!("No thumbnail for image {}: {}", hash, e);
+				return None;
+			}
+		};
+		if let Err(e) = self.write_image_file(&Self::thumbnail_name(hash, size), &thumbnail) {
+			eprintln!("Couldn't save thumbnail for image {}: {}", hash, e);
+		}
+		Some(thumbnail)
+	}
+	// Save an image under its hash along with its thumbnails, unless it's already there. Returns the hash.
+	pub fn write_image(
+		&self,
+		data: &[u8],
+		mime: &str,
+		robot_info: &RobotInfo,
+	) -> Result<String, DatabaseError> {
+		let hash = images::hash_image(data);
+		self.write_image_with_meta(
+			&ImageMeta {
+				hash: hash.clone(),
+				mime: mime.to_string(),
+				size: data.len() as u64,
+				team_number: robot_info.team_number,
+				uploader: robot_info.scout.clone(),
+				time: robot_info.last_modified_time,
+				caption: String::new(),
+				tags: Vec::new(),
+				preferred: false,
+				deleted: false,
+				last_modified_time: robot_info.last_modified_time,
+			},
+			data,
+		)?;
+		Ok(hash)
+	}
+	// Save an image that already has its details, like one from another server. The hash has to match the data.
+	pub fn write_image_with_meta(
+		&self,
+		meta: &ImageMeta,
+		data: &[u8],
+	) -> Result<(), DatabaseError> {
+		if images::hash_image(data) != meta.hash {
+			return Err(DatabaseError::ImageHash(meta.hash.clone()));
+		}
+		if meta.deleted {
+			return self.merge_image_meta(meta);
+		}
+		let old_meta = self.get_image_meta(&meta.hash)?;
+		let newer_here = old_meta
+			.as_ref()
+			.is_some_and(|old_meta| old_meta.last_modified_time >= meta.last_modified_time);
+		if newer_here && old_meta.as_ref().is_some_and(|old_meta| old_meta.deleted) {
+			return Ok(());
+		}
+		// Always written, since the file is gone if the image was deleted before.
+		self.write_image_file(&meta.hash, data)?;
+		for size in ImageSize::THUMBNAILS.iter() {
+			if self.write_thumbnail(&meta.hash, data, *size).is_none() {
+				break;
+			}
+		}
+		if !newer_here {
+			self.insert(
+				Self::get_image_meta_id(&meta.hash),
+				bincode::serialize(meta)?,
+			)?;
+		}
+		println!(
+			"Saved image {} with {} bytes of data.",
+			meta.hash,
+			data.len()
+		);
+		Ok(())
+	}
+	// Keep whichever version of an image's details was changed last. Deleting an image gets rid of the image itself.
+	pub fn merge_image_meta(&self, meta: &ImageMeta) -> Result<(), DatabaseError> {
+		match self.get_image_meta(&meta.hash)? {
+			// Don't replace newer things.
+			Some(old_meta) if old_meta.last_modified_time >= meta.last_modified_time => {
+				return Ok(())
+			}
+			// The details aren't any use without the image, unless it's been deleted.
+			None if !meta.deleted => return Ok(()),
+			// The file is gone, so the image can only come back along with its data.
+			Some(old_meta) if old_meta.deleted && !meta.deleted => return Ok(()),
+			_ => {}
+		}
+		if meta.deleted {
+			self.remove_image_file(&meta.hash)?;
+			for size in ImageSize::THUMBNAILS.iter() {
+				self.remove_image_file(&Self::thumbnail_name(&meta.hash, *size))?;
+			}
+		}
+		self.insert(
+			Self::get_image_meta_id(&meta.hash),
+			bincode::serialize(meta)?,
+		)?;
+		Ok(())
+	}
+	// Change an image's details from the API. Marking an image as preferred takes it away from the team's other images.
+	pub fn edit_image(&self, edit: &ImageEdit) -> Result<Option<ImageMeta>, DatabaseError> {
+		let mut meta = match self.get_image_meta(&edit.id)? {
+			Some(meta) if !meta.deleted => meta,
+			_ => return Ok(None),
+		};
+		let time = current_time();
+		edit.apply(&mut meta, time);
+		if meta.preferred {
+			for mut other in self.get_all_image_meta()? {
+				if other.team_number == meta.team_number
+					&& other.preferred
+					&& other.hash != meta.hash
+				{
+					other.preferred = false;
+					other.last_modified_time = time;
+					self.merge_image_meta(&other)?;
+				}
+			}
+		}
+		self.merge_image_meta(&meta)?;
+		Ok(Some(meta))
+	}
+	pub fn get_all_image_meta(&self) -> Result<Vec<ImageMeta>, DatabaseError> {
+		let mut all_meta = Vec::new();
+		for next in self.backend.scan_prefix(b"image_meta_") {
+			let (_key, value) = next?;
+			all_meta.push(bincode::deserialize(&value)?);
+		}
+		Ok(all_meta)
+	}
+	// A team's images that haven't been deleted, with the preferred one first and then the newest.
+	pub fn get_team_image_meta(&self, team_number: u32) -> Result<Vec<ImageMeta>, DatabaseError> {
+		let mut team_meta = self
+			.get_all_image_meta()?
+			.into_iter()
+			.filter(|meta| meta.team_number == team_number && !meta.deleted)
+			.collect::<Vec<_>>();
+		team_meta.sort_by_key(|meta| (!meta.preferred, std::cmp::Reverse(meta.time)));
+		Ok(team_meta)
+	}
+	pub fn get_image_meta(&self, hash: &str) -> Result<Option<ImageMeta>, DatabaseError> {
+		match self.backend.get(Self::get_image_meta_id(hash))? {
+			Some(data) => Ok(Some(bincode::deserialize(&data)?)),
+			None => Ok(None),
+		}
+	}
+	// An image and its MIME type. Thumbnails missing from images saved before they existed are made now,
+	// and the original is sent instead for images that can't have one.
+	pub fn get_image(
+		&self,
+		hash: &str,
+		size: ImageSize,
+	) -> Result<Option<(String, Vec<u8>)>, DatabaseError> {
+		let meta = match self.get_image_meta(hash)? {
+			Some(meta) if !meta.deleted => meta,
+			_ => return Ok(None),
+		};
+		if size != ImageSize::Original {
+			if let Some(thumbnail) = self.read_image_file(&Self::thumbnail_name(hash, size))? {
+				return Ok(Some(("image/jpeg".to_string(), thumbnail)));
+			}
+		}
+		let data = match self.read_image_file(hash)? {
+			Some(data) => data,
+			None => return Ok(None),
+		};
+		if size != ImageSize::Original {
+			if let Some(thumbnail) = self.write_thumbnail(hash, &data, size) {
+				return Ok(Some(("image/jpeg".to_string(), thumbnail)));
+			}
+		}
+		Ok(Some((meta.mime, data)))
+	}
+	pub fn get_pick_list_id(name: &str) -> Vec<u8> {
+		Vec::from(format!("pick_list_{}", name))
+	}
+	// Change a pick list in place, this is atomic so several people can edit the same list at once.
+	fn update_pick_list(
+		&self,
+		name: &str,
+		update: impl Fn(&mut PickList),
+	) -> Result<PickList, DatabaseError> {
+		let data = self
+			.update_and_fetch(Self::get_pick_list_id(name), |old| {
+				let mut pick_list = old
+					.and_then(|data| bincode::deserialize::<PickList>(data).ok())
+					.unwrap_or_else(|| PickList::new(name));
+				update(&mut pick_list);
+				Some(bincode::serialize(&pick_list).unwrap())
+			})?
+			.unwrap();
+		Ok(bincode::deserialize(&data)?)
+	}
+	pub fn write_pick_list(&self, pick_list: &PickList) -> Result<(), DatabaseError> {
+		self.update_pick_list(&pick_list.name, |stored| stored.merge(pick_list))?;
+		Ok(())
+	}
+	pub fn edit_pick_list(
+		&self,
+		name: &str,
+		edits: &[PickListEdit],
+	) -> Result<PickList, DatabaseError> {
+		let time = current_time();
+		self.update_pick_list(name, |pick_list| {
+			for edit in edits {
+				pick_list.apply(edit, time);
+			}
+		})
+	}
+	pub fn get_all_pick_lists(&self) -> Result<Vec<PickList>, DatabaseError> {
+		let mut pick_lists = Vec::new();
+		for next in self.backend.scan_prefix(b"pick_list_") {
+			let (_key, value) = next?;
+			pick_lists.push(bincode::deserialize(&value)?);
+		}
+		Ok(pick_lists)
+	}
+	pub fn get_formula_id(name: &str) -> Vec<u8> {
+		Vec::from(format!("formula_{}", name))
+	}
+	pub fn write_formula(&self, formula: &Formula) -> Result<(), DatabaseError> {
+		let data = bincode::serialize(formula)?;
+		self.insert(Self::get_formula_id(&formula.name), data)?;
+		self.changed();
+		Ok(())
+	}
+	// Keep whichever of the saved formula and this one was changed last, skipping formulas that don't parse.
+	pub fn merge_formula(&self, formula: &Formula) -> Result<(), DatabaseError> {
+		if !formula.deleted && formula::parse(&formula.expression).is_err() {
+			return Ok(());
+		}
+		let data = bincode::serialize(formula)?;
+		// The closure can run more than once, so this is whatever the last run decided.
+		let mut replaced = false;
+		self.update_and_fetch(Self::get_formula_id(&formula.name), |old| {
+			// Don't replace newer things.
+			let newer = old
+				.and_then(|old| bincode::deserialize::<Formula>(old).ok())
+				.is_some_and(|old| old.last_modified_time >= formula.last_modified_time);
+			replaced = !newer;
+			if newer {
+				old.map(|old| old.to_vec())
+			} else {
+				Some(data.clone())
+			}
+		})?;
+		if replaced {
+			self.changed();
+		}
+		Ok(())
+	}
+	// Formulas are marked as deleted rather than removed, so other servers find out about it when syncing.
+	pub fn delete_formula(&self, name: &str) -> Result<bool, DatabaseError> {
+		let formula = match self.get_formula(name)? {
+			Some(formula) if !formula.deleted => formula,
+			_ => return Ok(false),
+		};
+		self.write_formula(&Formula {
+			last_modified_time: current_time(),
+			deleted: true,
+			..formula
+		})?;
+		Ok(true)
+	}
+	fn get_formula(&self, name: &str) -> Result<Option<Formula>, DatabaseError> {
+		match self.backend.get(Self::get_formula_id(name))? {
+			Some(data) => Ok(Some(bincode::deserialize(&data)?)),
+			None => Ok(None),
+		}
+	}
+	// Every formula that hasn't been deleted.
+	pub fn get_all_formulas(&self) -> Result<Vec<Formula>, DatabaseError> {
+		Ok(self
+			.get_formula_records()?
+			.into_iter()
+			.filter(|f| !f.deleted)
+			.collect())
+	}
+	// Every formula including deleted ones, for syncing.
+	fn get_formula_records(&self) -> Result<Vec<Formula>, DatabaseError> {
+		let mut formulas = Vec::new();
+		for next in self.backend.scan_prefix(b"formula_") {
+			let (_key, value) = next?;
+			formulas.push(bincode::deserialize(&value)?);
+		}
+		Ok(formulas)
+	}
+	pub fn get_alliance_selection(&self) -> Result<Option<AllianceSelection>, DatabaseError> {
+		match self.backend.get(b"alliance_selection")? {
+			Some(data) => Ok(Some(bincode::deserialize(&data)?)),
+			None => Ok(None),
+		}
+	}
+	// Change the alliance selection in one step, so two people recording picks at once can't undo each other's.
+	// Nothing is saved if the update returns false.
+	pub fn update_alliance_selection(
+		&self,
+		mut update: impl FnMut(&mut AllianceSelection) -> bool,
+	) -> Result<(), DatabaseError> {
+		self.update_and_fetch(b"alliance_selection", |old| {
+			let mut selection = old
+				.and_then(|data| bincode::deserialize::<AllianceSelection>(data).ok())
+				.unwrap_or_default();
+			if update(&mut selection) {
+				Some(bincode::serialize(&selection).unwrap())
+			} else {
+				old.map(|data| data.to_vec())
+			}
+		})?;
+		Ok(())
+	}
+	pub fn get_assignments(&self) -> Result<Option<Assignments>, DatabaseError> {
+		match self.backend.get(b"assignments")? {
+			Some(data) => Ok(Some(bincode::deserialize(&data)?)),
+			None => Ok(None),
+		}
+	}
+	pub fn write_assignments(&self, assignments: &Assignments) -> Result<(), DatabaseError> {
+		let data = bincode::serialize(assignments)?;
+		self.insert(b"assignments", data)?;
 		Ok(())
 	}
 	pub fn write_info(&self, info: &Info) -> Result<(), DatabaseError> {
 		match info {
 			Info::MatchInfo(match_info) => self.write_match(match_info),
 			Info::RobotInfo(robot_info) => self.write_robot(robot_info),
+			Info::PickList(pick_list) => self.write_pick_list(pick_list),
+			Info::Formula(formula) => self.merge_formula(formula),
 		}
 	}
 	pub fn get_all_matches(&self) -> MatchIter {
 		MatchIter::from_sled(self.backend.scan_prefix(b"match_"))
 	}
+	pub fn get_all_scout_matches(&self) -> Result<Vec<MatchInfo>, DatabaseError> {
+		let mut matches = Vec::new();
+		for next in self.backend.scan_prefix(b"scout_match_") {
+			let (_key, value) = next?;
+			matches.push(decode_match(&value)?);
+		}
+		Ok(matches)
+	}
 	pub fn get_all_robots(&self) -> RobotIter {
 		RobotIter::from_sled(self.backend.scan_prefix(b"robot_"))
 	}
@@ -175,13 +742,189 @@ impl Database {
 		}
 		Ok(())
 	}
-	pub fn get_info_list(&self) -> Vec<Info> {
-		self.get_all_matches()
-			.map(|data| Info::MatchInfo(data.unwrap()))
-			.chain(
-				self.get_all_robots()
-					.map(|data| Info::RobotInfo(data.unwrap())),
-			)
-			.collect()
+	// Every tree with all of its keys and values, for backups. Writes should be paused, so the trees all match.
+	pub fn dump_trees(&self) -> Result<Vec<TreeDump>, DatabaseError> {
+		self.backend.flush()?;
+		let mut trees = Vec::new();
+		for name in self.backend.tree_names() {
+			let tree = self.backend.open_tree(&name)?;
+			let mut entries = Vec::new();
+			for next in tree.iter() {
+				let (key, value) = next?;
+				entries.push((key.to_vec(), value.to_vec()));
+			}
+			trees.push(TreeDump {
+				name: name.to_vec(),
+				entries,
+			});
+		}
+		Ok(trees)
+	}
+	// Throw away everything in the database and the image directory and put these trees and images in their place,
+	// for restoring backups. The images are written next to the image directory first, and each tree is changed in
+	// one batch, so nothing is lost if this fails partway. Everything the server saves is in the default tree.
+	pub fn replace_trees(
+		&self,
+		trees: &[TreeDump],
+		images: &[(String, Vec<u8>)],
+	) -> Result<(), DatabaseError> {
+		let image_directory = config::get_config().image_directory.as_deref();
+		let staged = match image_directory {
+			Some(directory) => Some(backup::stage_directory(directory, images)?),
+			None => None,
+		};
+		let _paused = self.pause_writes();
+		let result = self.replace_trees_paused(trees, if staged.is_some() { &[] } else { images });
+		if let (Some(directory), Some(staged)) = (image_directory, staged) {
+			match &result {
+				Ok(()) => backup::swap_directory(directory, &staged)?,
+				Err(_) => {
+					let _ = std::fs::remove_dir_all(staged);
+				}
+			}
+		}
+		result?;
+		self.changed();
+		Ok(())
+	}
+	fn replace_trees_paused(
+		&self,
+		trees: &[TreeDump],
+		images: &[(String, Vec<u8>)],
+	) -> Result<(), DatabaseError> {
+		let mut batches = Vec::new();
+		for name in self.backend.tree_names() {
+			if !trees.iter().any(|dump| *dump.name == *name) {
+				batches.push((name.to_vec(), self.replace_tree_batch(&name, &[])?));
+			}
+		}
+		for dump in trees {
+			let mut entries = dump.entries.clone();
+			if *dump.name == *b"__sled__default" {
+				entries.extend(
+					images
+						.iter()
+						.map(|(name, data)| (Self::get_image_data_id(name), data.clone())),
+				);
+			}
+			batches.push((
+				dump.name.clone(),
+				self.replace_tree_batch(&dump.name, &entries)?,
+			));
+		}
+		for (name, batch) in batches {
+			self.backend.open_tree(name)?.apply_batch(batch)?;
+		}
+		self.backend.flush()?;
+		Ok(())
+	}
+	// One batch that leaves the tree with exactly these entries.
+	fn replace_tree_batch(
+		&self,
+		name: &[u8],
+		entries: &[(Vec<u8>, Vec<u8>)],
+	) -> Result<sled::Batch, DatabaseError> {
+		let keep = entries
+			.iter()
+			.map(|(key, _)| key.as_slice())
+			.collect::<std::collections::HashSet<_>>();
+		let mut batch = sled::Batch::default();
+		for next in self.backend.open_tree(name)?.iter().keys() {
+			let key = next?;
+			if !keep.contains(&*key) {
+				batch.remove(key);
+			}
+		}
+		for (key, value) in entries {
+			batch.insert(key.as_slice(), value.as_slice());
+		}
+		Ok(batch)
+	}
+	// Every record that can't be read, or that the server would skip, along with what's wrong with it.
+	pub fn validate_records(&self) -> Result<Vec<(String, String)>, DatabaseError> {
+		let mut problems = Vec::new();
+		for name in self.backend.tree_names() {
+			for next in self.backend.open_tree(&name)?.iter() {
+				let (key, value) = next?;
+				let name = String::from_utf8_lossy(&key).into_owned();
+				if let Err(e) = check_record(&key, &value) {
+					problems.push((name, e.to_string()));
+				} else if key.starts_with(b"match_") && !is_match_valid(&decode_match(&value)?) {
+					problems.push((
+						name,
+						"Match is from before the competition or has no team or number."
+							.to_string(),
+					));
+				} else if key.starts_with(b"robot_") && !is_robot_valid(&decode_robot(&value)?) {
+					problems.push((
+						name,
+						"Pit visit is from before the competition or has no team.".to_string(),
+					));
+				}
+			}
+		}
+		Ok(problems)
+	}
+	fn purge_horizon(&self) -> Result<u64, DatabaseError> {
+		match self.backend.get(PURGE_HORIZON_KEY)? {
+			Some(data) => Ok(bincode::deserialize(&data)?),
+			None => Ok(0),
+		}
+	}
+	// Delete matches and pit visits last changed before the given time, returning how many there were.
+	// Nothing is deleted on a dry run. Records that can't be read are left for validate_records to report.
+	// Older records are refused from then on, so syncing doesn't bring them back, but other servers keep
+	// their own copies until they're purged there too.
+	pub fn purge_before(&self, time: u64, dry_run: bool) -> Result<usize, DatabaseError> {
+		let mut purged = Vec::new();
+		for prefix in [&b"match_"[..], b"scout_match_", b"robot_"] {
+			for next in self.backend.scan_prefix(prefix) {
+				let (key, value) = next?;
+				let last_modified_time = if prefix == b"robot_" {
+					decode_robot(&value).map(|r| r.last_modified_time)
+				} else {
+					decode_match(&value).map(|m| m.last_modified_time)
+				};
+				if matches!(last_modified_time, Ok(t) if t < time) {
+					purged.push(key);
+				}
+			}
+		}
+		if !dry_run {
+			self.update_and_fetch(PURGE_HORIZON_KEY, |old| {
+				let old = old
+					.and_then(|data| bincode::deserialize::<u64>(data).ok())
+					.unwrap_or(0);
+				Some(bincode::serialize(&old.max(time)).unwrap())
+			})?;
+			for key in purged.iter() {
+				self.remove(key)?;
+			}
+			self.backend.flush()?;
+			self.changed();
+		}
+		Ok(purged.len())
+	}
+	pub fn size_on_disk(&self) -> Result<u64, DatabaseError> {
+		Ok(self.backend.size_on_disk()?)
+	}
+	// Everything that's synced between servers. Each scout's copy of a match is sent as well, so scout stats match everywhere.
+	pub fn get_info_list(&self) -> Result<Vec<Info>, DatabaseError> {
+		let mut infos = Vec::new();
+		for match_info in self.get_all_matches() {
+			infos.push(Info::MatchInfo(match_info?));
+		}
+		infos.extend(
+			self.get_all_scout_matches()?
+				.into_iter()
+				.filter(is_match_valid)
+				.map(Info::MatchInfo),
+		);
+		for robot_info in self.get_all_robots() {
+			infos.push(Info::RobotInfo(robot_info?));
+		}
+		infos.extend(self.get_all_pick_lists()?.into_iter().map(Info::PickList));
+		infos.extend(self.get_formula_records()?.into_iter().map(Info::Formula));
+		Ok(infos)
 	}
 }
@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::analysis::TeamInfo;
+use crate::season::{
+	ACTIVATION_THRESHOLD, AUTO_DOCKED_POINTS, AUTO_ENGAGED_POINTS, PARK_POINTS,
+	TELEOP_DOCKED_POINTS, TELEOP_ENGAGED_POINTS,
+};
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RobotEndgame {
+	pub team_number: u32,
+	pub auto_samples: u32,
+	pub samples: u32,
+	pub park_probability: f32,
+	pub dock_probability: f32,
+	pub engage_rate_when_docked: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChargeStationState {
+	None,
+	Docked,
+	Engaged,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AutoOutcome {
+	pub state: ChargeStationState,
+	pub probability: f32,
+	pub points: f32,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EndgameOutcome {
+	// Number of robots on the charge station.
+	pub docked: usize,
+	pub state: ChargeStationState,
+	pub probability: f32,
+	pub points: f32,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AllianceEndgame {
+	pub teams: Vec<u32>,
+	// Teams that haven't been scouted yet, these are estimated using the average team.
+	pub unscouted_teams: Vec<u32>,
+	pub robots: Vec<RobotEndgame>,
+	// Only one robot can usually fit on the charge station in auto, so it's the one most likely to score the most there.
+	pub auto_team: Option<u32>,
+	pub auto: Vec<AutoOutcome>,
+	pub endgame: Vec<EndgameOutcome>,
+	pub expected_charge_station_points: f32,
+	pub expected_park_points: f32,
+	pub activation_bonus_probability: f32,
+}
+
+fn auto_points(team: &TeamInfo) -> f32 {
+	team.charge_station_auto_on * AUTO_DOCKED_POINTS
+		+ team.charge_station_auto_charged * AUTO_ENGAGED_POINTS
+}
+
+fn auto_outcomes(team: Option<&TeamInfo>) -> Vec<AutoOutcome> {
+	let (docked, engaged) = team
+		.map(|t| (t.charge_station_auto_on, t.charge_station_auto_charged))
+		.unwrap_or((0.0, 0.0));
+	vec![
+		AutoOutcome {
+			state: ChargeStationState::None,
+			probability: (1.0 - docked - engaged).max(0.0),
+			points: 0.0,
+		},
+		AutoOutcome {
+			state: ChargeStationState::Docked,
+			probability: docked,
+			points: AUTO_DOCKED_POINTS,
+		},
+		AutoOutcome {
+			state: ChargeStationState::Engaged,
+			probability: engaged,
+			points: AUTO_ENGAGED_POINTS,
+		},
+	]
+}
+
+// Go through every combination of robots that dock at the end. The charge station is engaged when every robot on it does its part,
+// so the chance of that is every docked robot's engage rate multiplied together. Robots are treated as independent.
+fn endgame_outcomes(robots: &[RobotEndgame]) -> Vec<EndgameOutcome> {
+	let mut probabilities = HashMap::new();
+	for docked_robots in 0..(1usize << robots.len()) {
+		let mut probability = 1.0;
+		let mut engage_probability = 1.0;
+		for (i, robot) in robots.iter().enumerate() {
+			if docked_robots & (1 << i) != 0 {
+				probability *= robot.dock_probability;
+				engage_probability *= robot.engage_rate_when_docked;
+			} else {
+				probability *= 1.0 - robot.dock_probability;
+			}
+		}
+		let docked = docked_robots.count_ones() as usize;
+		if docked == 0 {
+			*probabilities
+				.entry((docked, ChargeStationState::None))
+				.or_insert(0.0) += probability;
+		} else {
+			*probabilities
+				.entry((docked, ChargeStationState::Engaged))
+				.or_insert(0.0) += probability * engage_probability;
+			*probabilities
+				.entry((docked, ChargeStationState::Docked))
+				.or_insert(0.0) += probability * (1.0 - engage_probability);
+		}
+	}
+	let mut outcomes = probabilities
+		.into_iter()
+		.map(|((docked, state), probability)| EndgameOutcome {
+			docked,
+			state,
+			probability,
+			points: docked as f32
+				* if state == ChargeStationState::Engaged {
+					TELEOP_ENGAGED_POINTS
+				} else {
+					TELEOP_DOCKED_POINTS
+				},
+		})
+		.collect::<Vec<_>>();
+	outcomes.sort_by(|a, b| a.points.total_cmp(&b.points));
+	outcomes
+}
+
+// Chances of an alliance getting each charge station result, in auto and at the end of the match.
+pub fn analyze_endgame(teams: &HashMap<u32, TeamInfo>, alliance: &[u32]) -> AllianceEndgame {
+	let default_team = TeamInfo::default();
+	let average_team = teams.get(&0).unwrap_or(&default_team);
+	let mut endgame = AllianceEndgame {
+		teams: alliance.to_vec(),
+		..AllianceEndgame::default()
+	};
+	let mut members = Vec::new();
+	for team_number in alliance {
+		let team = match teams.get(team_number) {
+			Some(team) if *team_number != 0 => team,
+			_ => {
+				endgame.unscouted_teams.push(*team_number);
+				average_team
+			}
+		};
+		members.push((*team_number, team));
+		endgame.robots.push(RobotEndgame {
+			team_number: *team_number,
+			auto_samples: team.auto_charge_station_samples,
+			samples: team.endgame_samples,
+			park_probability: team.charge_station_teleop_parked,
+			dock_probability: team.endgame_docked_rate,
+			engage_rate_when_docked: team.endgame_engage_rate_when_docked,
+		});
+		endgame.expected_park_points += team.charge_station_teleop_parked * PARK_POINTS;
+	}
+
+	let auto_team = members
+		.iter()
+		.filter(|(_, team)| auto_points(team) > 0.0)
+		.fold(None, |best: Option<&(u32, &TeamInfo)>, next| match best {
+			Some(best) if auto_points(best.1) >= auto_points(next.1) => Some(best),
+			_ => Some(next),
+		});
+	endgame.auto_team = auto_team.map(|(team_number, _)| *team_number);
+	endgame.auto = auto_outcomes(auto_team.map(|(_, team)| *team));
+	endgame.endgame = endgame_outcomes(&endgame.robots);
+
+	for auto in endgame.auto.iter() {
+		endgame.expected_charge_station_points += auto.probability * auto.points;
+		for end in endgame.endgame.iter() {
+			if auto.points + end.points >= ACTIVATION_THRESHOLD {
+				endgame.activation_bonus_probability += auto.probability * end.probability;
+			}
+		}
+	}
+	endgame.expected_charge_station_points += endgame
+		.endgame
+		.iter()
+		.map(|end| end.probability * end.points)
+		.sum::<f32>();
+	endgame
+}
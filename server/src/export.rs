@@ -0,0 +1,863 @@
+use std::fmt::{Display, Formatter};
+
+use rust_xlsxwriter::{Format, Workbook, Worksheet, XlsxError};
+use thiserror::Error;
+
+use crate::analysis::{self, AnalysisOptions, TbaMatch, TeamInfo};
+use crate::data::{MatchInfo, MatchType, RobotInfo};
+use crate::database::{Database, DatabaseError};
+use crate::pick_list::PickList;
+
+#[derive(Debug, Error)]
+pub enum ExportError {
+	#[error("There's no column called {0}.")]
+	UnknownColumn(String),
+	#[error("Failed to write CSV: {0}")]
+	Csv(#[from] csv::Error),
+	#[error("Failed to write CSV: {0}")]
+	Io(#[from] std::io::Error),
+	#[error("Failed to write workbook: {0}")]
+	Xlsx(#[from] XlsxError),
+	#[error("Couldn't read the data to export: {0}")]
+	Database(#[from] DatabaseError),
+}
+
+// One cell of an export, kept typed so spreadsheets can tell numbers from text.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+	Integer(i64),
+	Number(f64),
+	Bool(bool),
+	Text(String),
+	Empty,
+}
+
+impl Display for Value {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Value::Integer(value) => write!(f, "{}", value),
+			Value::Number(value) => write!(f, "{}", value),
+			Value::Bool(value) => write!(f, "{}", value),
+			Value::Text(value) => write!(f, "{}", value),
+			Value::Empty => Ok(()),
+		}
+	}
+}
+
+impl From<u32> for Value {
+	fn from(value: u32) -> Self {
+		Value::Integer(value.into())
+	}
+}
+
+impl From<f32> for Value {
+	// Going through the shortest text for the f32 keeps 0.1 from turning into 0.10000000149011612.
+	fn from(value: f32) -> Self {
+		Value::Number(value.to_string().parse().unwrap_or_default())
+	}
+}
+
+impl From<bool> for Value {
+	fn from(value: bool) -> Self {
+		Value::Bool(value)
+	}
+}
+
+impl From<String> for Value {
+	fn from(value: String) -> Self {
+		if value.is_empty() {
+			Value::Empty
+		} else {
+			Value::Text(value)
+		}
+	}
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+	fn from(value: Option<T>) -> Self {
+		value.map_or(Value::Empty, Into::into)
+	}
+}
+
+// A column in an export, the header and every row come from the same list so they can't get out of line.
+pub struct Column<T: 'static> {
+	pub name: &'static str,
+	pub value: fn(&T) -> Value,
+}
+
+pub const MATCH_COLUMNS: &[Column<MatchInfo>] = &[
+	Column {
+		name: "match_number",
+		value: |m| Value::Integer(m.match_number.into()),
+	},
+	Column {
+		name: "match_category",
+		value: |m| Value::Text(m.match_category.to_string()),
+	},
+	Column {
+		name: "team",
+		value: |m| Value::Integer(m.team_number.into()),
+	},
+	Column {
+		name: "scout",
+		value: |m| Value::Text(m.scout.clone()),
+	},
+	Column {
+		name: "auto_exited_tarmac",
+		value: |m| Value::Bool(m.auto.exited_tarmac),
+	},
+	Column {
+		name: "auto_charge_station",
+		value: |m| Value::Text(m.auto.auto_charge_station.to_string()),
+	},
+	Column {
+		name: "auto_hybrid_cube_scored",
+		value: |m| Value::Integer(m.auto.hybrid_cube_scored.into()),
+	},
+	Column {
+		name: "auto_hybrid_cone_scored",
+		value: |m| Value::Integer(m.auto.hybrid_cone_scored.into()),
+	},
+	Column {
+		name: "auto_middle_cube_scored",
+		value: |m| Value::Integer(m.auto.middle_cube_scored.into()),
+	},
+	Column {
+		name: "auto_middle_cone_scored",
+		value: |m| Value::Integer(m.auto.middle_cone_scored.into()),
+	},
+	Column {
+		name: "auto_high_cube_scored",
+		value: |m| Value::Integer(m.auto.high_cube_scored.into()),
+	},
+	Column {
+		name: "auto_high_cone_scored",
+		value: |m| Value::Integer(m.auto.high_cone_scored.into()),
+	},
+	Column {
+		name: "teleop_hybrid_cube_scored",
+		value: |m| Value::Integer(m.teleop.hybrid_cube_scored.into()),
+	},
+	Column {
+		name: "teleop_hybrid_cone_scored",
+		value: |m| Value::Integer(m.teleop.hybrid_cone_scored.into()),
+	},
+	Column {
+		name: "teleop_middle_cube_scored",
+		value: |m| Value::Integer(m.teleop.middle_cube_scored.into()),
+	},
+	Column {
+		name: "teleop_middle_cone_scored",
+		value: |m| Value::Integer(m.teleop.middle_cone_scored.into()),
+	},
+	Column {
+		name: "teleop_high_cube_scored",
+		value: |m| Value::Integer(m.teleop.high_cube_scored.into()),
+	},
+	Column {
+		name: "teleop_high_cone_scored",
+		value: |m| Value::Integer(m.teleop.high_cone_scored.into()),
+	},
+	Column {
+		name: "teleop_parked",
+		value: |m| Value::Bool(m.teleop.parked),
+	},
+	Column {
+		name: "teleop_charge_station",
+		value: |m| Value::Text(m.teleop.teleop_charge_station.to_string()),
+	},
+	Column {
+		name: "auto_score",
+		value: |m| analysis::calculate_auto_score(m).into(),
+	},
+	Column {
+		name: "teleop_score",
+		value: |m| analysis::calculate_teleop_score(m).into(),
+	},
+	Column {
+		name: "speed",
+		value: |m| m.speed.into(),
+	},
+	Column {
+		name: "stability",
+		value: |m| m.stability.into(),
+	},
+	Column {
+		name: "defence",
+		value: |m| m.defence.into(),
+	},
+	Column {
+		name: "was_disabled",
+		value: |m| Value::Bool(m.was_disabled),
+	},
+	Column {
+		name: "notes",
+		value: |m| Value::Text(m.notes.clone()),
+	},
+	Column {
+		name: "last_modified_time",
+		value: |m| Value::Integer(m.last_modified_time as i64),
+	},
+];
+
+// Every pit visit for a team folded into one row, so a team that was visited twice doesn't show up twice.
+#[derive(Debug, Clone, Default)]
+pub struct PitRow {
+	pub visits: u32,
+	pub scouts: Vec<String>,
+	pub image_count: u32,
+	// Each answer from the latest visit that gave one.
+	pub latest: RobotInfo,
+	// Comments from every visit, oldest first.
+	pub comments: Vec<String>,
+}
+
+fn merge_option<T>(into: &mut Option<T>, from: Option<T>) {
+	if from.is_some() {
+		*into = from;
+	}
+}
+
+fn merge_string(into: &mut String, from: String) {
+	if !from.trim().is_empty() {
+		*into = from;
+	}
+}
+
+// Flatten pit visits into one row per team, in team number order.
+pub fn flatten_visits(mut visits: Vec<RobotInfo>) -> Vec<PitRow> {
+	visits.sort_by_key(|visit| {
+		(
+			visit.team_number,
+			visit.last_modified_time,
+			visit.visit_number,
+		)
+	});
+	let mut rows: Vec<PitRow> = Vec::new();
+	for visit in visits {
+		let row = match rows.last_mut() {
+			Some(row) if row.latest.team_number == visit.team_number => row,
+			_ => {
+				rows.push(PitRow::default());
+				rows.last_mut().unwrap()
+			}
+		};
+		row.visits += 1;
+		if !visit.scout.trim().is_empty() && !row.scouts.contains(&visit.scout) {
+			row.scouts.push(visit.scout.clone());
+		}
+		row.image_count += visit.images.len() as u32;
+		if !visit.robot.comments.trim().is_empty() {
+			row.comments.push(visit.robot.comments.trim().to_string());
+		}
+		let latest = &mut row.latest;
+		latest.team_number = visit.team_number;
+		latest.visit_number = latest.visit_number.max(visit.visit_number);
+		latest.last_modified_time = latest.last_modified_time.max(visit.last_modified_time);
+		merge_option(&mut latest.pit.pit_people, visit.pit.pit_people);
+		merge_option(&mut latest.pit.chaos, visit.pit.chaos);
+		merge_option(&mut latest.pit.confidence_level, visit.pit.confidence_level);
+		merge_string(&mut latest.pit.scouting_method, visit.pit.scouting_method);
+		let (into, from) = (&mut latest.robot, visit.robot);
+		merge_option(&mut into.bumper_type, from.bumper_type);
+		merge_option(&mut into.vision_type, from.vision_type);
+		merge_option(&mut into.human_pickup_range, from.human_pickup_range);
+		merge_option(&mut into.stack_type, from.stack_type);
+		merge_option(&mut into.preferred_play, from.preferred_play);
+		merge_option(&mut into.preferred_stack, from.preferred_stack);
+		merge_option(&mut into.charge_battery, from.charge_battery);
+		merge_option(&mut into.battery_amount, from.battery_amount);
+		merge_option(&mut into.drive_motor_amount, from.drive_motor_amount);
+		merge_option(&mut into.other_motor_amount, from.other_motor_amount);
+		merge_option(&mut into.balance_time, from.balance_time);
+		merge_string(&mut into.auto_settings, from.auto_settings);
+		merge_string(&mut into.drive_type, from.drive_type);
+	}
+	rows
+}
+
+fn display<T: Display>(value: Option<T>) -> Value {
+	value.map_or(Value::Empty, |value| Value::Text(value.to_string()))
+}
+
+pub const PIT_COLUMNS: &[Column<PitRow>] = &[
+	Column {
+		name: "team",
+		value: |p| p.latest.team_number.into(),
+	},
+	Column {
+		name: "visits",
+		value: |p| p.visits.into(),
+	},
+	Column {
+		name: "scouts",
+		value: |p| p.scouts.join(", ").into(),
+	},
+	Column {
+		name: "pit_people",
+		value: |p| p.latest.pit.pit_people.into(),
+	},
+	Column {
+		name: "chaos",
+		value: |p| p.latest.pit.chaos.into(),
+	},
+	Column {
+		name: "confidence_level",
+		value: |p| display(p.latest.pit.confidence_level),
+	},
+	Column {
+		name: "scouting_method",
+		value: |p| p.latest.pit.scouting_method.clone().into(),
+	},
+	Column {
+		name: "bumper_type",
+		value: |p| display(p.latest.robot.bumper_type),
+	},
+	Column {
+		name: "vision_type",
+		value: |p| display(p.latest.robot.vision_type),
+	},
+	Column {
+		name: "human_pickup_range",
+		value: |p| display(p.latest.robot.human_pickup_range),
+	},
+	Column {
+		name: "stack_type",
+		value: |p| display(p.latest.robot.stack_type),
+	},
+	Column {
+		name: "preferred_play",
+		value: |p| display(p.latest.robot.preferred_play),
+	},
+	Column {
+		name: "preferred_stack",
+		value: |p| display(p.latest.robot.preferred_stack),
+	},
+	Column {
+		name: "charge_battery",
+		value: |p| display(p.latest.robot.charge_battery),
+	},
+	Column {
+		name: "battery_amount",
+		value: |p| p.latest.robot.battery_amount.into(),
+	},
+	Column {
+		name: "drive_motor_amount",
+		value: |p| p.latest.robot.drive_motor_amount.into(),
+	},
+	Column {
+		name: "other_motor_amount",
+		value: |p| p.latest.robot.other_motor_amount.into(),
+	},
+	Column {
+		name: "balance_time",
+		value: |p| p.latest.robot.balance_time.into(),
+	},
+	Column {
+		name: "auto_settings",
+		value: |p| p.latest.robot.auto_settings.clone().into(),
+	},
+	Column {
+		name: "drive_type",
+		value: |p| p.latest.robot.drive_type.clone().into(),
+	},
+	Column {
+		name: "comments",
+		value: |p| p.comments.join(" | ").into(),
+	},
+	Column {
+		name: "images",
+		value: |p| p.image_count.into(),
+	},
+	Column {
+		name: "last_modified_time",
+		value: |p| Value::Integer(p.latest.last_modified_time as i64),
+	},
+];
+
+// Analysis fields are all plain numbers, so the columns are named after the fields.
+macro_rules! team_columns {
+	($($field:ident),* $(,)?) => {
+		&[
+			Column {
+				name: "team",
+				value: |t| t.team_number.into(),
+			},
+			Column {
+				name: "team_name",
+				value: |t| t.team_name.clone().into(),
+			},
+			Column {
+				name: "team_rookie_year",
+				value: |t| t.team_rookie_year.into(),
+			},
+			$(Column {
+				name: stringify!($field),
+				value: |t| t.$field.into(),
+			},)*
+		]
+	};
+}
+
+pub const ANALYSIS_COLUMNS: &[Column<TeamInfo>] = team_columns!(
+	matches,
+	average_auto_score,
+	average_teleop_score,
+	average_auto_hybrid_score,
+	average_auto_middle_score,
+	average_auto_high_score,
+	average_auto_cone_score,
+	average_auto_cube_score,
+	average_auto_hybrid_cube_score,
+	average_auto_hybrid_cone_score,
+	average_auto_middle_cube_score,
+	average_auto_middle_cone_score,
+	average_auto_high_cube_score,
+	average_auto_high_cone_score,
+	average_teleop_hybrid_score,
+	average_teleop_middle_score,
+	average_teleop_high_score,
+	average_teleop_cone_score,
+	average_teleop_cube_score,
+	average_teleop_hybrid_cube_score,
+	average_teleop_hybrid_cone_score,
+	average_teleop_middle_cube_score,
+	average_teleop_middle_cone_score,
+	average_teleop_high_cube_score,
+	average_teleop_high_cone_score,
+	average_defence_score,
+	defence_samples,
+	defence_std_error,
+	average_luck_score,
+	average_cone_score,
+	average_cube_score,
+	average_hybrid_score,
+	average_middle_score,
+	average_high_score,
+	charge_station_auto_off,
+	charge_station_auto_on,
+	charge_station_auto_charged,
+	charge_station_auto_other,
+	charge_station_teleop_off,
+	charge_station_teleop_parked,
+	charge_station_teleop_on,
+	charge_station_teleop_charged,
+	auto_charge_station_samples,
+	auto_charge_station_attempt_rate,
+	auto_engage_rate_when_docked,
+	endgame_samples,
+	endgame_attempt_rate,
+	endgame_success_rate,
+	endgame_docked_rate,
+	endgame_engaged_rate,
+	endgame_engage_rate_when_docked,
+	opr,
+	dpr,
+	win_count,
+	loss_count,
+	overall_speed,
+	overall_stability,
+	overall_defence,
+	ranking_points,
+	trend_slope,
+	auto_score_variance,
+	teleop_score_variance,
+	average_game_pieces,
+	game_pieces_variance,
+	disabled_rate,
+	disabled_matches,
+	zero_score_rate,
+	zero_score_matches,
+	matches_since_failure,
+	reliability,
+);
+
+// What gets written out, with the header and the cells already worked out.
+#[derive(Debug, Clone, Default)]
+pub struct Table {
+	pub headers: Vec<String>,
+	pub rows: Vec<Vec<Value>>,
+}
+
+impl Table {
+	pub fn new<T>(columns: &[Column<T>], rows: &[T]) -> Table {
+		Table {
+			headers: columns
+				.iter()
+				.map(|column| column.name.to_string())
+				.collect(),
+			rows: rows
+				.iter()
+				.map(|row| columns.iter().map(|column| (column.value)(row)).collect())
+				.collect(),
+		}
+	}
+
+	// Analysis with a column for every saved formula and note tag, which aren't known until the data is in.
+	pub fn analysis(teams: &[TeamInfo]) -> Table {
+		let mut table = Table::new(ANALYSIS_COLUMNS, teams);
+		let mut formulas = teams
+			.iter()
+			.flat_map(|team| team.formula_scores.keys())
+			.collect::<Vec<_>>();
+		formulas.sort_unstable();
+		formulas.dedup();
+		for formula in formulas {
+			table.headers.push(format!("formula:{}", formula));
+			for (row, team) in table.rows.iter_mut().zip(teams) {
+				row.push(team.formula_scores.get(formula).copied().into());
+			}
+		}
+		let mut tags = teams
+			.iter()
+			.flat_map(|team| team.note_tags.keys())
+			.collect::<Vec<_>>();
+		tags.sort_unstable();
+		tags.dedup();
+		for tag in tags {
+			table.headers.push(format!("tag:{}", tag));
+			for (row, team) in table.rows.iter_mut().zip(teams) {
+				row.push(team.note_tags.get(tag).copied().unwrap_or(0).into());
+			}
+		}
+		table
+	}
+
+	// Only the columns asked for by name, in the order they were asked for, or every column if none were.
+	pub fn select(self, names: Option<&str>) -> Result<Table, ExportError> {
+		let names = match names {
+			Some(names) if !names.trim().is_empty() => names,
+			_ => return Ok(self),
+		};
+		let indices = names
+			.split(',')
+			.map(|name| name.trim())
+			.filter(|name| !name.is_empty())
+			.map(|name| {
+				self.headers
+					.iter()
+					.position(|header| header.eq_ignore_ascii_case(name))
+					.ok_or_else(|| ExportError::UnknownColumn(name.to_string()))
+			})
+			.collect::<Result<Vec<_>, _>>()?;
+		Ok(Table {
+			headers: indices.iter().map(|i| self.headers[*i].clone()).collect(),
+			rows: self
+				.rows
+				.into_iter()
+				.map(|row| indices.iter().map(|i| row[*i].clone()).collect())
+				.collect(),
+		})
+	}
+
+	pub fn to_csv(&self) -> Result<String, ExportError> {
+		let mut writer = csv::Writer::from_writer(Vec::new());
+		writer.write_record(&self.headers)?;
+		for row in self.rows.iter() {
+			writer.write_record(row.iter().map(|value| value.to_string()))?;
+		}
+		let data = writer.into_inner().map_err(|e| e.into_error())?;
+		Ok(String::from_utf8_lossy(&data).into_owned())
+	}
+}
+
+impl Table {
+	fn write_sheet(&self, worksheet: &mut Worksheet, header: &Format) -> Result<(), ExportError> {
+		for (col, name) in self.headers.iter().enumerate() {
+			worksheet.write_string_with_format(0, col as u16, name, header)?;
+		}
+		for (row, values) in self.rows.iter().enumerate() {
+			let row = row as u32 + 1;
+			for (col, value) in values.iter().enumerate() {
+				let col = col as u16;
+				match value {
+					Value::Integer(value) => worksheet.write_number(row, col, *value as f64)?,
+					Value::Number(value) if value.is_finite() => {
+						worksheet.write_number(row, col, *value)?
+					}
+					Value::Number(_) | Value::Empty => worksheet,
+					Value::Bool(value) => worksheet.write_boolean(row, col, *value)?,
+					Value::Text(value) => worksheet.write_string(row, col, value)?,
+				};
+			}
+		}
+		// Keep the header in view while scrolling.
+		worksheet.set_freeze_panes(1, 0)?;
+		worksheet.autofit();
+		Ok(())
+	}
+}
+
+// Schedule from TBA, blank scores until the match has been played.
+pub const SCHEDULE_COLUMNS: &[Column<((MatchType, u32), TbaMatch)>] = &[
+	Column {
+		name: "match_category",
+		value: |((category, _), _)| Value::Text(category.to_string()),
+	},
+	Column {
+		name: "match_number",
+		value: |((_, match_number), _)| (*match_number).into(),
+	},
+	Column {
+		name: "red_1",
+		value: |(_, m)| m.red_teams.first().copied().into(),
+	},
+	Column {
+		name: "red_2",
+		value: |(_, m)| m.red_teams.get(1).copied().into(),
+	},
+	Column {
+		name: "red_3",
+		value: |(_, m)| m.red_teams.get(2).copied().into(),
+	},
+	Column {
+		name: "blue_1",
+		value: |(_, m)| m.blue_teams.first().copied().into(),
+	},
+	Column {
+		name: "blue_2",
+		value: |(_, m)| m.blue_teams.get(1).copied().into(),
+	},
+	Column {
+		name: "blue_3",
+		value: |(_, m)| m.blue_teams.get(2).copied().into(),
+	},
+	Column {
+		name: "played",
+		value: |(_, m)| m.played.into(),
+	},
+	Column {
+		name: "red_score",
+		value: |(_, m)| {
+			m.played
+				.then_some(Value::Integer(m.red_score.into()))
+				.into()
+		},
+	},
+	Column {
+		name: "blue_score",
+		value: |(_, m)| {
+			m.played
+				.then_some(Value::Integer(m.blue_score.into()))
+				.into()
+		},
+	},
+	Column {
+		name: "red_ranking_points",
+		value: |(_, m)| m.red_ranking_points.into(),
+	},
+	Column {
+		name: "blue_ranking_points",
+		value: |(_, m)| m.blue_ranking_points.into(),
+	},
+];
+
+// One row per team on a pick list, lists one after another in the order they'd be read.
+fn pick_list_table(pick_lists: &[PickList]) -> Table {
+	let mut table = Table {
+		headers: ["list", "rank", "team", "comment", "tags", "picked"]
+			.iter()
+			.map(|name| name.to_string())
+			.collect(),
+		rows: Vec::new(),
+	};
+	for pick_list in pick_lists {
+		for (rank, entry) in pick_list.visible_entries().into_iter().enumerate() {
+			table.rows.push(vec![
+				Value::Text(pick_list.name.clone()),
+				(rank as u32 + 1).into(),
+				entry.team_number.into(),
+				entry.comment.clone().into(),
+				entry.tags.join(", ").into(),
+				entry.picked.into(),
+			]);
+		}
+	}
+	table
+}
+
+// A workbook with a sheet for everything the strategy team looks at, and a summary sheet in front.
+// Everything comes from the database and the TBA data the server already has.
+pub fn write_workbook(
+	database: &Database,
+	options: &AnalysisOptions,
+) -> Result<Vec<u8>, ExportError> {
+	let matches = database.get_all_matches().collect::<Result<Vec<_>, _>>()?;
+	let matches = options
+		.filter
+		.apply(matches, options.filter.scout_copies(database)?);
+	let visits = database.get_all_robots().collect::<Result<Vec<_>, _>>()?;
+	let pits = flatten_visits(options.filter.apply_pit(visits));
+	let teams = analysis::analyze_data(database, options);
+	let (_, tba_matches) = analysis::get_tba_data();
+	let mut schedule = tba_matches.into_iter().collect::<Vec<_>>();
+	schedule.sort_by_key(|((category, match_number), _)| {
+		(*category == MatchType::Qualification, *match_number)
+	});
+	let mut pick_lists = database.get_all_pick_lists()?;
+	pick_lists.sort_by(|a, b| a.name.cmp(&b.name));
+	let best_team = teams.iter().filter(|t| t.team_number != 0).max_by(|a, b| {
+		(a.average_auto_score + a.average_teleop_score)
+			.total_cmp(&(b.average_auto_score + b.average_teleop_score))
+	});
+
+	let summary = Table {
+		headers: vec!["item".to_string(), "value".to_string()],
+		rows: vec![
+			vec![
+				Value::Text("Match records".to_string()),
+				(matches.len() as u32).into(),
+			],
+			vec![
+				Value::Text("Pit scouted teams".to_string()),
+				(pits.len() as u32).into(),
+			],
+			vec![
+				Value::Text("Teams analysed".to_string()),
+				(teams.iter().filter(|t| t.team_number != 0).count() as u32).into(),
+			],
+			vec![
+				Value::Text("Scheduled matches".to_string()),
+				(schedule.len() as u32).into(),
+			],
+			vec![
+				Value::Text("Played matches".to_string()),
+				(schedule.iter().filter(|(_, m)| m.played).count() as u32).into(),
+			],
+			vec![
+				Value::Text("Pick lists".to_string()),
+				(pick_lists.len() as u32).into(),
+			],
+			vec![
+				Value::Text("Best average team".to_string()),
+				best_team.map(|t| t.team_number).into(),
+			],
+			vec![
+				Value::Text("Best average score".to_string()),
+				best_team
+					.map(|t| t.average_auto_score + t.average_teleop_score)
+					.into(),
+			],
+		],
+	};
+
+	let header = Format::new().set_bold();
+	let mut workbook = Workbook::new();
+	for (name, table) in [
+		("Summary", summary),
+		("Matches", Table::new(MATCH_COLUMNS, &matches)),
+		("Pit", Table::new(PIT_COLUMNS, &pits)),
+		("Analysis", Table::analysis(&teams)),
+		("Schedule", Table::new(SCHEDULE_COLUMNS, &schedule)),
+		("Pick Lists", pick_list_table(&pick_lists)),
+	] {
+		let worksheet = workbook.add_worksheet();
+		worksheet.set_name(name)?;
+		table.write_sheet(worksheet, &header)?;
+	}
+	Ok(workbook.save_to_buffer()?)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn match_info(team_number: u32, notes: &str) -> MatchInfo {
+		let mut match_info = MatchInfo {
+			match_number: 4,
+			team_number,
+			notes: notes.to_string(),
+			..MatchInfo::default()
+		};
+		match_info.auto.high_cone_scored = 2;
+		match_info
+	}
+
+	#[test]
+	fn every_row_matches_the_headers() {
+		let table = Table::new(MATCH_COLUMNS, &[match_info(1, ""), match_info(2, "")]);
+		assert_eq!(table.headers.len(), MATCH_COLUMNS.len());
+		assert!(table
+			.rows
+			.iter()
+			.all(|row| row.len() == table.headers.len()));
+		let team = table.headers.iter().position(|h| h == "team").unwrap();
+		assert_eq!(table.rows[1][team], Value::Integer(2));
+	}
+
+	#[test]
+	fn select_keeps_the_order_asked_for() {
+		let table = Table::new(MATCH_COLUMNS, &[match_info(254, "")])
+			.select(Some(" Team, auto_score ,,match_number"))
+			.unwrap();
+		assert_eq!(table.headers, vec!["team", "auto_score", "match_number"]);
+		assert_eq!(
+			table.rows,
+			vec![vec![
+				Value::Integer(254),
+				Value::Number(12.0),
+				Value::Integer(4)
+			]]
+		);
+		assert!(matches!(
+			Table::new(MATCH_COLUMNS, &[]).select(Some("team,nope")),
+			Err(ExportError::UnknownColumn(name)) if name == "nope"
+		));
+		let all = Table::new(MATCH_COLUMNS, &[]).select(Some(" ")).unwrap();
+		assert_eq!(all.headers.len(), MATCH_COLUMNS.len());
+	}
+
+	#[test]
+	fn csv_escapes_notes() {
+		let notes = "fast, \"very\" fast\nsecond line";
+		let csv = Table::new(MATCH_COLUMNS, &[match_info(1, notes)])
+			.select(Some("team,notes,defence"))
+			.unwrap()
+			.to_csv()
+			.unwrap();
+		let mut reader = csv::Reader::from_reader(csv.as_bytes());
+		assert_eq!(
+			reader.headers().unwrap().iter().collect::<Vec<_>>(),
+			vec!["team", "notes", "defence"]
+		);
+		let records = reader.records().collect::<Result<Vec<_>, _>>().unwrap();
+		assert_eq!(records.len(), 1);
+		assert_eq!(records[0].iter().collect::<Vec<_>>(), vec!["1", notes, ""]);
+	}
+
+	fn visit(team_number: u32, time: u64, scout: &str, pit_people: Option<u32>) -> RobotInfo {
+		let mut visit = RobotInfo {
+			team_number,
+			visit_number: 1,
+			last_modified_time: time,
+			scout: scout.to_string(),
+			images: vec!["a".to_string()],
+			..RobotInfo::default()
+		};
+		visit.pit.pit_people = pit_people;
+		visit.robot.comments = format!("visit at {}", time);
+		visit
+	}
+
+	#[test]
+	fn flatten_visits_keeps_latest_answers() {
+		let rows = flatten_visits(vec![
+			visit(2, 30, "sam", Some(4)),
+			visit(1, 10, "ana", None),
+			visit(2, 10, "ana", Some(3)),
+			visit(2, 20, "sam", None),
+		]);
+		assert_eq!(rows.len(), 2);
+		assert_eq!(rows[0].latest.team_number, 1);
+		let row = &rows[1];
+		assert_eq!((row.visits, row.image_count), (3, 3));
+		assert_eq!(row.scouts, vec!["ana", "sam"]);
+		// A later visit that didn't answer doesn't clear the earlier answer.
+		assert_eq!(row.latest.pit.pit_people, Some(4));
+		assert_eq!(row.latest.last_modified_time, 30);
+		assert_eq!(
+			row.comments,
+			vec!["visit at 10", "visit at 20", "visit at 30"]
+		);
+	}
+}
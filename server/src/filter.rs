@@ -0,0 +1,219 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use crate::data::{MatchInfo, MatchType, RobotInfo};
+use crate::database::{Database, DatabaseError};
+use crate::scouts::scout_key;
+use crate::trends;
+
+// Which matches to use, for the endpoints that take filters. Lists are comma separated since query strings can't hold arrays.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct MatchFilter {
+	pub category: Option<MatchType>,
+	// Match numbers to use, both ends included.
+	pub from_match: Option<u32>,
+	pub to_match: Option<u32>,
+	// Times in milliseconds, compared against when the match was scouted.
+	pub after: Option<u64>,
+	pub before: Option<u64>,
+	// Only use each team's most recent matches.
+	pub last: Option<usize>,
+	pub exclude_disabled: bool,
+	pub exclude_scouts: Option<String>,
+	pub teams: Option<String>,
+}
+
+fn split_list(list: &Option<String>) -> impl Iterator<Item = &str> {
+	list.iter()
+		.flat_map(|list| list.split(','))
+		.map(|item| item.trim())
+		.filter(|item| !item.is_empty())
+}
+
+impl MatchFilter {
+	fn team_numbers(&self) -> Result<Option<Vec<u32>>, String> {
+		if self.teams.is_none() {
+			return Ok(None);
+		}
+		split_list(&self.teams)
+			.map(|team| {
+				team.parse::<u32>()
+					.map_err(|_| format!("{} is not a team number.", team))
+			})
+			.collect::<Result<Vec<_>, _>>()
+			.map(Some)
+	}
+
+	// Make sure the filter makes sense before using it.
+	pub fn validate(&self) -> Result<(), String> {
+		self.team_numbers()?;
+		if let (Some(from), Some(to)) = (self.from_match, self.to_match) {
+			if from > to {
+				return Err("fromMatch can't be after toMatch.".to_string());
+			}
+		}
+		if let (Some(after), Some(before)) = (self.after, self.before) {
+			if after > before {
+				return Err("after can't be later than before.".to_string());
+			}
+		}
+		if self.last == Some(0) {
+			return Err("last has to be at least 1.".to_string());
+		}
+		Ok(())
+	}
+
+	fn keeps(&self, match_info: &MatchInfo, teams: &Option<Vec<u32>>, scouts: &[String]) -> bool {
+		self.category
+			.is_none_or(|category| match_info.match_category == category)
+			&& self
+				.from_match
+				.is_none_or(|from| match_info.match_number >= from)
+			&& self.to_match.is_none_or(|to| match_info.match_number <= to)
+			&& self
+				.after
+				.is_none_or(|after| match_info.last_modified_time >= after)
+			&& self
+				.before
+				.is_none_or(|before| match_info.last_modified_time <= before)
+			&& !(self.exclude_disabled && match_info.was_disabled)
+			&& !scouts.contains(&scout_key(&match_info.scout))
+			&& teams
+				.as_ref()
+				.is_none_or(|teams| teams.contains(&match_info.team_number))
+	}
+
+	fn excludes_scouts(&self) -> bool {
+		split_list(&self.exclude_scouts).next().is_some()
+	}
+
+	// Every scout's copy of the matches, which apply needs when scouts are left out.
+	pub fn scout_copies(&self, database: &Database) -> Result<Vec<MatchInfo>, DatabaseError> {
+		if self.excludes_scouts() {
+			database.get_all_scout_matches()
+		} else {
+			Ok(Vec::new())
+		}
+	}
+
+	// Only the matches the filter asks for, in the order they were played. Each robot in a match is stored once,
+	// with whichever scout's copy came last, so if that scout is left out the newest copy in scout_copies from
+	// someone else is used instead.
+	pub fn apply(&self, matches: Vec<MatchInfo>, scout_copies: Vec<MatchInfo>) -> Vec<MatchInfo> {
+		let teams = self.team_numbers().unwrap_or_default();
+		let scouts = split_list(&self.exclude_scouts)
+			.map(scout_key)
+			.collect::<Vec<_>>();
+		let excluded = |m: &MatchInfo| scouts.contains(&scout_key(&m.scout));
+		let mut other_copies: HashMap<_, MatchInfo> = HashMap::new();
+		for copy in scout_copies.into_iter().filter(|m| !excluded(m)) {
+			match other_copies.entry((copy.match_category, copy.match_number, copy.team_number)) {
+				Entry::Occupied(mut newest) => {
+					if newest.get().last_modified_time < copy.last_modified_time {
+						newest.insert(copy);
+					}
+				}
+				Entry::Vacant(entry) => {
+					entry.insert(copy);
+				}
+			}
+		}
+		let mut matches = matches
+			.into_iter()
+			.filter_map(|m| {
+				if excluded(&m) {
+					other_copies.remove(&(m.match_category, m.match_number, m.team_number))
+				} else {
+					Some(m)
+				}
+			})
+			.filter(|m| self.keeps(m, &teams, &scouts))
+			.collect::<Vec<_>>();
+		matches.sort_by_key(trends::match_order_key);
+		if let Some(last) = self.last {
+			let mut counts = HashMap::new();
+			for match_info in matches.iter() {
+				*counts.entry(match_info.team_number).or_insert(0) += 1;
+			}
+			let mut seen = HashMap::new();
+			matches.retain(|match_info| {
+				let seen = seen.entry(match_info.team_number).or_insert(0);
+				*seen += 1;
+				*seen + last > counts[&match_info.team_number]
+			});
+		}
+		matches
+	}
+
+	// Pit visits the filter asks for. Only the teams, scouts and times make sense for a pit visit, the rest is ignored.
+	pub fn apply_pit(&self, visits: Vec<RobotInfo>) -> Vec<RobotInfo> {
+		let teams = self.team_numbers().unwrap_or_default();
+		let scouts = split_list(&self.exclude_scouts)
+			.map(scout_key)
+			.collect::<Vec<_>>();
+		visits
+			.into_iter()
+			.filter(|visit| {
+				self.after
+					.is_none_or(|after| visit.last_modified_time >= after)
+					&& self
+						.before
+						.is_none_or(|before| visit.last_modified_time <= before)
+					&& !scouts.contains(&scout_key(&visit.scout))
+					&& teams
+						.as_ref()
+						.is_none_or(|teams| teams.contains(&visit.team_number))
+			})
+			.collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn scouted(match_number: u32, scout: &str, last_modified_time: u64) -> MatchInfo {
+		MatchInfo {
+			match_number,
+			match_category: MatchType::Qualification,
+			team_number: 4421,
+			last_modified_time,
+			scout: scout.to_string(),
+			..MatchInfo::default()
+		}
+	}
+
+	#[test]
+	fn excluded_scout_falls_back_to_another_copy() {
+		let filter = MatchFilter {
+			exclude_scouts: Some("Alice".to_string()),
+			..MatchFilter::default()
+		};
+		// Alice's copy of match 1 came last, Bob and Carol scouted it too. Only Alice scouted match 2.
+		let matches = vec![scouted(1, "alice", 30), scouted(2, "Alice", 40)];
+		let scout_copies = vec![
+			scouted(1, "bob", 10),
+			scouted(1, "carol", 20),
+			scouted(1, "alice", 30),
+			scouted(2, "Alice", 40),
+		];
+		assert!(filter.excludes_scouts());
+		let kept = filter.apply(matches, scout_copies);
+		assert_eq!(kept.len(), 1);
+		assert_eq!(kept[0].match_number, 1);
+		assert_eq!(kept[0].scout, "carol");
+	}
+
+	#[test]
+	fn no_excluded_scouts_keeps_stored_matches() {
+		let filter = MatchFilter::default();
+		assert!(!filter.excludes_scouts());
+		let kept = filter.apply(vec![scouted(1, "alice", 30)], Vec::new());
+		assert_eq!(kept.len(), 1);
+		assert_eq!(kept[0].scout, "alice");
+	}
+}
@@ -0,0 +1,441 @@
+use std::collections::HashMap;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::analysis::TeamInfo;
+
+#[derive(Debug, Error)]
+pub enum FormulaError {
+	#[error("Unexpected character '{0}' in formula")]
+	UnexpectedCharacter(char),
+	#[error("Unexpected end of formula")]
+	UnexpectedEnd,
+	#[error("Unexpected '{0}' in formula")]
+	UnexpectedToken(String),
+	#[error("Unknown metric '{0}'")]
+	UnknownMetric(String),
+	#[error("Unknown function '{0}'")]
+	UnknownFunction(String),
+	#[error("Function '{0}' takes {1} arguments")]
+	WrongArguments(String, usize),
+	#[error("Formula is longer than {0} characters")]
+	TooLong(usize),
+	#[error("Formula is nested more than {0} levels deep")]
+	TooDeep(usize),
+}
+
+// Formulas come from scouts and other servers, so keep them small enough that parsing and working them out stays cheap.
+const MAX_LENGTH: usize = 1000;
+const MAX_DEPTH: usize = 32;
+
+// A named ranking formula, as it's saved in the database.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct Formula {
+	pub name: String,
+	pub expression: String,
+	pub last_modified_time: u64,
+	// Deleted formulas are kept so the deletion syncs to other servers.
+	pub deleted: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+	Number(f32),
+	Identifier(String),
+	Operator(char),
+	OpenParen,
+	CloseParen,
+	Comma,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+	Number(f32),
+	Metric(String),
+	Negate(Box<Expression>),
+	Binary(char, Box<Expression>, Box<Expression>),
+	Call(String, Vec<Expression>),
+}
+
+// Metric names are matched without caring about case or underscores, so both averageAutoScore and average_auto_score work.
+fn normalize_name(name: &str) -> String {
+	name.chars()
+		.filter(|c| *c != '_')
+		.flat_map(|c| c.to_lowercase())
+		.collect()
+}
+
+// Every number in TeamInfo that can be used in a formula, by normalized name.
+pub fn team_metrics(team: &TeamInfo) -> HashMap<String, f32> {
+	let mut metrics = HashMap::new();
+	if let Ok(serde_json::Value::Object(fields)) = serde_json::to_value(team) {
+		for (name, value) in fields {
+			if let Some(number) = value.as_f64() {
+				metrics.insert(normalize_name(&name), number as f32);
+			}
+		}
+	}
+	metrics
+}
+
+fn read_number(first: char, chars: &mut Peekable<Chars>) -> Result<f32, FormulaError> {
+	let mut text = first.to_string();
+	while let Some(&c) = chars.peek() {
+		if c.is_ascii_digit() || c == '.' {
+			text.push(c);
+			chars.next();
+		} else {
+			break;
+		}
+	}
+	text.parse()
+		.map_err(|_| FormulaError::UnexpectedToken(text.clone()))
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, FormulaError> {
+	let mut tokens = Vec::new();
+	let mut chars = expression.chars().peekable();
+	while let Some(c) = chars.next() {
+		match c {
+			c if c.is_whitespace() => {}
+			c if c.is_ascii_digit() || c == '.' => {
+				tokens.push(Token::Number(read_number(c, &mut chars)?))
+			}
+			c if c.is_alphabetic() || c == '_' => {
+				let mut name = c.to_string();
+				while let Some(&c) = chars.peek() {
+					if c.is_alphanumeric() || c == '_' {
+						name.push(c);
+						chars.next();
+					} else {
+						break;
+					}
+				}
+				tokens.push(Token::Identifier(name));
+			}
+			'+' | '-' | '*' | '/' | '^' => tokens.push(Token::Operator(c)),
+			// Allow the way people write formulas down by hand.
+			'·' | '×' => tokens.push(Token::Operator('*')),
+			'−' => tokens.push(Token::Operator('-')),
+			'(' => tokens.push(Token::OpenParen),
+			')' => tokens.push(Token::CloseParen),
+			',' => tokens.push(Token::Comma),
+			c => return Err(FormulaError::UnexpectedCharacter(c)),
+		}
+	}
+	Ok(tokens)
+}
+
+struct Parser {
+	tokens: Vec<Token>,
+	index: usize,
+	depth: usize,
+}
+
+impl Parser {
+	fn peek(&self) -> Option<&Token> {
+		self.tokens.get(self.index)
+	}
+
+	fn next(&mut self) -> Result<Token, FormulaError> {
+		let token = self
+			.tokens
+			.get(self.index)
+			.cloned()
+			.ok_or(FormulaError::UnexpectedEnd)?;
+		self.index += 1;
+		Ok(token)
+	}
+
+	fn expect(&mut self, expected: Token) -> Result<(), FormulaError> {
+		let token = self.next()?;
+		if token == expected {
+			Ok(())
+		} else {
+			Err(FormulaError::UnexpectedToken(format!("{:?}", token)))
+		}
+	}
+
+	// sum := product (('+' | '-') product)*
+	fn sum(&mut self) -> Result<Expression, FormulaError> {
+		let mut left = self.product()?;
+		while let Some(Token::Operator(op)) = self.peek().cloned() {
+			if op != '+' && op != '-' {
+				break;
+			}
+			self.index += 1;
+			left = Expression::Binary(op, Box::new(left), Box::new(self.product()?));
+		}
+		Ok(left)
+	}
+
+	// product := unary (('*' | '/') unary)*
+	fn product(&mut self) -> Result<Expression, FormulaError> {
+		let mut left = self.unary()?;
+		while let Some(Token::Operator(op)) = self.peek().cloned() {
+			if op != '*' && op != '/' {
+				break;
+			}
+			self.index += 1;
+			left = Expression::Binary(op, Box::new(left), Box::new(self.unary()?));
+		}
+		Ok(left)
+	}
+
+	// unary := '-' unary | power
+	// Everything that nests goes through here, so this is where the depth is counted.
+	fn unary(&mut self) -> Result<Expression, FormulaError> {
+		if self.depth >= MAX_DEPTH {
+			return Err(FormulaError::TooDeep(MAX_DEPTH));
+		}
+		self.depth += 1;
+		let expression = if self.peek() == Some(&Token::Operator('-')) {
+			self.index += 1;
+			self.unary()
+				.map(|inner| Expression::Negate(Box::new(inner)))
+		} else {
+			self.power()
+		};
+		self.depth -= 1;
+		expression
+	}
+
+	// power := primary ('^' unary)?
+	fn power(&mut self) -> Result<Expression, FormulaError> {
+		let base = self.primary()?;
+		if self.peek() == Some(&Token::Operator('^')) {
+			self.index += 1;
+			return Ok(Expression::Binary(
+				'^',
+				Box::new(base),
+				Box::new(self.unary()?),
+			));
+		}
+		Ok(base)
+	}
+
+	// primary := number | metric | function '(' sum (',' sum)* ')' | '(' sum ')'
+	fn primary(&mut self) -> Result<Expression, FormulaError> {
+		match self.next()? {
+			Token::Number(value) => Ok(Expression::Number(value)),
+			Token::Identifier(name) => {
+				if self.peek() != Some(&Token::OpenParen) {
+					return Ok(Expression::Metric(normalize_name(&name)));
+				}
+				self.index += 1;
+				let mut arguments = vec![self.sum()?];
+				while self.peek() == Some(&Token::Comma) {
+					self.index += 1;
+					arguments.push(self.sum()?);
+				}
+				self.expect(Token::CloseParen)?;
+				Ok(Expression::Call(name.to_lowercase(), arguments))
+			}
+			Token::OpenParen => {
+				let inner = self.sum()?;
+				self.expect(Token::CloseParen)?;
+				Ok(inner)
+			}
+			token => Err(FormulaError::UnexpectedToken(format!("{:?}", token))),
+		}
+	}
+}
+
+// Make sure every metric and function in the expression exists.
+fn check(expression: &Expression, metrics: &HashMap<String, f32>) -> Result<(), FormulaError> {
+	match expression {
+		Expression::Number(_) => Ok(()),
+		Expression::Metric(name) => {
+			if metrics.contains_key(name) {
+				Ok(())
+			} else {
+				Err(FormulaError::UnknownMetric(name.clone()))
+			}
+		}
+		Expression::Negate(inner) => check(inner, metrics),
+		Expression::Binary(_, left, right) => {
+			check(left, metrics)?;
+			check(right, metrics)
+		}
+		Expression::Call(name, arguments) => {
+			let count = match name.as_str() {
+				"abs" | "sqrt" => 1,
+				"min" | "max" => 2,
+				_ => return Err(FormulaError::UnknownFunction(name.clone())),
+			};
+			if arguments.len() != count {
+				return Err(FormulaError::WrongArguments(name.clone(), count));
+			}
+			for argument in arguments {
+				check(argument, metrics)?;
+			}
+			Ok(())
+		}
+	}
+}
+
+pub fn parse(expression: &str) -> Result<Expression, FormulaError> {
+	if expression.chars().count() > MAX_LENGTH {
+		return Err(FormulaError::TooLong(MAX_LENGTH));
+	}
+	let mut parser = Parser {
+		tokens: tokenize(expression)?,
+		index: 0,
+		depth: 0,
+	};
+	let parsed = parser.sum()?;
+	if let Some(token) = parser.peek() {
+		return Err(FormulaError::UnexpectedToken(format!("{:?}", token)));
+	}
+	check(&parsed, &team_metrics(&TeamInfo::default()))?;
+	Ok(parsed)
+}
+
+pub fn evaluate(expression: &Expression, metrics: &HashMap<String, f32>) -> f32 {
+	let value = match expression {
+		Expression::Number(value) => *value,
+		Expression::Metric(name) => metrics.get(name).copied().unwrap_or(0.0),
+		Expression::Negate(inner) => -evaluate(inner, metrics),
+		Expression::Binary(op, left, right) => {
+			let (left, right) = (evaluate(left, metrics), evaluate(right, metrics));
+			match op {
+				'+' => left + right,
+				'-' => left - right,
+				'*' => left * right,
+				'/' => left / right,
+				_ => left.powf(right),
+			}
+		}
+		Expression::Call(name, arguments) => {
+			let values = arguments
+				.iter()
+				.map(|a| evaluate(a, metrics))
+				.collect::<Vec<_>>();
+			match name.as_str() {
+				"abs" => values[0].abs(),
+				"sqrt" => values[0].sqrt(),
+				"min" => values[0].min(values[1]),
+				_ => values[0].max(values[1]),
+			}
+		}
+	};
+	// Dividing by zero and the like shouldn't break the ranking.
+	if value.is_finite() {
+		value
+	} else {
+		0.0
+	}
+}
+
+// Work out every formula for every team, and sort the teams by one of them if asked to.
+pub fn score_teams(teams: &mut [TeamInfo], formulas: &[Formula], sort: Option<&str>) {
+	let parsed = formulas
+		.iter()
+		.filter_map(|f| parse(&f.expression).ok().map(|e| (f.name.clone(), e)))
+		.collect::<Vec<_>>();
+	for team in teams.iter_mut() {
+		let metrics = team_metrics(team);
+		for (name, expression) in parsed.iter() {
+			team.formula_scores
+				.insert(name.clone(), evaluate(expression, &metrics));
+		}
+	}
+	if let Some(sort) = sort {
+		teams.sort_by(|a, b| {
+			let a = a.formula_scores.get(sort).copied().unwrap_or(0.0);
+			let b = b.formula_scores.get(sort).copied().unwrap_or(0.0);
+			b.total_cmp(&a)
+		});
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn metrics() -> HashMap<String, f32> {
+		let mut metrics = team_metrics(&TeamInfo::default());
+		metrics.insert("averageautoscore".to_string(), 4.0);
+		metrics.insert("opr".to_string(), 10.0);
+		metrics
+	}
+
+	fn value(expression: &str) -> f32 {
+		evaluate(&parse(expression).unwrap(), &metrics())
+	}
+
+	#[test]
+	fn precedence() {
+		assert_eq!(value("1 + 2 * 3"), 7.0);
+		assert_eq!(value("(1 + 2) * 3"), 9.0);
+		assert_eq!(value("-2 ^ 2"), -4.0);
+		assert_eq!(value("2 ^ 3 ^ 2"), 512.0);
+		assert_eq!(value("10 - 4 - 3"), 3.0);
+	}
+
+	#[test]
+	fn metrics_and_functions() {
+		assert_eq!(value("average_auto_score × 2 + opr"), 18.0);
+		assert_eq!(value("max(OPR, averageAutoScore) - min(1, 2)"), 9.0);
+		assert_eq!(value("sqrt(abs(-16))"), 4.0);
+		// Dividing by zero counts as zero.
+		assert_eq!(value("opr / 0"), 0.0);
+	}
+
+	#[test]
+	fn rejects_bad_formulas() {
+		assert!(matches!(parse("1 +"), Err(FormulaError::UnexpectedEnd)));
+		assert!(matches!(
+			parse("1 2"),
+			Err(FormulaError::UnexpectedToken(_))
+		));
+		assert!(matches!(
+			parse("1 $ 2"),
+			Err(FormulaError::UnexpectedCharacter('$'))
+		));
+		assert!(matches!(
+			parse("nothing"),
+			Err(FormulaError::UnknownMetric(_))
+		));
+		assert!(matches!(
+			parse("log(1)"),
+			Err(FormulaError::UnknownFunction(_))
+		));
+		assert!(matches!(
+			parse("max(1)"),
+			Err(FormulaError::WrongArguments(_, 2))
+		));
+	}
+
+	#[test]
+	fn depth_limit() {
+		let nested = |depth: usize| format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+		assert!(parse(&nested(MAX_DEPTH - 1)).is_ok());
+		assert!(matches!(
+			parse(&nested(MAX_DEPTH)),
+			Err(FormulaError::TooDeep(MAX_DEPTH))
+		));
+		assert!(matches!(
+			parse(&"-".repeat(MAX_DEPTH * 2)),
+			Err(FormulaError::TooDeep(MAX_DEPTH))
+		));
+		assert!(matches!(
+			parse(&vec!["2"; MAX_DEPTH + 1].join("^")),
+			Err(FormulaError::TooDeep(MAX_DEPTH))
+		));
+	}
+
+	#[test]
+	fn length_limit() {
+		let long = vec!["1"; MAX_LENGTH / 2 + 1].join("+");
+		assert!(matches!(
+			parse(&long),
+			Err(FormulaError::TooLong(MAX_LENGTH))
+		));
+	}
+}
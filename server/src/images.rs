@@ -0,0 +1,194 @@
+use std::io::Cursor;
+
+use image::codecs::jpeg::JpegEncoder;
+use image::{DynamicImage, ImageDecoder, ImageReader};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ImageError {
+	#[error("Image isn't a data URL.")]
+	NotDataUrl,
+	#[error("Image data isn't valid base64: {0}")]
+	Base64(#[from] base64::DecodeError),
+	#[error("Image isn't a PNG, JPEG, WebP or HEIC file.")]
+	UnknownFormat,
+	#[error("Couldn't resize image: {0}")]
+	Resize(#[from] image::ImageError),
+}
+
+const THUMBNAIL_QUALITY: u8 = 80;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageSize {
+	Small,
+	Medium,
+	Large,
+	#[default]
+	Original,
+}
+
+impl ImageSize {
+	pub const THUMBNAILS: [ImageSize; 3] = [ImageSize::Small, ImageSize::Medium, ImageSize::Large];
+
+	// Longest side of the thumbnail in pixels.
+	fn max_dimension(self) -> Option<u32> {
+		match self {
+			ImageSize::Small => Some(160),
+			ImageSize::Medium => Some(480),
+			ImageSize::Large => Some(1280),
+			ImageSize::Original => None,
+		}
+	}
+
+	pub fn name(self) -> &'static str {
+		match self {
+			ImageSize::Small => "small",
+			ImageSize::Medium => "medium",
+			ImageSize::Large => "large",
+			ImageSize::Original => "original",
+		}
+	}
+}
+
+// Brands in the `ftyp` box of HEIC and HEIF files, which is what iPads take pictures in.
+const HEIC_BRANDS: [&[u8]; 6] = [b"heic", b"heix", b"hevc", b"heif", b"mif1", b"msf1"];
+
+// Everything known about a stored image, the image itself is kept separately under its hash.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageMeta {
+	pub hash: String,
+	pub mime: String,
+	pub size: u64,
+	pub team_number: u32,
+	// Scout that uploaded it first, the same picture sent again later keeps the original details.
+	pub uploader: String,
+	pub time: u64,
+	pub caption: String,
+	// What's in the picture, like intake, drivetrain or bumpers.
+	pub tags: Vec<String>,
+	// Shown first for the team, only one of a team's images can be preferred.
+	pub preferred: bool,
+	// Deleted images keep their details so the deletion syncs, but the image itself is gone.
+	pub deleted: bool,
+	// When the caption, tags or anything else was last changed, the newest version wins when syncing.
+	pub last_modified_time: u64,
+}
+
+// A change to an image from the API, anything left out stays the same.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageEdit {
+	pub id: String,
+	pub caption: Option<String>,
+	pub tags: Option<Vec<String>>,
+	pub preferred: Option<bool>,
+	#[serde(default)]
+	pub delete: bool,
+}
+
+impl ImageEdit {
+	pub fn apply(&self, meta: &mut ImageMeta, time: u64) {
+		if let Some(caption) = &self.caption {
+			meta.caption = caption.trim().to_string();
+		}
+		if let Some(tags) = &self.tags {
+			meta.tags = tags
+				.iter()
+				.map(|tag| tag.trim().to_lowercase())
+				.filter(|tag| !tag.is_empty())
+				.collect();
+			meta.tags.sort_unstable();
+			meta.tags.dedup();
+		}
+		if let Some(preferred) = self.preferred {
+			meta.preferred = preferred;
+		}
+		if self.delete {
+			meta.deleted = true;
+			meta.preferred = false;
+		}
+		meta.last_modified_time = time;
+	}
+}
+
+// An image sent between servers when syncing, with the data in base64. Only the details are sent if the
+// other server already has the image, or if it's been deleted.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageUpload {
+	pub meta: ImageMeta,
+	pub data: Option<String>,
+}
+
+// Work out the format from the first bytes of the file, since tablets don't always say the right one in the data URL.
+pub fn detect_mime(data: &[u8]) -> Option<&'static str> {
+	if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+		Some("image/png")
+	} else if data.starts_with(&[0xff, 0xd8, 0xff]) {
+		Some("image/jpeg")
+	} else if data.len() >= 12 && &data[0..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+		Some("image/webp")
+	} else if data.len() >= 12
+		&& &data[4..8] == b"ftyp"
+		&& HEIC_BRANDS.iter().any(|brand| &data[8..12] == *brand)
+	{
+		Some("image/heic")
+	} else {
+		None
+	}
+}
+
+// Images are named by the SHA-256 of their contents, so the same picture uploaded twice is only kept once.
+pub fn hash_image(data: &[u8]) -> String {
+	format!("{:x}", Sha256::digest(data))
+}
+
+// Old images were saved as JPEG files named with a number, new ones use the hash.
+pub fn is_image_hash(id: &str) -> bool {
+	id.len() == 64 && id.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+// The hash in a link to an image in the store, if it is one.
+pub fn image_hash_from_url(url: &str) -> Option<&str> {
+	url.split_once("/api/img?id=")
+		.map(|(_, id)| id.split('&').next().unwrap_or(id))
+		.filter(|id| is_image_hash(id))
+}
+
+pub fn image_url(hash: &str) -> String {
+	format!("{{AUTOSCOUT_URL}}/api/img?id={}", hash)
+}
+
+// The image in a `data:image/...;base64,...` URL, along with its format.
+pub fn decode_data_url(url: &str) -> Result<(Vec<u8>, &'static str), ImageError> {
+	let data = url
+		.strip_prefix("data:")
+		.and_then(|url| url.split_once(','))
+		.map(|(_, data)| data)
+		.ok_or(ImageError::NotDataUrl)?;
+	let data = base64::decode(data.trim())?;
+	let mime = detect_mime(&data).ok_or(ImageError::UnknownFormat)?;
+	Ok((data, mime))
+}
+
+// A smaller JPEG copy of an image, turned the right way up. Nothing from the original file's metadata is kept,
+// so phone photos don't carry their location around. HEIC can't be decoded, so those only have the original.
+// Thumbnails are always JPEG: the image crate can only write lossless WebP, which comes out bigger than JPEG for photos.
+pub fn make_thumbnail(data: &[u8], size: ImageSize) -> Result<Vec<u8>, ImageError> {
+	let max_dimension = size.max_dimension().ok_or(ImageError::UnknownFormat)?;
+	let mut decoder = ImageReader::new(Cursor::new(data))
+		.with_guessed_format()
+		.map_err(image::ImageError::IoError)?
+		.into_decoder()?;
+	let orientation = decoder.orientation()?;
+	let mut image = DynamicImage::from_decoder(decoder)?;
+	image.apply_orientation(orientation);
+	let thumbnail = image.thumbnail(max_dimension, max_dimension).to_rgb8();
+	let mut output = Vec::new();
+	JpegEncoder::new_with_quality(&mut output, THUMBNAIL_QUALITY).encode_image(&thumbnail)?;
+	Ok(output)
+}
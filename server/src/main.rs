@@ -1,10 +1,28 @@
+mod alliance_analysis;
+mod alliance_selection;
 mod analysis;
+mod assignments;
+mod backup;
+mod cli;
 mod config;
+mod coverage;
 mod data;
 mod database;
+mod endgame;
+mod export;
+mod filter;
+mod formula;
+mod images;
 mod match_info;
+mod pick_list;
+mod prediction;
+mod projection;
+mod schedule_strength;
+mod scouts;
+mod season;
 mod server_sync;
 mod team_info;
+mod trends;
 
 use std::path::PathBuf;
 use std::str::FromStr;
@@ -14,14 +32,83 @@ use actix_files::{Files, NamedFile};
 use actix_web::http::header::ContentType;
 use actix_web::http::{header, StatusCode};
 use actix_web::web::Data;
-use actix_web::{get, options, put, web, App, HttpRequest, HttpResponse, HttpServer, Responder};
+use actix_web::{
+	delete, get, options, put, web, App, HttpRequest, HttpResponse, HttpServer, Responder,
+};
 use futures_util::stream::StreamExt as _;
+use serde::de::DeserializeOwned;
 use serde::Deserialize;
 use serde_json::json;
 use simplelog::TermLogger;
 
-use crate::data::{Info, MatchInfo};
+use crate::data::{parse_infos, Info, MatchInfo};
 use crate::database::Database;
+use crate::pick_list::{PickList, PickListEdit};
+
+// Biggest request body that's read, image uploads come in batches so this leaves plenty of room.
+const MAX_REQUEST_SIZE: usize = 64 * 1024 * 1024;
+// Backups carry every image, so they get more room than other requests.
+const MAX_BACKUP_SIZE: usize = 1024 * 1024 * 1024;
+
+// Read a request body, giving up once it's bigger than limit.
+async fn read_bytes(mut body: web::Payload, limit: usize) -> Result<web::BytesMut, String> {
+	let mut bytes = web::BytesMut::new();
+	while let Some(item) = body.next().await {
+		let item = item.map_err(|e| e.to_string())?;
+		if bytes.len() + item.len() > limit {
+			return Err(format!("The request is bigger than {} bytes.", limit));
+		}
+		bytes.extend_from_slice(&item);
+	}
+	Ok(bytes)
+}
+
+// Read a JSON request body, without caring about the content type that was sent.
+async fn read_json<T: DeserializeOwned>(body: web::Payload) -> Result<T, String> {
+	serde_json::from_slice(&read_bytes(body, MAX_REQUEST_SIZE).await?).map_err(|e| e.to_string())
+}
+
+// Admin routes can replace the whole database, so they only work when the config has an admin_token,
+// and it has to be sent as "Authorization: Bearer <token>". Gives back the response to send if the request isn't allowed.
+fn reject_admin_request(req: &HttpRequest) -> Option<HttpResponse> {
+	let token = match &config::get_config().admin_token {
+		Some(token) if !token.is_empty() => token,
+		_ => {
+			return Some(admin_error(
+				StatusCode::FORBIDDEN,
+				"Admin routes are turned off, set admin_token in the config to use them.",
+			))
+		}
+	};
+	let sent = req
+		.headers()
+		.get(header::AUTHORIZATION)
+		.and_then(|value| value.to_str().ok())
+		.and_then(|value| value.strip_prefix("Bearer "));
+	match sent {
+		Some(sent) if same_token(sent.as_bytes(), token.as_bytes()) => None,
+		_ => Some(admin_error(
+			StatusCode::UNAUTHORIZED,
+			"Wrong or missing admin token.",
+		)),
+	}
+}
+
+// Compares every byte, so how long the check takes doesn't give away how much of the token was right.
+fn same_token(sent: &[u8], token: &[u8]) -> bool {
+	sent.len() == token.len()
+		&& sent
+			.iter()
+			.zip(token)
+			.fold(0, |diff, (a, b)| diff | (a ^ b))
+			== 0
+}
+
+fn admin_error(status: StatusCode, error: &str) -> HttpResponse {
+	HttpResponse::build(status)
+		.content_type(ContentType::json())
+		.body(serde_json::to_string(&json!({"success": false, "error": error})).unwrap())
+}
 
 #[options("/api/push")]
 async fn push_options(_params: ()) -> HttpResponse {
@@ -41,15 +128,7 @@ async fn push_data(data: Data<Arc<Database>>, mut body: web::Payload) -> HttpRes
 	}
 	let string = String::from_utf8(bytes.to_vec()).unwrap();
 	let matches_raw: Vec<serde_json::Value> = serde_json::from_str(&string).unwrap();
-	let mut matches = Vec::new();
-	for match_raw in matches_raw {
-		let match_str = serde_json::to_string(&match_raw).unwrap();
-		if let Ok(info_parsed) = serde_json::from_str::<Info>(&match_str) {
-			matches.push(info_parsed);
-		} else if let Ok(info_parsed) = serde_json::from_str::<MatchInfo>(&match_str) {
-			matches.push(Info::MatchInfo(info_parsed));
-		}
-	}
+	let matches = parse_infos(matches_raw);
 	if let Err(e) = data.merge_info(&matches) {
 		return HttpResponse::build(StatusCode::OK)
 			.content_type(ContentType::json())
@@ -71,13 +150,29 @@ async fn pull_data(data: Data<Arc<Database>>) -> HttpResponse {
 		.content_type(ContentType::json())
 		.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
 		.body(
-			serde_json::to_string(&json!({"success": true, "data": data.get_info_list()})).unwrap(),
+			serde_json::to_string(&match data.get_info_list() {
+				Ok(infos) => json!({"success": true, "data": infos}),
+				Err(e) => json!({"success": false, "error": e.to_string()}),
+			})
+			.unwrap(),
 		)
 }
 
 #[get("/api/analysis")]
-async fn get_analysis(data: Data<Arc<Database>>) -> HttpResponse {
-	let teams = analysis::analyze_data(&data);
+async fn get_analysis(
+	data: Data<Arc<Database>>,
+	options: web::Query<analysis::AnalysisOptions>,
+	filter: web::Query<filter::MatchFilter>,
+) -> HttpResponse {
+	let mut options = options.into_inner();
+	options.filter = filter.into_inner();
+	if let Err(error) = options.validate(&data) {
+		return HttpResponse::build(StatusCode::OK)
+			.content_type(ContentType::json())
+			.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+			.body(serde_json::to_string(&json!({"success": false, "error": error})).unwrap());
+	}
+	let teams = analysis::analyze_data(&data, &options);
 	HttpResponse::build(StatusCode::OK)
 		.content_type(ContentType::json())
 		.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
@@ -87,6 +182,8 @@ async fn get_analysis(data: Data<Arc<Database>>) -> HttpResponse {
 #[derive(Debug, Deserialize)]
 struct TeamInfoQueryParams {
 	team: u32,
+	window: Option<usize>,
+	decay: Option<f32>,
 }
 
 #[get("/api/team_info")]
@@ -94,7 +191,17 @@ async fn get_team_info(
 	data: Data<Arc<Database>>,
 	params: web::Query<TeamInfoQueryParams>,
 ) -> HttpResponse {
-	let team = team_info::get_team_info(&data, params.team);
+	if let Err(error) = analysis::validate_decay(params.decay) {
+		return HttpResponse::build(StatusCode::OK)
+			.content_type(ContentType::json())
+			.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+			.body(serde_json::to_string(&json!({"success": false, "error": error})).unwrap());
+	}
+	let trend_options = trends::TrendOptions {
+		window: params.window,
+		decay: params.decay,
+	};
+	let team = team_info::get_team_info(&data, params.team, &trend_options);
 	HttpResponse::build(StatusCode::OK)
 		.content_type(ContentType::json())
 		.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
@@ -132,34 +239,729 @@ async fn get_match_info(
 	}
 }
 
-#[get("/api/csv")]
-async fn get_csv(data: Data<Arc<Database>>) -> HttpResponse {
-	let mut csv = MatchInfo::HEADER.to_string();
-	for match_info in data.get_all_matches() {
-		csv.push_str(&match_info.unwrap().write_csv_line());
+#[derive(Debug, Deserialize)]
+struct ProjectionQueryParams {
+	simulations: Option<u32>,
+}
+
+#[get("/api/projections")]
+async fn get_projections(
+	data: Data<Arc<Database>>,
+	params: web::Query<ProjectionQueryParams>,
+) -> HttpResponse {
+	let projections = projection::project_rankings(
+		&data,
+		params
+			.simulations
+			.unwrap_or(projection::DEFAULT_SIMULATIONS),
+	);
+	HttpResponse::build(StatusCode::OK)
+		.content_type(ContentType::json())
+		.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+		.body(serde_json::to_string(&json!({"success": true, "data": projections})).unwrap())
+}
+
+// Read a comma separated list of the teams on one alliance.
+fn parse_alliance(teams: &str) -> Result<Vec<u32>, String> {
+	match teams
+		.split(',')
+		.map(|team| team.trim().parse::<u32>())
+		.collect::<Result<Vec<_>, _>>()
+	{
+		Ok(alliance) if !alliance.is_empty() && alliance.len() <= 3 => Ok(alliance),
+		_ => Err("Teams must be one to three team numbers separated by commas.".to_string()),
+	}
+}
+
+#[derive(Debug, Deserialize)]
+struct AllianceAnalysisQueryParams {
+	// Comma separated team numbers.
+	teams: String,
+}
+
+#[get("/api/alliance_analysis")]
+async fn get_alliance_analysis(
+	data: Data<Arc<Database>>,
+	params: web::Query<AllianceAnalysisQueryParams>,
+) -> HttpResponse {
+	let alliance = match parse_alliance(&params.teams) {
+		Ok(alliance) => alliance,
+		Err(error) => {
+			return HttpResponse::build(StatusCode::OK)
+				.content_type(ContentType::json())
+				.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+				.body(serde_json::to_string(&json!({"success": false, "error": error})).unwrap())
+		}
+	};
+	let teams = prediction::team_map(analysis::analyze_data(
+		&data,
+		&analysis::AnalysisOptions::default(),
+	));
+	let alliance_analysis = alliance_analysis::analyze_alliance(&teams, &alliance);
+	HttpResponse::build(StatusCode::OK)
+		.content_type(ContentType::json())
+		.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+		.body(serde_json::to_string(&json!({"success": true, "data": alliance_analysis})).unwrap())
+}
+
+#[get("/api/endgame")]
+async fn get_endgame(
+	data: Data<Arc<Database>>,
+	params: web::Query<AllianceAnalysisQueryParams>,
+) -> HttpResponse {
+	let alliance = match parse_alliance(&params.teams) {
+		Ok(alliance) => alliance,
+		Err(error) => {
+			return HttpResponse::build(StatusCode::OK)
+				.content_type(ContentType::json())
+				.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+				.body(serde_json::to_string(&json!({"success": false, "error": error})).unwrap())
+		}
+	};
+	let teams = prediction::team_map(analysis::analyze_data(
+		&data,
+		&analysis::AnalysisOptions::default(),
+	));
+	let endgame = endgame::analyze_endgame(&teams, &alliance);
+	HttpResponse::build(StatusCode::OK)
+		.content_type(ContentType::json())
+		.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+		.body(serde_json::to_string(&json!({"success": true, "data": endgame})).unwrap())
+}
+
+#[derive(Debug, Deserialize)]
+struct ScheduleStrengthQueryParams {
+	team: Option<u32>,
+}
+
+#[get("/api/schedule_strength")]
+async fn get_schedule_strength(
+	data: Data<Arc<Database>>,
+	params: web::Query<ScheduleStrengthQueryParams>,
+) -> HttpResponse {
+	let mut schedules = schedule_strength::get_schedule_strength(&data);
+	if let Some(team) = params.team {
+		schedules.retain(|s| s.team_number == team);
+		if schedules.is_empty() {
+			return HttpResponse::build(StatusCode::OK)
+				.content_type(ContentType::json())
+				.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+				.body(
+					serde_json::to_string(
+						&json!({"success": false, "error": format!("Team {} has no qualification matches.", team)}),
+					)
+					.unwrap(),
+				);
+		}
+	}
+	HttpResponse::build(StatusCode::OK)
+		.content_type(ContentType::json())
+		.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+		.body(serde_json::to_string(&json!({"success": true, "data": schedules})).unwrap())
+}
+
+#[get("/api/scouts")]
+async fn get_scouts(data: Data<Arc<Database>>) -> HttpResponse {
+	match scouts::get_scout_stats(&data) {
+		Ok(scouts) => HttpResponse::build(StatusCode::OK)
+			.content_type(ContentType::json())
+			.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+			.body(serde_json::to_string(&json!({"success": true, "data": scouts})).unwrap()),
+		Err(e) => HttpResponse::build(StatusCode::OK)
+			.content_type(ContentType::json())
+			.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+			.body(
+				serde_json::to_string(&json!({"success": false, "error": e.to_string()})).unwrap(),
+			),
+	}
+}
+
+#[get("/api/coverage")]
+async fn get_coverage(data: Data<Arc<Database>>) -> HttpResponse {
+	match coverage::get_coverage(&data) {
+		Ok(report) => HttpResponse::build(StatusCode::OK)
+			.content_type(ContentType::json())
+			.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+			.body(serde_json::to_string(&json!({"success": true, "data": report})).unwrap()),
+		Err(e) => HttpResponse::build(StatusCode::OK)
+			.content_type(ContentType::json())
+			.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+			.body(
+				serde_json::to_string(&json!({"success": false, "error": e.to_string()})).unwrap(),
+			),
+	}
+}
+
+#[get("/api/pick_lists")]
+async fn get_pick_lists(data: Data<Arc<Database>>) -> HttpResponse {
+	match data.get_all_pick_lists() {
+		Ok(stored) => {
+			let mut pick_lists = stored.iter().map(|p| p.visible()).collect::<Vec<_>>();
+			for name in pick_list::DEFAULT_PICK_LISTS.iter() {
+				if !pick_lists.iter().any(|p| p.name == *name) {
+					pick_lists.push(PickList::new(name));
+				}
+			}
+			HttpResponse::build(StatusCode::OK)
+				.content_type(ContentType::json())
+				.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+				.body(serde_json::to_string(&json!({"success": true, "data": pick_lists})).unwrap())
+		}
+		Err(e) => HttpResponse::build(StatusCode::OK)
+			.content_type(ContentType::json())
+			.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+			.body(
+				serde_json::to_string(&json!({"success": false, "error": e.to_string()})).unwrap(),
+			),
+	}
+}
+
+#[derive(Debug, Deserialize)]
+struct PickListEditRequest {
+	name: String,
+	edits: Vec<PickListEdit>,
+}
+
+#[options("/api/pick_list")]
+async fn pick_list_options(_params: ()) -> HttpResponse {
+	HttpResponse::build(StatusCode::OK)
+		.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+		.append_header((header::ACCESS_CONTROL_ALLOW_METHODS, "PUT"))
+		.append_header((header::ACCESS_CONTROL_ALLOW_HEADERS, "Content-Type"))
+		.body("")
+}
+
+#[put("/api/pick_list")]
+async fn edit_pick_list(data: Data<Arc<Database>>, body: web::Payload) -> HttpResponse {
+	let result = match read_json::<PickListEditRequest>(body).await {
+		Ok(request) => data
+			.edit_pick_list(&request.name, &request.edits)
+			.map_err(|e| e.to_string()),
+		Err(e) => Err(e),
+	};
+	match result {
+		Ok(pick_list) => HttpResponse::build(StatusCode::OK)
+			.content_type(ContentType::json())
+			.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+			.body(
+				serde_json::to_string(&json!({"success": true, "data": pick_list.visible()}))
+					.unwrap(),
+			),
+		Err(e) => HttpResponse::build(StatusCode::OK)
+			.content_type(ContentType::json())
+			.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+			.body(serde_json::to_string(&json!({"success": false, "error": e})).unwrap()),
+	}
+}
+
+#[get("/api/formulas")]
+async fn get_formulas(data: Data<Arc<Database>>) -> HttpResponse {
+	match data.get_all_formulas() {
+		Ok(formulas) => HttpResponse::build(StatusCode::OK)
+			.content_type(ContentType::json())
+			.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+			.body(serde_json::to_string(&json!({"success": true, "data": formulas})).unwrap()),
+		Err(e) => HttpResponse::build(StatusCode::OK)
+			.content_type(ContentType::json())
+			.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+			.body(
+				serde_json::to_string(&json!({"success": false, "error": e.to_string()})).unwrap(),
+			),
 	}
+}
+
+#[options("/api/formula")]
+async fn formula_options(_params: ()) -> HttpResponse {
 	HttpResponse::build(StatusCode::OK)
-		.content_type(ContentType::plaintext())
 		.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
-		.body(csv)
+		.append_header((header::ACCESS_CONTROL_ALLOW_METHODS, "PUT, DELETE"))
+		.append_header((header::ACCESS_CONTROL_ALLOW_HEADERS, "Content-Type"))
+		.body("")
+}
+
+#[put("/api/formula")]
+async fn put_formula(data: Data<Arc<Database>>, body: web::Payload) -> HttpResponse {
+	let result = match read_json::<formula::Formula>(body).await {
+		Ok(mut new_formula) => match formula::parse(&new_formula.expression) {
+			Ok(_) if new_formula.name.is_empty() => Err("Formulas need a name.".to_string()),
+			Ok(_) => {
+				new_formula.last_modified_time = database::current_time();
+				new_formula.deleted = false;
+				data.write_formula(&new_formula).map_err(|e| e.to_string())
+			}
+			Err(e) => Err(e.to_string()),
+		},
+		Err(e) => Err(e),
+	};
+	match result {
+		Ok(()) => HttpResponse::build(StatusCode::OK)
+			.content_type(ContentType::json())
+			.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+			.body(serde_json::to_string(&json!({"success": true})).unwrap()),
+		Err(e) => HttpResponse::build(StatusCode::OK)
+			.content_type(ContentType::json())
+			.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+			.body(serde_json::to_string(&json!({"success": false, "error": e})).unwrap()),
+	}
+}
+
+#[derive(Debug, Deserialize)]
+struct FormulaQueryParams {
+	name: String,
+}
+
+#[delete("/api/formula")]
+async fn delete_formula(
+	data: Data<Arc<Database>>,
+	params: web::Query<FormulaQueryParams>,
+) -> HttpResponse {
+	match data.delete_formula(&params.name) {
+		Ok(true) => HttpResponse::build(StatusCode::OK)
+			.content_type(ContentType::json())
+			.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+			.body(serde_json::to_string(&json!({"success": true})).unwrap()),
+		Ok(false) => HttpResponse::build(StatusCode::OK)
+			.content_type(ContentType::json())
+			.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+			.body(
+				serde_json::to_string(&json!({"success": false, "error": "No such formula."}))
+					.unwrap(),
+			),
+		Err(e) => HttpResponse::build(StatusCode::OK)
+			.content_type(ContentType::json())
+			.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+			.body(
+				serde_json::to_string(&json!({"success": false, "error": e.to_string()})).unwrap(),
+			),
+	}
+}
+
+fn alliance_selection_response(data: &Database) -> HttpResponse {
+	match alliance_selection::get_overview(data) {
+		Ok(overview) => HttpResponse::build(StatusCode::OK)
+			.content_type(ContentType::json())
+			.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+			.body(serde_json::to_string(&json!({"success": true, "data": overview})).unwrap()),
+		Err(e) => HttpResponse::build(StatusCode::OK)
+			.content_type(ContentType::json())
+			.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+			.body(serde_json::to_string(&json!({"success": false, "error": e})).unwrap()),
+	}
+}
+
+#[get("/api/alliance_selection")]
+async fn get_alliance_selection(data: Data<Arc<Database>>) -> HttpResponse {
+	alliance_selection_response(&data)
+}
+
+#[options("/api/alliance_selection")]
+async fn alliance_selection_options(_params: ()) -> HttpResponse {
+	HttpResponse::build(StatusCode::OK)
+		.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+		.append_header((header::ACCESS_CONTROL_ALLOW_METHODS, "PUT"))
+		.append_header((header::ACCESS_CONTROL_ALLOW_HEADERS, "Content-Type"))
+		.body("")
+}
+
+#[put("/api/alliance_selection")]
+async fn put_alliance_selection(data: Data<Arc<Database>>, body: web::Payload) -> HttpResponse {
+	let result = match read_json::<alliance_selection::SelectionAction>(body).await {
+		Ok(action) => alliance_selection::apply_action(&data, &action),
+		Err(e) => Err(e),
+	};
+	match result {
+		Ok(()) => alliance_selection_response(&data),
+		Err(e) => HttpResponse::build(StatusCode::OK)
+			.content_type(ContentType::json())
+			.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+			.body(serde_json::to_string(&json!({"success": false, "error": e})).unwrap()),
+	}
+}
+
+#[derive(Debug, Deserialize)]
+struct AssignmentsQueryParams {
+	scout: Option<String>,
+}
+
+#[get("/api/assignments")]
+async fn get_assignments(
+	data: Data<Arc<Database>>,
+	params: web::Query<AssignmentsQueryParams>,
+) -> HttpResponse {
+	match data.get_assignments() {
+		Ok(stored) => {
+			let stored = stored.unwrap_or_default();
+			let response = match &params.scout {
+				Some(scout) => json!({"success": true, "data": stored.for_scout(scout)}),
+				None => json!({"success": true, "data": stored}),
+			};
+			HttpResponse::build(StatusCode::OK)
+				.content_type(ContentType::json())
+				.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+				.body(serde_json::to_string(&response).unwrap())
+		}
+		Err(e) => HttpResponse::build(StatusCode::OK)
+			.content_type(ContentType::json())
+			.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+			.body(
+				serde_json::to_string(&json!({"success": false, "error": e.to_string()})).unwrap(),
+			),
+	}
+}
+
+#[options("/api/assignments")]
+async fn assignments_options(_params: ()) -> HttpResponse {
+	HttpResponse::build(StatusCode::OK)
+		.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+		.append_header((header::ACCESS_CONTROL_ALLOW_METHODS, "PUT"))
+		.append_header((header::ACCESS_CONTROL_ALLOW_HEADERS, "Content-Type"))
+		.body("")
+}
+
+// Make new assignments from the roster in the body, replacing the old ones.
+#[put("/api/assignments")]
+async fn put_assignments(data: Data<Arc<Database>>, body: web::Payload) -> HttpResponse {
+	let result = match read_json::<assignments::AssignmentRequest>(body).await {
+		Ok(request) => assignments::update_assignments(&data, request),
+		Err(e) => Err(e),
+	};
+	match result {
+		Ok(assignments) => HttpResponse::build(StatusCode::OK)
+			.content_type(ContentType::json())
+			.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+			.body(serde_json::to_string(&json!({"success": true, "data": assignments})).unwrap()),
+		Err(e) => HttpResponse::build(StatusCode::OK)
+			.content_type(ContentType::json())
+			.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+			.body(serde_json::to_string(&json!({"success": false, "error": e})).unwrap()),
+	}
+}
+
+#[derive(Debug, Deserialize)]
+struct CsvQueryParams {
+	// Comma separated column names, every column is included if this is left out.
+	columns: Option<String>,
+}
+
+fn csv_response(csv: Result<String, String>, filename: &str) -> HttpResponse {
+	match csv {
+		Ok(csv) => HttpResponse::build(StatusCode::OK)
+			.content_type("text/csv; charset=utf-8")
+			.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+			.append_header((
+				header::CONTENT_DISPOSITION,
+				format!("attachment; filename=\"{}\"", filename),
+			))
+			.body(csv),
+		Err(e) => HttpResponse::build(StatusCode::OK)
+			.content_type(ContentType::json())
+			.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+			.body(serde_json::to_string(&json!({"success": false, "error": e})).unwrap()),
+	}
+}
+
+#[get("/api/csv")]
+async fn get_csv(
+	data: Data<Arc<Database>>,
+	params: web::Query<CsvQueryParams>,
+	filter: web::Query<filter::MatchFilter>,
+) -> HttpResponse {
+	let csv = filter.validate().and_then(|()| {
+		let matches = data
+			.get_all_matches()
+			.collect::<Result<Vec<_>, _>>()
+			.map_err(|e| e.to_string())?;
+		let scout_copies = filter.scout_copies(&data).map_err(|e| e.to_string())?;
+		export::Table::new(export::MATCH_COLUMNS, &filter.apply(matches, scout_copies))
+			.select(params.columns.as_deref())
+			.and_then(|table| table.to_csv())
+			.map_err(|e| e.to_string())
+	});
+	csv_response(csv, "matches.csv")
+}
+
+#[get("/api/export.xlsx")]
+async fn get_workbook(
+	data: Data<Arc<Database>>,
+	options: web::Query<analysis::AnalysisOptions>,
+	filter: web::Query<filter::MatchFilter>,
+) -> HttpResponse {
+	let mut options = options.into_inner();
+	options.filter = filter.into_inner();
+	let workbook = options
+		.validate(&data)
+		.and_then(|()| export::write_workbook(&data, &options).map_err(|e| e.to_string()));
+	match workbook {
+		Ok(workbook) => HttpResponse::build(StatusCode::OK)
+			.content_type("application/vnd.openxmlformats-officedocument.spreadsheetml.sheet")
+			.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+			.append_header((
+				header::CONTENT_DISPOSITION,
+				"attachment; filename=\"scouting.xlsx\"",
+			))
+			.body(workbook),
+		Err(e) => HttpResponse::build(StatusCode::OK)
+			.content_type(ContentType::json())
+			.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+			.body(serde_json::to_string(&json!({"success": false, "error": e})).unwrap()),
+	}
+}
+
+// Admin routes don't send CORS headers, backups are taken with curl or the command line, not from the web app.
+#[get("/api/admin/backup")]
+async fn get_backup(data: Data<Arc<Database>>, req: HttpRequest) -> HttpResponse {
+	if let Some(response) = reject_admin_request(&req) {
+		return response;
+	}
+	match backup::create_backup(&data) {
+		Ok((manifest, backup)) => HttpResponse::build(StatusCode::OK)
+			.content_type("application/gzip")
+			.append_header((
+				header::CONTENT_DISPOSITION,
+				format!(
+					"attachment; filename=\"backup-{}.tar.gz\"",
+					manifest.created
+				),
+			))
+			.body(backup),
+		Err(e) => HttpResponse::build(StatusCode::OK)
+			.content_type(ContentType::json())
+			.body(
+				serde_json::to_string(&json!({"success": false, "error": e.to_string()})).unwrap(),
+			),
+	}
+}
+
+#[derive(Debug, Deserialize)]
+struct RestoreQueryParams {
+	#[serde(default)]
+	mode: backup::RestoreMode,
+}
+
+// The body is the .tar.gz from /api/admin/backup. Nothing is changed unless the whole backup can be read.
+#[put("/api/admin/restore")]
+async fn restore(
+	data: Data<Arc<Database>>,
+	req: HttpRequest,
+	params: web::Query<RestoreQueryParams>,
+	body: web::Payload,
+) -> HttpResponse {
+	if let Some(response) = reject_admin_request(&req) {
+		return response;
+	}
+	let result = match read_bytes(body, MAX_BACKUP_SIZE).await {
+		Ok(archive) => {
+			backup::restore_backup(&data, &archive, params.mode).map_err(|e| e.to_string())
+		}
+		Err(e) => Err(e),
+	};
+	HttpResponse::build(StatusCode::OK)
+		.content_type(ContentType::json())
+		.body(
+			serde_json::to_string(&match result {
+				Ok(restored) => json!({"success": true, "data": restored}),
+				Err(e) => json!({"success": false, "error": e}),
+			})
+			.unwrap(),
+		)
+}
+
+#[get("/api/csv/pit")]
+async fn get_pit_csv(
+	data: Data<Arc<Database>>,
+	params: web::Query<CsvQueryParams>,
+	filter: web::Query<filter::MatchFilter>,
+) -> HttpResponse {
+	let csv = filter.validate().and_then(|()| {
+		let visits = data
+			.get_all_robots()
+			.collect::<Result<Vec<_>, _>>()
+			.map_err(|e| e.to_string())?;
+		let rows = export::flatten_visits(filter.apply_pit(visits));
+		export::Table::new(export::PIT_COLUMNS, &rows)
+			.select(params.columns.as_deref())
+			.and_then(|table| table.to_csv())
+			.map_err(|e| e.to_string())
+	});
+	csv_response(csv, "pit.csv")
+}
+
+#[get("/api/csv/analysis")]
+async fn get_analysis_csv(
+	data: Data<Arc<Database>>,
+	params: web::Query<CsvQueryParams>,
+	options: web::Query<analysis::AnalysisOptions>,
+	filter: web::Query<filter::MatchFilter>,
+) -> HttpResponse {
+	let mut options = options.into_inner();
+	options.filter = filter.into_inner();
+	let csv = options.validate(&data).and_then(|()| {
+		let teams = analysis::analyze_data(&data, &options);
+		export::Table::analysis(&teams)
+			.select(params.columns.as_deref())
+			.and_then(|table| table.to_csv())
+			.map_err(|e| e.to_string())
+	});
+	csv_response(csv, "analysis.csv")
 }
 
 #[derive(Debug, Deserialize)]
 struct ImgQueryParams {
-	id: u64,
+	id: String,
+	#[serde(default)]
+	size: images::ImageSize,
 }
 
+// Images never change once they're saved, since they're named by what's in them, so browsers can keep them for as long as they like.
+const IMAGE_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+
 #[get("/api/img")]
-async fn get_img(params: web::Query<ImgQueryParams>) -> HttpResponse {
-	if let Ok(data) = std::fs::read(format!("images/bot-{}.jpeg", params.id)) {
-		HttpResponse::build(StatusCode::OK)
-			.content_type(ContentType::jpeg())
-			.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
-			.body(data)
+async fn get_img(
+	req: HttpRequest,
+	data: Data<Arc<Database>>,
+	params: web::Query<ImgQueryParams>,
+) -> HttpResponse {
+	let image = if images::is_image_hash(&params.id) {
+		data.get_image(&params.id, params.size).ok().flatten()
 	} else {
-		HttpResponse::build(StatusCode::NOT_FOUND)
+		// Images saved before the image store are JPEG files named with a number, and don't have thumbnails.
+		params
+			.id
+			.parse::<u64>()
+			.ok()
+			.and_then(|id| std::fs::read(format!("images/bot-{}.jpeg", id)).ok())
+			.map(|image| ("image/jpeg".to_string(), image))
+	};
+	let (mime, image) = match image {
+		Some(image) => image,
+		None => {
+			return HttpResponse::build(StatusCode::NOT_FOUND)
+				.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+				.body("Bad image id.")
+		}
+	};
+	// Only checked once the image is known to exist, so a missing image isn't cached as if it were there.
+	let etag = format!("\"{}-{}\"", params.id, params.size.name());
+	let cached = req
+		.headers()
+		.get(header::IF_NONE_MATCH)
+		.and_then(|value| value.to_str().ok())
+		.is_some_and(|value| {
+			value
+				.split(',')
+				.any(|tag| tag.trim() == etag || tag.trim() == "*")
+		});
+	if cached {
+		return HttpResponse::build(StatusCode::NOT_MODIFIED)
+			.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+			.append_header((header::ETAG, etag))
+			.append_header((header::CACHE_CONTROL, IMAGE_CACHE_CONTROL))
+			.finish();
+	}
+	HttpResponse::build(StatusCode::OK)
+		.content_type(mime)
+		.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+		.append_header((header::ETAG, etag))
+		.append_header((header::CACHE_CONTROL, IMAGE_CACHE_CONTROL))
+		.body(image)
+}
+
+#[options("/api/img")]
+async fn img_options(_params: ()) -> HttpResponse {
+	HttpResponse::build(StatusCode::OK)
+		.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+		.append_header((header::ACCESS_CONTROL_ALLOW_METHODS, "PUT"))
+		.append_header((header::ACCESS_CONTROL_ALLOW_HEADERS, "Content-Type"))
+		.body("")
+}
+
+// Change an image's caption or tags, mark it as the team's preferred image, or delete it.
+#[put("/api/img")]
+async fn edit_img(data: Data<Arc<Database>>, body: web::Payload) -> HttpResponse {
+	let result = match read_json::<images::ImageEdit>(body).await {
+		Ok(edit) => data
+			.edit_image(&edit)
+			.map_err(|e| e.to_string())
+			.and_then(|meta| meta.ok_or_else(|| "No such image.".to_string())),
+		Err(e) => Err(e),
+	};
+	match result {
+		Ok(meta) => HttpResponse::build(StatusCode::OK)
+			.content_type(ContentType::json())
 			.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
-			.body("Bad image id.")
+			.body(serde_json::to_string(&json!({"success": true, "data": meta})).unwrap()),
+		Err(e) => HttpResponse::build(StatusCode::OK)
+			.content_type(ContentType::json())
+			.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+			.body(serde_json::to_string(&json!({"success": false, "error": e})).unwrap()),
+	}
+}
+
+#[derive(Debug, Deserialize)]
+struct ImagesQueryParams {
+	team: Option<u32>,
+}
+
+// Every image's details for syncing, or just one team's images that haven't been deleted, preferred first.
+#[get("/api/images")]
+async fn get_images(
+	data: Data<Arc<Database>>,
+	params: web::Query<ImagesQueryParams>,
+) -> HttpResponse {
+	let all_meta = match params.team {
+		Some(team) => data.get_team_image_meta(team),
+		None => data.get_all_image_meta(),
+	};
+	match all_meta {
+		Ok(all_meta) => HttpResponse::build(StatusCode::OK)
+			.content_type(ContentType::json())
+			.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+			.body(serde_json::to_string(&json!({"success": true, "data": all_meta})).unwrap()),
+		Err(e) => HttpResponse::build(StatusCode::OK)
+			.content_type(ContentType::json())
+			.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+			.body(
+				serde_json::to_string(&json!({"success": false, "error": e.to_string()})).unwrap(),
+			),
+	}
+}
+
+#[options("/api/images")]
+async fn images_options(_params: ()) -> HttpResponse {
+	HttpResponse::build(StatusCode::OK)
+		.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+		.append_header((header::ACCESS_CONTROL_ALLOW_METHODS, "PUT"))
+		.append_header((header::ACCESS_CONTROL_ALLOW_HEADERS, "Content-Type"))
+		.body("")
+}
+
+// Images sent by another server while syncing.
+#[put("/api/images")]
+async fn put_images(data: Data<Arc<Database>>, body: web::Payload) -> HttpResponse {
+	let result = read_json::<Vec<images::ImageUpload>>(body)
+		.await
+		.and_then(|uploads| {
+			for upload in uploads.iter() {
+				match &upload.data {
+					Some(image) => {
+						let image = base64::decode(image).map_err(|e| e.to_string())?;
+						data.write_image_with_meta(&upload.meta, &image)
+					}
+					None => data.merge_image_meta(&upload.meta),
+				}
+				.map_err(|e| e.to_string())?;
+			}
+			Ok(())
+		});
+	match result {
+		Ok(()) => HttpResponse::build(StatusCode::OK)
+			.content_type(ContentType::json())
+			.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+			.body(serde_json::to_string(&json!({"success": true})).unwrap()),
+		Err(e) => HttpResponse::build(StatusCode::OK)
+			.content_type(ContentType::json())
+			.append_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
+			.body(serde_json::to_string(&json!({"success": false, "error": e})).unwrap()),
 	}
 }
 
@@ -181,6 +983,34 @@ async fn main() {
 	)
 	.unwrap();
 	let database = Arc::new(Database::open(&PathBuf::from_str("matches.db").unwrap()));
+	if let Some(command) = &config.command {
+		if let Err(e) = cli::run(command, &database) {
+			eprintln!("{}", e);
+			std::process::exit(1);
+		}
+		if !matches!(command, cli::Command::Serve(_)) {
+			return;
+		}
+	}
+	if let (Some(directory), Some(minutes)) = (&config.backup_directory, config.backup_interval) {
+		let directory = directory.to_owned();
+		let database = database.clone();
+		println!(
+			"Backing up to {} every {} minutes",
+			directory.display(),
+			minutes
+		);
+		tokio::spawn(async move {
+			let mut interval =
+				tokio::time::interval(tokio::time::Duration::from_secs(minutes.max(1) * 60));
+			loop {
+				interval.tick().await;
+				if let Err(e) = backup::run_scheduled_backup(&database, &directory) {
+					eprintln!("Error backing up: {}", e);
+				}
+			}
+		});
+	}
 	if let Some(leader_url) = &config.leader_url {
 		let leader_url = leader_url.to_owned();
 		let database = database.clone();
@@ -205,10 +1035,39 @@ async fn main() {
 			.service(push_options)
 			.service(pull_data)
 			.service(get_csv)
+			.service(get_pit_csv)
+			.service(get_analysis_csv)
+			.service(get_workbook)
+			.service(get_backup)
+			.service(restore)
 			.service(get_analysis)
 			.service(get_img)
+			.service(img_options)
+			.service(edit_img)
+			.service(get_images)
+			.service(images_options)
+			.service(put_images)
 			.service(get_team_info)
 			.service(get_match_info)
+			.service(get_projections)
+			.service(get_alliance_analysis)
+			.service(get_schedule_strength)
+			.service(get_endgame)
+			.service(get_scouts)
+			.service(get_coverage)
+			.service(get_pick_lists)
+			.service(pick_list_options)
+			.service(edit_pick_list)
+			.service(get_formulas)
+			.service(formula_options)
+			.service(put_formula)
+			.service(delete_formula)
+			.service(get_alliance_selection)
+			.service(alliance_selection_options)
+			.service(put_alliance_selection)
+			.service(get_assignments)
+			.service(assignments_options)
+			.service(put_assignments)
 			.service(Files::new("/dist", "../client/dist/").prefer_utf8(true))
 			.service(
 				Files::new("/", "../client/assets/")
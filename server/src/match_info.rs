@@ -2,8 +2,9 @@ use std::collections::HashMap;
 
 use serde::Serialize;
 
-use crate::analysis::RawMatchData;
+use crate::analysis::{self, AnalysisOptions, RawAllianceData, RawMatchData};
 use crate::data::{MatchType};
+use crate::prediction::{self, MatchPrediction};
 use crate::{Database, MatchInfo};
 
 #[derive(Debug, Serialize)]
@@ -14,6 +15,8 @@ pub struct FullMatchInfo {
 	red_teams: Vec<(u32, Option<MatchInfo>)>,
 	blue_score: i32,
 	red_score: i32,
+	// Only given for matches that haven't been played yet.
+	prediction: Option<MatchPrediction>,
 }
 
 fn team_key_to_number(team_key: &str) -> u32 {
@@ -46,6 +49,25 @@ pub fn get_match_info(database: &Database, match_number: u32) -> Option<FullMatc
 				.into_string()
 				.map(|data| serde_json::from_str::<RawMatchData>(&data))
 			{
+				let prediction = if tba_match.actual_time.is_none() {
+					let teams = prediction::team_map(analysis::analyze_data(
+						database,
+						&AnalysisOptions::default(),
+					));
+					let alliance_teams = |alliance: &RawAllianceData| {
+						alliance
+							.all_team_keys()
+							.map(|team| team_key_to_number(team))
+							.collect::<Vec<_>>()
+					};
+					Some(prediction::predict_match(
+						&teams,
+						&alliance_teams(&tba_match.alliances.blue),
+						&alliance_teams(&tba_match.alliances.red),
+					))
+				} else {
+					None
+				};
 				Some(FullMatchInfo {
 					time: tba_match
 						.actual_time
@@ -54,9 +76,7 @@ pub fn get_match_info(database: &Database, match_number: u32) -> Option<FullMatc
 					blue_teams: tba_match
 						.alliances
 						.blue
-						.team_keys
-						.iter()
-						.chain(tba_match.alliances.blue.surrogate_team_keys.iter())
+						.all_team_keys()
 						.map(|team| {
 							let team_number = team_key_to_number(team);
 							(
@@ -68,9 +88,7 @@ pub fn get_match_info(database: &Database, match_number: u32) -> Option<FullMatc
 					red_teams: tba_match
 						.alliances
 						.red
-						.team_keys
-						.iter()
-						.chain(tba_match.alliances.red.surrogate_team_keys.iter())
+						.all_team_keys()
 						.map(|team| {
 							let team_number = team_key_to_number(team);
 							(
@@ -81,6 +99,7 @@ pub fn get_match_info(database: &Database, match_number: u32) -> Option<FullMatc
 						.collect(),
 					blue_score: tba_match.alliances.blue.score,
 					red_score: tba_match.alliances.red.score,
+					prediction,
 				})
 			} else {
 				None
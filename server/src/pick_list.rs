@@ -0,0 +1,281 @@
+use serde::{Deserialize, Serialize};
+
+// Lists that always show up, even before anyone has put a team on them.
+pub const DEFAULT_PICK_LISTS: [&str; 3] = ["first pick", "second pick", "do not pick"];
+
+// Smallest gap between two positions before the list gets renumbered.
+const MIN_POSITION_GAP: f64 = 1e-9;
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct PickListEntry {
+	#[serde(rename = "team")]
+	pub team_number: u32,
+	// Entries are shown sorted by position, moving a team puts it halfway between its new neighbours.
+	pub position: f64,
+	pub comment: String,
+	pub tags: Vec<String>,
+	// Teams that were already picked by someone stay on the list but get struck through.
+	pub picked: bool,
+	// Removed entries are kept so the removal can be synchronized.
+	pub removed: bool,
+	pub last_modified_time: u64,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct PickList {
+	pub name: String,
+	pub entries: Vec<PickListEntry>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[serde(tag = "type")]
+pub enum PickListEdit {
+	// Add a team (or move it if it's already there) so that it ends up at this index in the list.
+	Move { team: u32, index: usize },
+	Remove { team: u32 },
+	Comment { team: u32, comment: String },
+	Tags { team: u32, tags: Vec<String> },
+	Picked { team: u32, picked: bool },
+}
+
+impl PickList {
+	pub fn new(name: &str) -> Self {
+		PickList {
+			name: name.to_string(),
+			entries: Vec::new(),
+		}
+	}
+
+	// Entries that are still on the list, in order.
+	pub fn visible_entries(&self) -> Vec<&PickListEntry> {
+		let mut entries = self
+			.entries
+			.iter()
+			.filter(|e| !e.removed)
+			.collect::<Vec<_>>();
+		entries.sort_by(|a, b| {
+			a.position
+				.total_cmp(&b.position)
+				.then(a.team_number.cmp(&b.team_number))
+		});
+		entries
+	}
+
+	// Copy of this list with only the entries that are still on it, in order.
+	pub fn visible(&self) -> PickList {
+		PickList {
+			name: self.name.clone(),
+			entries: self.visible_entries().into_iter().cloned().collect(),
+		}
+	}
+
+	fn entry_mut(&mut self, team_number: u32, time: u64) -> &mut PickListEntry {
+		let index = match self
+			.entries
+			.iter()
+			.position(|e| e.team_number == team_number)
+		{
+			Some(index) => index,
+			None => {
+				let position = self
+					.visible_entries()
+					.last()
+					.map(|e| e.position + 1.0)
+					.unwrap_or(0.0);
+				self.entries.push(PickListEntry {
+					team_number,
+					position,
+					..PickListEntry::default()
+				});
+				self.entries.len() - 1
+			}
+		};
+		let entry = &mut self.entries[index];
+		entry.last_modified_time = time;
+		entry
+	}
+
+	fn move_team(&mut self, team_number: u32, index: usize, time: u64) {
+		let others = self
+			.visible_entries()
+			.into_iter()
+			.filter(|e| e.team_number != team_number)
+			.map(|e| e.position)
+			.collect::<Vec<_>>();
+		let index = index.min(others.len());
+		let position = match (index.checked_sub(1).map(|i| others[i]), others.get(index)) {
+			(None, None) => 0.0,
+			(None, Some(next)) => next - 1.0,
+			(Some(previous), None) => previous + 1.0,
+			(Some(previous), Some(next)) => (previous + next) / 2.0,
+		};
+		let too_close = others
+			.iter()
+			.any(|other| (other - position).abs() < MIN_POSITION_GAP);
+		let entry = self.entry_mut(team_number, time);
+		entry.position = position;
+		entry.removed = false;
+		if too_close {
+			self.renumber(time);
+		}
+	}
+
+	// Spread the positions back out once they've been split too many times.
+	fn renumber(&mut self, time: u64) {
+		let order = self
+			.visible_entries()
+			.into_iter()
+			.map(|e| e.team_number)
+			.collect::<Vec<_>>();
+		for (position, team_number) in order.into_iter().enumerate() {
+			let entry = self.entry_mut(team_number, time);
+			entry.position = position as f64;
+		}
+	}
+
+	pub fn apply(&mut self, edit: &PickListEdit, time: u64) {
+		match edit {
+			PickListEdit::Move { team, index } => self.move_team(*team, *index, time),
+			PickListEdit::Remove { team } => self.entry_mut(*team, time).removed = true,
+			PickListEdit::Comment { team, comment } => {
+				self.entry_mut(*team, time).comment = comment.clone()
+			}
+			PickListEdit::Tags { team, tags } => self.entry_mut(*team, time).tags = tags.clone(),
+			PickListEdit::Picked { team, picked } => self.entry_mut(*team, time).picked = *picked,
+		}
+	}
+
+	// Combine with a copy of the same list from somewhere else, keeping the newest version of every entry.
+	pub fn merge(&mut self, other: &PickList) {
+		for other_entry in other.entries.iter() {
+			match self
+				.entries
+				.iter_mut()
+				.find(|e| e.team_number == other_entry.team_number)
+			{
+				Some(entry) => {
+					if other_entry.last_modified_time > entry.last_modified_time {
+						*entry = other_entry.clone();
+					}
+				}
+				None => self.entries.push(other_entry.clone()),
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn order(pick_list: &PickList) -> Vec<u32> {
+		pick_list
+			.visible_entries()
+			.iter()
+			.map(|e| e.team_number)
+			.collect()
+	}
+
+	fn list_of(teams: &[u32]) -> PickList {
+		let mut pick_list = PickList::new("first pick");
+		for (index, team) in teams.iter().enumerate() {
+			pick_list.apply(&PickListEdit::Move { team: *team, index }, 1);
+		}
+		pick_list
+	}
+
+	#[test]
+	fn move_clamps_index_to_the_end() {
+		let mut pick_list = list_of(&[1, 2, 3]);
+		pick_list.apply(
+			&PickListEdit::Move {
+				team: 4,
+				index: 100,
+			},
+			2,
+		);
+		assert_eq!(order(&pick_list), vec![1, 2, 3, 4]);
+		pick_list.apply(
+			&PickListEdit::Move {
+				team: 1,
+				index: 100,
+			},
+			3,
+		);
+		assert_eq!(order(&pick_list), vec![2, 3, 4, 1]);
+		pick_list.apply(&PickListEdit::Move { team: 1, index: 0 }, 4);
+		assert_eq!(order(&pick_list), vec![1, 2, 3, 4]);
+	}
+
+	#[test]
+	fn renumbers_once_positions_get_too_close() {
+		let mut pick_list = list_of(&[1, 2]);
+		// Each move halves the gap after team 1, so this goes well past the smallest gap allowed.
+		for team in 3..60 {
+			pick_list.apply(&PickListEdit::Move { team, index: 1 }, 2);
+		}
+		let mut expected = vec![1];
+		expected.extend((3..60).rev());
+		expected.push(2);
+		assert_eq!(order(&pick_list), expected);
+		let positions = pick_list
+			.visible_entries()
+			.iter()
+			.map(|e| e.position)
+			.collect::<Vec<_>>();
+		assert!(positions
+			.windows(2)
+			.all(|pair| pair[1] - pair[0] >= MIN_POSITION_GAP));
+	}
+
+	#[test]
+	fn moving_a_removed_team_brings_it_back() {
+		let mut pick_list = list_of(&[1, 2, 3]);
+		pick_list.apply(&PickListEdit::Remove { team: 2 }, 2);
+		assert_eq!(order(&pick_list), vec![1, 3]);
+		pick_list.apply(&PickListEdit::Move { team: 2, index: 0 }, 3);
+		assert_eq!(order(&pick_list), vec![2, 1, 3]);
+		assert_eq!(pick_list.entries.len(), 3);
+	}
+
+	#[test]
+	fn merge_keeps_the_newer_entry_either_way() {
+		let mut older = list_of(&[1, 2]);
+		older.apply(
+			&PickListEdit::Comment {
+				team: 1,
+				comment: "older".to_string(),
+			},
+			10,
+		);
+		let mut newer = list_of(&[1, 3]);
+		newer.apply(
+			&PickListEdit::Comment {
+				team: 1,
+				comment: "newer".to_string(),
+			},
+			20,
+		);
+
+		let mut merged_into_older = older.clone();
+		merged_into_older.merge(&newer);
+		let mut merged_into_newer = newer.clone();
+		merged_into_newer.merge(&older);
+		for merged in [merged_into_older, merged_into_newer] {
+			let entry = merged.entries.iter().find(|e| e.team_number == 1).unwrap();
+			assert_eq!(entry.comment, "newer");
+			let mut teams = merged
+				.entries
+				.iter()
+				.map(|e| e.team_number)
+				.collect::<Vec<_>>();
+			teams.sort_unstable();
+			assert_eq!(teams, vec![1, 2, 3]);
+		}
+	}
+}
@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::analysis::TeamInfo;
+use crate::data::TeleopChargeStation;
+use crate::endgame;
+use crate::season::{
+	AUTO_DOCKED_POINTS, AUTO_ENGAGED_POINTS, NODES_PER_LINK, PARK_POINTS, SUSTAINABILITY_LINKS,
+	TELEOP_DOCKED_POINTS, TELEOP_ENGAGED_POINTS, WIN_RANKING_POINTS,
+};
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EndgameLikelihood {
+	pub team_number: u32,
+	pub state: TeleopChargeStation,
+	pub probability: f32,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AlliancePrediction {
+	pub teams: Vec<u32>,
+	// Teams that haven't been scouted yet, these are predicted using the average team.
+	pub unscouted_teams: Vec<u32>,
+	pub score: f32,
+	pub std_dev: f32,
+	pub auto_score: f32,
+	pub auto_charge_station_score: f32,
+	pub teleop_grid_score: f32,
+	pub endgame_score: f32,
+	pub endgame: Vec<EndgameLikelihood>,
+	pub activation_bonus_probability: f32,
+	pub sustainability_bonus_probability: f32,
+	pub expected_ranking_points: f32,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MatchPrediction {
+	pub blue: AlliancePrediction,
+	pub red: AlliancePrediction,
+	pub blue_win_probability: f32,
+	pub red_win_probability: f32,
+}
+
+// Standard normal cumulative distribution function, using the Abramowitz and Stegun approximation of erf.
+pub fn normal_cdf(x: f32) -> f32 {
+	let z = x.abs() / std::f32::consts::SQRT_2;
+	let t = 1.0 / (1.0 + 0.3275911 * z);
+	let erf = 1.0
+		- (((((1.0614054 * t - 1.4531521) * t) + 1.4214138) * t - 0.28449672) * t + 0.2548296)
+			* t * (-z * z).exp();
+	if x >= 0.0 {
+		0.5 * (1.0 + erf)
+	} else {
+		0.5 * (1.0 - erf)
+	}
+}
+
+// Probability that a normally distributed value is at least the threshold.
+pub fn probability_at_least(mean: f32, variance: f32, threshold: f32) -> f32 {
+	if variance <= 0.0 {
+		return if mean >= threshold { 1.0 } else { 0.0 };
+	}
+	1.0 - normal_cdf((threshold - mean) / variance.sqrt())
+}
+
+// Probability that the first value is bigger than the second, when both are normally distributed.
+pub fn win_probability(mean: f32, variance: f32, other_mean: f32, other_variance: f32) -> f32 {
+	let variance = variance + other_variance;
+	if variance <= 0.0 {
+		return if mean > other_mean {
+			1.0
+		} else if mean < other_mean {
+			0.0
+		} else {
+			0.5
+		};
+	}
+	normal_cdf((mean - other_mean) / variance.sqrt())
+}
+
+// Turn the list from analyze_data into a lookup by team number, the average team is kept under team 0.
+pub fn team_map(teams: Vec<TeamInfo>) -> HashMap<u32, TeamInfo> {
+	teams
+		.into_iter()
+		.map(|team| (team.team_number, team))
+		.collect()
+}
+
+fn likely_endgame(team_number: u32, team: &TeamInfo) -> EndgameLikelihood {
+	let (state, probability) = [
+		(TeleopChargeStation::Off, team.charge_station_teleop_off),
+		(
+			TeleopChargeStation::Parked,
+			team.charge_station_teleop_parked,
+		),
+		(TeleopChargeStation::On, team.charge_station_teleop_on),
+		(
+			TeleopChargeStation::Charged,
+			team.charge_station_teleop_charged,
+		),
+	]
+	.iter()
+	.copied()
+	.fold((TeleopChargeStation::Off, 0.0), |best, next| {
+		if next.1 > best.1 {
+			next
+		} else {
+			best
+		}
+	});
+	EndgameLikelihood {
+		team_number,
+		state,
+		probability,
+	}
+}
+
+fn predict_alliance(teams: &HashMap<u32, TeamInfo>, alliance: &[u32]) -> AlliancePrediction {
+	let default_team = TeamInfo::default();
+	let average_team = teams.get(&0).unwrap_or(&default_team);
+	let mut prediction = AlliancePrediction {
+		teams: alliance.to_vec(),
+		unscouted_teams: Vec::new(),
+		score: 0.0,
+		std_dev: 0.0,
+		auto_score: 0.0,
+		auto_charge_station_score: 0.0,
+		teleop_grid_score: 0.0,
+		endgame_score: 0.0,
+		endgame: Vec::new(),
+		activation_bonus_probability: 0.0,
+		sustainability_bonus_probability: 0.0,
+		expected_ranking_points: 0.0,
+	};
+	let mut variance = 0.0;
+	let (mut game_pieces_mean, mut game_pieces_variance) = (0.0, 0.0);
+	for team_number in alliance {
+		let team = match teams.get(team_number) {
+			Some(team) if *team_number != 0 => team,
+			_ => {
+				prediction.unscouted_teams.push(*team_number);
+				average_team
+			}
+		};
+		prediction.score += team.average_auto_score + team.average_teleop_score;
+		variance += team.auto_score_variance + team.teleop_score_variance;
+		prediction.auto_score += team.average_auto_score;
+		prediction.auto_charge_station_score += team.charge_station_auto_on * AUTO_DOCKED_POINTS
+			+ team.charge_station_auto_charged * AUTO_ENGAGED_POINTS;
+		prediction.teleop_grid_score += team.average_teleop_hybrid_score
+			+ team.average_teleop_middle_score
+			+ team.average_teleop_high_score;
+		prediction.endgame_score += team.charge_station_teleop_parked * PARK_POINTS
+			+ team.charge_station_teleop_on * TELEOP_DOCKED_POINTS
+			+ team.charge_station_teleop_charged * TELEOP_ENGAGED_POINTS;
+		prediction.endgame.push(likely_endgame(*team_number, team));
+		game_pieces_mean += team.average_game_pieces;
+		game_pieces_variance += team.game_pieces_variance;
+	}
+	prediction.std_dev = variance.sqrt();
+	prediction.activation_bonus_probability =
+		endgame::analyze_endgame(teams, alliance).activation_bonus_probability;
+	prediction.sustainability_bonus_probability = probability_at_least(
+		game_pieces_mean,
+		game_pieces_variance,
+		SUSTAINABILITY_LINKS * NODES_PER_LINK - 0.5,
+	);
+	prediction
+}
+
+// Predict both alliances' scores from the scouted averages and variances, treating every robot's score as independent and normally distributed.
+pub fn predict_match(teams: &HashMap<u32, TeamInfo>, blue: &[u32], red: &[u32]) -> MatchPrediction {
+	let mut blue = predict_alliance(teams, blue);
+	let mut red = predict_alliance(teams, red);
+	let blue_win_probability = win_probability(
+		blue.score,
+		blue.std_dev * blue.std_dev,
+		red.score,
+		red.std_dev * red.std_dev,
+	);
+	let red_win_probability = 1.0 - blue_win_probability;
+	blue.expected_ranking_points = blue_win_probability * WIN_RANKING_POINTS
+		+ blue.activation_bonus_probability
+		+ blue.sustainability_bonus_probability;
+	red.expected_ranking_points = red_win_probability * WIN_RANKING_POINTS
+		+ red.activation_bonus_probability
+		+ red.sustainability_bonus_probability;
+	MatchPrediction {
+		blue,
+		red,
+		blue_win_probability,
+		red_win_probability,
+	}
+}
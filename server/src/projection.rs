@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+
+use rand::Rng;
+use serde::Serialize;
+
+use crate::analysis::{self, AnalysisOptions};
+use crate::data::MatchType;
+use crate::prediction;
+use crate::season::WIN_RANKING_POINTS;
+use crate::Database;
+
+pub const DEFAULT_SIMULATIONS: u32 = 1000;
+const MAX_SIMULATIONS: u32 = 100000;
+// How many alliance captains there are in alliance selection.
+const TOP_RANKS: usize = 8;
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TeamProjection {
+	pub team_number: u32,
+	pub matches_played: u32,
+	pub remaining_matches: u32,
+	pub current_ranking_points: f32,
+	pub projected_ranking_points: f32,
+	pub projected_average_ranking_points: f32,
+	pub projected_rank: f32,
+	pub best_rank: u32,
+	pub worst_rank: u32,
+	pub top_eight_probability: f32,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Projections {
+	pub simulations: u32,
+	pub remaining_matches: u32,
+	pub teams: Vec<TeamProjection>,
+}
+
+// The ranking point chances for one alliance in one of the remaining matches.
+struct RemainingAlliance {
+	teams: Vec<u32>,
+	win_probability: f32,
+	activation_bonus_probability: f32,
+	sustainability_bonus_probability: f32,
+}
+
+// Simulate the rest of the qualification matches many times using the match prediction model, and see where everyone ends up.
+pub fn project_rankings(database: &Database, simulations: u32) -> Projections {
+	let simulations = simulations.clamp(1, MAX_SIMULATIONS);
+	let (tba_teams, tba_matches) = analysis::get_tba_data();
+	let teams = prediction::team_map(analysis::analyze_data(
+		database,
+		&AnalysisOptions::default(),
+	));
+
+	let mut projections = HashMap::new();
+	for (team_number, tba_team) in tba_teams.iter() {
+		projections.insert(
+			*team_number,
+			TeamProjection {
+				team_number: *team_number,
+				matches_played: tba_team.matches_played,
+				current_ranking_points: (tba_team.ranking_points * tba_team.matches_played as f32)
+					.round(),
+				best_rank: u32::MAX,
+				..TeamProjection::default()
+			},
+		);
+	}
+
+	let mut remaining = Vec::new();
+	for ((match_type, _), tba_match) in tba_matches.iter() {
+		if *match_type != MatchType::Qualification || tba_match.played {
+			continue;
+		}
+		let prediction =
+			prediction::predict_match(&teams, &tba_match.blue_teams, &tba_match.red_teams);
+		remaining.push((
+			RemainingAlliance {
+				teams: tba_match.blue_teams.clone(),
+				win_probability: prediction.blue_win_probability,
+				activation_bonus_probability: prediction.blue.activation_bonus_probability,
+				sustainability_bonus_probability: prediction.blue.sustainability_bonus_probability,
+			},
+			RemainingAlliance {
+				teams: tba_match.red_teams.clone(),
+				win_probability: prediction.red_win_probability,
+				activation_bonus_probability: prediction.red.activation_bonus_probability,
+				sustainability_bonus_probability: prediction.red.sustainability_bonus_probability,
+			},
+		));
+		for team_number in tba_match
+			.blue_teams
+			.iter()
+			.chain(tba_match.red_teams.iter())
+		{
+			projections
+				.entry(*team_number)
+				.or_insert_with(|| TeamProjection {
+					team_number: *team_number,
+					best_rank: u32::MAX,
+					..TeamProjection::default()
+				})
+				.remaining_matches += 1;
+		}
+	}
+
+	let mut rng = rand::thread_rng();
+	let team_numbers = projections.keys().copied().collect::<Vec<_>>();
+	for _ in 0..simulations {
+		let mut ranking_points = team_numbers
+			.iter()
+			.map(|team_number| {
+				(
+					*team_number,
+					projections[team_number].current_ranking_points,
+				)
+			})
+			.collect::<HashMap<_, _>>();
+		for (blue, red) in remaining.iter() {
+			let blue_won = rng.gen::<f32>() < blue.win_probability;
+			for (alliance, won) in [(blue, blue_won), (red, !blue_won)].iter() {
+				let mut points = if *won { WIN_RANKING_POINTS } else { 0.0 };
+				if rng.gen::<f32>() < alliance.activation_bonus_probability {
+					points += 1.0;
+				}
+				if rng.gen::<f32>() < alliance.sustainability_bonus_probability {
+					points += 1.0;
+				}
+				for team_number in alliance.teams.iter() {
+					*ranking_points.get_mut(team_number).unwrap() += points;
+				}
+			}
+		}
+
+		// Teams are ranked by their average ranking points, ties are broken randomly since we don't model the tiebreakers.
+		let mut standings = ranking_points
+			.iter()
+			.map(|(team_number, points)| {
+				let team = &projections[team_number];
+				let played = (team.matches_played + team.remaining_matches).max(1) as f32;
+				(*team_number, points / played, rng.gen::<f32>())
+			})
+			.collect::<Vec<_>>();
+		standings.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.2.total_cmp(&b.2)));
+		for (rank, (team_number, _, _)) in standings.iter().enumerate() {
+			let rank = rank as u32 + 1;
+			let team = projections.get_mut(team_number).unwrap();
+			team.projected_rank += rank as f32;
+			team.projected_ranking_points += ranking_points[team_number];
+			team.best_rank = team.best_rank.min(rank);
+			team.worst_rank = team.worst_rank.max(rank);
+			if rank as usize <= TOP_RANKS {
+				team.top_eight_probability += 1.0;
+			}
+		}
+	}
+
+	let mut teams = projections.into_values().collect::<Vec<_>>();
+	for team in teams.iter_mut() {
+		team.projected_rank /= simulations as f32;
+		team.projected_ranking_points /= simulations as f32;
+		team.top_eight_probability /= simulations as f32;
+		team.projected_average_ranking_points = team.projected_ranking_points
+			/ (team.matches_played + team.remaining_matches).max(1) as f32;
+	}
+	teams.sort_by(|a, b| a.projected_rank.total_cmp(&b.projected_rank));
+	Projections {
+		simulations,
+		remaining_matches: remaining.len() as u32,
+		teams,
+	}
+}
@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::analysis::{self, AnalysisOptions, TbaMatch, TeamInfo};
+use crate::data::MatchType;
+use crate::prediction;
+use crate::season::{TIE_RANKING_POINTS, WIN_RANKING_POINTS};
+use crate::Database;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduleMatch {
+	pub match_number: u32,
+	pub played: bool,
+	pub allies: Vec<u32>,
+	pub opponents: Vec<u32>,
+	// Expected points of the other robots on each alliance, using the scouted averages.
+	pub ally_strength: f32,
+	pub opponent_strength: f32,
+	pub win_probability: f32,
+	pub expected_ranking_points: f32,
+	pub actual_ranking_points: Option<f32>,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TeamSchedule {
+	pub team_number: u32,
+	pub played_matches: u32,
+	pub remaining_matches: u32,
+	// Averages over the played matches.
+	pub ally_strength: f32,
+	pub opponent_strength: f32,
+	// Averages over the matches that haven't been played yet.
+	pub remaining_ally_strength: f32,
+	pub remaining_opponent_strength: f32,
+	pub expected_ranking_points: f32,
+	pub actual_ranking_points: f32,
+	// How many more ranking points the team got than expected, positive means they were lucky.
+	pub luck: f32,
+	pub remaining_expected_ranking_points: f32,
+	pub matches: Vec<ScheduleMatch>,
+}
+
+fn strength(teams: &HashMap<u32, TeamInfo>, team_numbers: &[u32]) -> f32 {
+	let default_team = TeamInfo::default();
+	let average_team = teams.get(&0).unwrap_or(&default_team);
+	team_numbers
+		.iter()
+		.map(|t| teams.get(t).unwrap_or(average_team))
+		.map(|t| t.average_auto_score + t.average_teleop_score)
+		.sum()
+}
+
+// Ranking points an alliance actually got, from the TBA breakdown if it's there, otherwise just from who won.
+fn actual_ranking_points(ranking_points: Option<u32>, score: i32, other_score: i32) -> f32 {
+	match ranking_points {
+		Some(ranking_points) => ranking_points as f32,
+		None if score > other_score => WIN_RANKING_POINTS,
+		None if score == other_score => TIE_RANKING_POINTS,
+		None => 0.0,
+	}
+}
+
+// Work out every team's qualification schedule, and how it compares to what the scouted averages say should have happened.
+// The teams should include the average team under 0, which is used for teams that haven't been scouted.
+pub fn analyze_schedules(
+	teams: &HashMap<u32, TeamInfo>,
+	tba_matches: &HashMap<(MatchType, u32), TbaMatch>,
+) -> HashMap<u32, TeamSchedule> {
+	let mut schedules = HashMap::new();
+	for ((match_type, match_number), tba_match) in tba_matches.iter() {
+		if *match_type != MatchType::Qualification {
+			continue;
+		}
+		let prediction =
+			prediction::predict_match(teams, &tba_match.blue_teams, &tba_match.red_teams);
+		let alliances = [
+			(
+				&tba_match.blue_teams,
+				&tba_match.red_teams,
+				&prediction.blue,
+				prediction.blue_win_probability,
+				actual_ranking_points(
+					tba_match.blue_ranking_points,
+					tba_match.blue_score,
+					tba_match.red_score,
+				),
+			),
+			(
+				&tba_match.red_teams,
+				&tba_match.blue_teams,
+				&prediction.red,
+				prediction.red_win_probability,
+				actual_ranking_points(
+					tba_match.red_ranking_points,
+					tba_match.red_score,
+					tba_match.blue_score,
+				),
+			),
+		];
+		for (alliance, opponents, alliance_prediction, win_probability, ranking_points) in
+			alliances.iter()
+		{
+			for team_number in alliance.iter() {
+				let allies = alliance
+					.iter()
+					.filter(|t| *t != team_number)
+					.copied()
+					.collect::<Vec<_>>();
+				schedules
+					.entry(*team_number)
+					.or_insert_with(|| TeamSchedule {
+						team_number: *team_number,
+						..TeamSchedule::default()
+					})
+					.matches
+					.push(ScheduleMatch {
+						match_number: *match_number,
+						played: tba_match.played,
+						ally_strength: strength(teams, &allies),
+						opponent_strength: strength(teams, opponents),
+						allies,
+						opponents: opponents.to_vec(),
+						win_probability: *win_probability,
+						expected_ranking_points: alliance_prediction.expected_ranking_points,
+						actual_ranking_points: if tba_match.played {
+							Some(*ranking_points)
+						} else {
+							None
+						},
+					});
+			}
+		}
+	}
+
+	for schedule in schedules.values_mut() {
+		schedule.matches.sort_by_key(|m| m.match_number);
+		for schedule_match in schedule.matches.iter() {
+			match schedule_match.actual_ranking_points {
+				Some(actual_ranking_points) => {
+					schedule.played_matches += 1;
+					schedule.ally_strength += schedule_match.ally_strength;
+					schedule.opponent_strength += schedule_match.opponent_strength;
+					schedule.expected_ranking_points += schedule_match.expected_ranking_points;
+					schedule.actual_ranking_points += actual_ranking_points;
+				}
+				None => {
+					schedule.remaining_matches += 1;
+					schedule.remaining_ally_strength += schedule_match.ally_strength;
+					schedule.remaining_opponent_strength += schedule_match.opponent_strength;
+					schedule.remaining_expected_ranking_points +=
+						schedule_match.expected_ranking_points;
+				}
+			}
+		}
+		schedule.luck = schedule.actual_ranking_points - schedule.expected_ranking_points;
+		if schedule.played_matches > 0 {
+			schedule.ally_strength /= schedule.played_matches as f32;
+			schedule.opponent_strength /= schedule.played_matches as f32;
+		}
+		if schedule.remaining_matches > 0 {
+			schedule.remaining_ally_strength /= schedule.remaining_matches as f32;
+			schedule.remaining_opponent_strength /= schedule.remaining_matches as f32;
+		}
+	}
+	schedules
+}
+
+pub fn get_schedule_strength(database: &Database) -> Vec<TeamSchedule> {
+	let teams = prediction::team_map(analysis::analyze_data(
+		database,
+		&AnalysisOptions::default(),
+	));
+	let (_, tba_matches) = analysis::get_tba_data();
+	let mut schedules = analyze_schedules(&teams, &tba_matches)
+		.into_values()
+		.collect::<Vec<_>>();
+	schedules.sort_by_key(|s| s.team_number);
+	schedules
+}
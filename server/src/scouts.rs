@@ -0,0 +1,233 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::analysis::{self, TbaMatch};
+use crate::data::{MatchInfo, MatchType};
+use crate::database::{current_time, Database, DatabaseError};
+
+// How long a match lasts, so submission delays are counted from the end of the match instead of the start.
+const MATCH_LENGTH_SECONDS: f32 = 150.0;
+// Two scouts disagree if their scores for the same robot are further apart than this, which is about one gamepiece.
+const DISAGREEMENT_POINTS: f32 = 3.0;
+const HOUR_MILLIS: u64 = 60 * 60 * 1000;
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScoutStats {
+	pub scout: String,
+	pub match_records: u32,
+	pub pit_records: u32,
+	// Robots given to this scout by the assignment scheduler, and how many of those they've sent in.
+	pub assigned_matches: u32,
+	pub completed_assignments: u32,
+	pub records_last_hour: u32,
+	// Matches in a row up to this scout's latest one, scouts that have been going for a long time might need a break.
+	pub current_streak: u32,
+	// Seconds between the end of the match and the record being saved.
+	pub average_submission_delay: Option<f32>,
+	// Records for robots someone else scouted in the same match too.
+	pub co_scouted_matches: u32,
+	// Share of those where this scout's score was more than a gamepiece from the others' average.
+	pub disagreement_rate: Option<f32>,
+	pub average_disagreement: Option<f32>,
+	pub official_score_samples: u32,
+	// How far off the scouted alliance score was from what TBA says the robots scored, in alliances this scout helped scout.
+	pub official_score_error: Option<f32>,
+}
+
+// Scouts type their own names on the tablets, so names are compared without case or the spaces around them.
+pub fn scout_key(name: &str) -> String {
+	name.trim().to_lowercase()
+}
+
+fn score(match_info: &MatchInfo) -> f32 {
+	analysis::calculate_auto_score(match_info) + analysis::calculate_teleop_score(match_info)
+}
+
+fn average(total: f32, count: u32) -> Option<f32> {
+	if count > 0 {
+		Some(total / count as f32)
+	} else {
+		None
+	}
+}
+
+// Running totals while going through the records.
+#[derive(Default)]
+struct ScoutTotals {
+	stats: ScoutStats,
+	match_numbers: Vec<u32>,
+	delay: f32,
+	delay_samples: u32,
+	disagreements: u32,
+	disagreement: f32,
+	official_error: f32,
+}
+
+// Empty names are from before scouts were recorded, those records aren't counted for anyone.
+// Stats are shown under however the name was first written.
+fn scout_totals<'a>(
+	totals: &'a mut HashMap<String, ScoutTotals>,
+	scout: &str,
+) -> Option<&'a mut ScoutTotals> {
+	let key = scout_key(scout);
+	if key.is_empty() {
+		return None;
+	}
+	Some(totals.entry(key).or_insert_with(|| ScoutTotals {
+		stats: ScoutStats {
+			scout: scout.trim().to_string(),
+			..ScoutStats::default()
+		},
+		..ScoutTotals::default()
+	}))
+}
+
+pub fn get_scout_stats(database: &Database) -> Result<Vec<ScoutStats>, DatabaseError> {
+	let (_, tba_matches) = analysis::get_tba_data();
+	let now = current_time();
+	let matches = database.get_all_matches().collect::<Result<Vec<_>, _>>()?;
+	let scout_matches = database.get_all_scout_matches()?;
+	let mut totals: HashMap<String, ScoutTotals> = HashMap::new();
+	for match_info in scout_matches.iter() {
+		let tba_match = tba_matches.get(&(match_info.match_category, match_info.match_number));
+		if let Some(scout) = scout_totals(&mut totals, &match_info.scout) {
+			scout.stats.match_records += 1;
+			if now.saturating_sub(match_info.last_modified_time) < HOUR_MILLIS {
+				scout.stats.records_last_hour += 1;
+			}
+			if match_info.match_category == MatchType::Qualification {
+				scout.match_numbers.push(match_info.match_number);
+			}
+			if let Some(actual_time) = tba_match.and_then(|m| m.actual_time) {
+				let saved = match_info.last_modified_time as f32 / 1000.0;
+				scout.delay += (saved - actual_time as f32 - MATCH_LENGTH_SECONDS).max(0.0);
+				scout.delay_samples += 1;
+			}
+		}
+	}
+
+	// Compare each record with the average of the other scouts that watched the same robot in the same match.
+	let mut by_robot: HashMap<(MatchType, u32, u32), Vec<&MatchInfo>> = HashMap::new();
+	for match_info in scout_matches.iter() {
+		by_robot
+			.entry((
+				match_info.match_category,
+				match_info.match_number,
+				match_info.team_number,
+			))
+			.or_default()
+			.push(match_info);
+	}
+	for records in by_robot.values() {
+		for record in records.iter() {
+			let others = records
+				.iter()
+				.filter(|r| scout_key(&r.scout) != scout_key(&record.scout))
+				.map(|r| score(r))
+				.collect::<Vec<_>>();
+			let others_average = match average(others.iter().sum(), others.len() as u32) {
+				Some(others_average) => others_average,
+				None => continue,
+			};
+			let difference = (score(record) - others_average).abs();
+			if let Some(scout) = scout_totals(&mut totals, &record.scout) {
+				scout.stats.co_scouted_matches += 1;
+				scout.disagreement += difference;
+				if difference > DISAGREEMENT_POINTS {
+					scout.disagreements += 1;
+				}
+			}
+		}
+	}
+
+	// Compare alliances where every robot was scouted against the official score.
+	let mut by_match: HashMap<(MatchType, u32), Vec<&MatchInfo>> = HashMap::new();
+	for match_info in matches.iter() {
+		by_match
+			.entry((match_info.match_category, match_info.match_number))
+			.or_default()
+			.push(match_info);
+	}
+	for (game, records) in by_match.iter() {
+		let tba_match: &TbaMatch = match tba_matches.get(game) {
+			Some(tba_match) => tba_match,
+			None => continue,
+		};
+		let alliances = [
+			(&tba_match.blue_teams, tba_match.blue_robot_points),
+			(&tba_match.red_teams, tba_match.red_robot_points),
+		];
+		for (teams, official) in alliances.iter() {
+			let official = match official {
+				Some(official) => *official as f32,
+				None => continue,
+			};
+			let scouted = records
+				.iter()
+				.filter(|r| teams.contains(&r.team_number))
+				.collect::<Vec<_>>();
+			if scouted.len() < teams.len() {
+				continue;
+			}
+			let error = (scouted.iter().map(|r| score(r)).sum::<f32>() - official).abs();
+			for record in scouted {
+				if let Some(scout) = scout_totals(&mut totals, &record.scout) {
+					scout.stats.official_score_samples += 1;
+					scout.official_error += error;
+				}
+			}
+		}
+	}
+
+	let assignments = database.get_assignments()?.unwrap_or_default();
+	for assignment in assignments.assignments.iter() {
+		let completed = scout_matches.iter().any(|m| {
+			m.match_category == MatchType::Qualification
+				&& m.match_number == assignment.match_number
+				&& m.team_number == assignment.team_number
+				&& scout_key(&m.scout) == scout_key(&assignment.scout)
+		});
+		if let Some(scout) = scout_totals(&mut totals, &assignment.scout) {
+			scout.stats.assigned_matches += 1;
+			if completed {
+				scout.stats.completed_assignments += 1;
+			}
+		}
+	}
+
+	for robot_info in database.get_all_robots() {
+		if let Some(scout) = scout_totals(&mut totals, &robot_info?.scout) {
+			scout.stats.pit_records += 1;
+		}
+	}
+
+	let mut stats = totals
+		.into_values()
+		.map(|mut totals| {
+			totals.match_numbers.sort_unstable();
+			totals.match_numbers.dedup();
+			let mut streak = 0;
+			let mut previous = None;
+			for match_number in totals.match_numbers.iter().rev() {
+				match previous {
+					Some(previous) if previous != match_number + 1 => break,
+					_ => streak += 1,
+				}
+				previous = Some(*match_number);
+			}
+			let mut stats = totals.stats;
+			stats.current_streak = streak;
+			stats.average_submission_delay = average(totals.delay, totals.delay_samples);
+			stats.disagreement_rate =
+				average(totals.disagreements as f32, stats.co_scouted_matches);
+			stats.average_disagreement = average(totals.disagreement, stats.co_scouted_matches);
+			stats.official_score_error =
+				average(totals.official_error, stats.official_score_samples);
+			stats
+		})
+		.collect::<Vec<_>>();
+	stats.sort_by(|a, b| a.scout.cmp(&b.scout));
+	Ok(stats)
+}
@@ -0,0 +1,27 @@
+// Scoring rules for the 2023 game (Charged Up), everything that depends on the game should come from here.
+
+// Points for a gamepiece in the hybrid, middle and high rows, in that order.
+pub const AUTO_ROW_POINTS: [f32; 3] = [3.0, 4.0, 6.0];
+pub const TELEOP_ROW_POINTS: [f32; 3] = [2.0, 3.0, 5.0];
+// Extra points for every gamepiece put on a node that's already full, once the whole grid is full.
+pub const SUPERCHARGE_POINTS: f32 = 3.0;
+pub const MOBILITY_POINTS: f32 = 3.0;
+
+pub const AUTO_DOCKED_POINTS: f32 = 8.0;
+pub const AUTO_ENGAGED_POINTS: f32 = 12.0;
+pub const PARK_POINTS: f32 = 2.0;
+pub const TELEOP_DOCKED_POINTS: f32 = 6.0;
+pub const TELEOP_ENGAGED_POINTS: f32 = 10.0;
+
+// Nodes in each alliance's grid. Hybrid nodes take either gamepiece, the middle and high rows have separate cone and cube nodes.
+pub const HYBRID_NODES: f32 = 9.0;
+pub const CONE_NODES_PER_ROW: f32 = 6.0;
+pub const CUBE_NODES_PER_ROW: f32 = 3.0;
+pub const NODES_PER_LINK: f32 = 3.0;
+
+// Charge station points needed for the activation bonus ranking point.
+pub const ACTIVATION_THRESHOLD: f32 = 26.0;
+// Links needed for the sustainability bonus ranking point.
+pub const SUSTAINABILITY_LINKS: f32 = 5.0;
+pub const WIN_RANKING_POINTS: f32 = 2.0;
+pub const TIE_RANKING_POINTS: f32 = 1.0;
@@ -1,5 +1,11 @@
+use crate::images::{ImageMeta, ImageSize, ImageUpload};
 use crate::{Database, Info};
 use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::Read;
+
+// How many images to send in one request, photos are big and the venue network isn't.
+const IMAGE_BATCH_SIZE: usize = 5;
 
 #[derive(Debug, Deserialize)]
 #[serde(from = "WebResultInner<T>")]
@@ -23,10 +29,11 @@ impl<T> From<WebResultInner<T>> for WebResult<T> {
 }
 
 pub fn try_sync(database: &Database, url: &str) -> Result<(), String> {
+	let infos = database.get_info_list().map_err(|e| e.to_string())?;
 	serde_json::from_str::<WebResult<()>>(
 		&ureq::put(&format!("{}/api/push", url))
 			.set("Content-Type", "application/json")
-			.send_json(&database.get_info_list())
+			.send_json(infos)
 			.map_err(|e| e.to_string())?
 			.into_string()
 			.map_err(|e| e.to_string())?,
@@ -45,6 +52,93 @@ pub fn try_sync(database: &Database, url: &str) -> Result<(), String> {
 	.ok_or_else(|| "No data".to_string())?;
 	database.merge_info(&new_info).map_err(|e| e.to_string())?;
 	ureq::get(&format!("{}/api/pull", url)).call().unwrap();
+	sync_images(database, url)?;
 	println!("Synchronized with {}.", url);
 	Ok(())
 }
+
+// Robot info only links to images, so send the images the other server is missing and fetch the ones we are.
+// Captions, tags and deletions are synced the same way, by sending whichever side's details are newer.
+fn sync_images(database: &Database, url: &str) -> Result<(), String> {
+	let remote = serde_json::from_str::<WebResult<Vec<ImageMeta>>>(
+		&ureq::get(&format!("{}/api/images", url))
+			.call()
+			.map_err(|e| e.to_string())?
+			.into_string()
+			.map_err(|e| e.to_string())?,
+	)
+	.map_err(|e| e.to_string())?
+	.0?
+	.unwrap_or_default();
+	let local = database.get_all_image_meta().map_err(|e| e.to_string())?;
+	let remote_by_hash = remote
+		.iter()
+		.map(|m| (&m.hash, m))
+		.collect::<HashMap<_, _>>();
+	let local_by_hash = local
+		.iter()
+		.map(|m| (&m.hash, m))
+		.collect::<HashMap<_, _>>();
+
+	let newer_here = local
+		.iter()
+		.filter(|m| {
+			remote_by_hash
+				.get(&m.hash)
+				.is_none_or(|r| r.last_modified_time < m.last_modified_time)
+		})
+		.collect::<Vec<_>>();
+	for batch in newer_here.chunks(IMAGE_BATCH_SIZE) {
+		let mut uploads = Vec::new();
+		for meta in batch {
+			// A deleted image's file is gone, so it needs the data to come back.
+			let has_file = remote_by_hash.get(&meta.hash).is_some_and(|r| !r.deleted);
+			let data = if has_file || meta.deleted {
+				None
+			} else {
+				database
+					.get_image(&meta.hash, ImageSize::Original)
+					.map_err(|e| e.to_string())?
+					.map(|(_, data)| base64::encode(data))
+			};
+			uploads.push(ImageUpload {
+				meta: (*meta).clone(),
+				data,
+			});
+		}
+		serde_json::from_str::<WebResult<()>>(
+			&ureq::put(&format!("{}/api/images", url))
+				.set("Content-Type", "application/json")
+				.send_json(uploads)
+				.map_err(|e| e.to_string())?
+				.into_string()
+				.map_err(|e| e.to_string())?,
+		)
+		.map_err(|e| e.to_string())?
+		.0?;
+	}
+
+	for meta in remote.iter() {
+		match local_by_hash.get(&meta.hash) {
+			Some(local) if local.last_modified_time >= meta.last_modified_time => {}
+			Some(local) if !local.deleted || meta.deleted => {
+				database.merge_image_meta(meta).map_err(|e| e.to_string())?
+			}
+			None if meta.deleted => database.merge_image_meta(meta).map_err(|e| e.to_string())?,
+			_ => {
+				let mut data = Vec::new();
+				ureq::get(&format!("{}/api/img", url))
+					.query("id", &meta.hash)
+					.call()
+					.map_err(|e| e.to_string())?
+					.into_reader()
+					.read_to_end(&mut data)
+					.map_err(|e| e.to_string())?;
+				database
+					.write_image_with_meta(meta, &data)
+					.map_err(|e| e.to_string())?;
+			}
+		}
+	}
+	Ok(())
+}
@@ -5,6 +5,8 @@ use serde::{Deserialize, Serialize};
 
 use crate::analysis::RawMatchData;
 use crate::data::{MatchType, RobotInfo};
+use crate::images;
+use crate::trends::{self, TeamTrend, TrendOptions};
 use crate::{Database, MatchInfo};
 
 #[derive(Debug, PartialEq, Serialize)]
@@ -32,6 +34,7 @@ pub struct FullTeamInfo {
 	pub matches: Vec<FullMatchInfo>,
 	pub images: Vec<String>,
 	pub pit_visits: Vec<RobotInfo>,
+	pub trend: TeamTrend,
 }
 
 #[derive(Debug, Deserialize, PartialEq, Eq)]
@@ -56,21 +59,46 @@ fn team_key_to_number(team_key: &str) -> u32 {
 	team_key.replace("frc", "").parse::<u32>().unwrap()
 }
 
-pub fn get_team_info(database: &Database, team_number: u32) -> FullTeamInfo {
-	let mut team_info = FullTeamInfo::default();
+pub fn get_team_info(
+	database: &Database,
+	team_number: u32,
+	trend_options: &TrendOptions,
+) -> FullTeamInfo {
+	let mut team_info = FullTeamInfo {
+		team_number,
+		trend: trends::get_team_trend(database, team_number, trend_options),
+		..FullTeamInfo::default()
+	};
 
 	let mut local_team_pits = database
 		.get_all_robots()
 		.filter_map(|r| r.ok())
 		.filter(|r| r.team_number == team_number)
 		.collect::<Vec<_>>();
-	local_team_pits.sort_by(|a, b| a.visit_number.cmp(&b.visit_number));
-	for pit_data in local_team_pits {
+	local_team_pits.sort_by_key(|a| a.visit_number);
+	let image_meta = database
+		.get_all_image_meta()
+		.unwrap_or_default()
+		.into_iter()
+		.map(|meta| (meta.hash.clone(), meta))
+		.collect::<HashMap<_, _>>();
+	let meta =
+		|image: &String| images::image_hash_from_url(image).and_then(|hash| image_meta.get(hash));
+	for mut pit_data in local_team_pits {
+		pit_data
+			.images
+			.retain(|image| !meta(image).is_some_and(|meta| meta.deleted));
 		for image in pit_data.images.iter() {
-			team_info.images.push(image.clone());
+			if !team_info.images.contains(image) {
+				team_info.images.push(image.clone());
+			}
 		}
 		team_info.pit_visits.push(pit_data);
 	}
+	// The image picked as the team's preferred one goes first, ahead of anything from TBA.
+	team_info
+		.images
+		.sort_by_key(|image| !meta(image).is_some_and(|meta| meta.preferred));
 
 	if let Ok(resp) = ureq::get(&format!(
 		"https://www.thebluealliance.com/api/v3/team/frc{}/media/2022",
@@ -136,9 +164,7 @@ pub fn get_team_info(database: &Database, team_number: u32) -> FullTeamInfo {
 						blue_teams: tba_match
 							.alliances
 							.blue
-							.team_keys
-							.iter()
-							.chain(tba_match.alliances.blue.surrogate_team_keys.iter())
+							.all_team_keys()
 							.map(|team| {
 								let team_number = team_key_to_number(team);
 								(
@@ -150,9 +176,7 @@ pub fn get_team_info(database: &Database, team_number: u32) -> FullTeamInfo {
 						red_teams: tba_match
 							.alliances
 							.red
-							.team_keys
-							.iter()
-							.chain(tba_match.alliances.red.surrogate_team_keys.iter())
+							.all_team_keys()
 							.map(|team| {
 								let team_number = team_key_to_number(team);
 								(
@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::analysis::{calculate_auto_score, calculate_teleop_score};
+use crate::data::{MatchInfo, MatchType};
+use crate::Database;
+
+const DEFAULT_ROLLING_WINDOW: usize = 3;
+const DEFAULT_DECAY: f32 = 0.85;
+
+#[derive(Debug, Default)]
+pub struct TrendOptions {
+	// How many matches the rolling average looks back over.
+	pub window: Option<usize>,
+	// How much each older match is worth compared to the one after it, between 0 and 1.
+	pub decay: Option<f32>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrendPoint {
+	pub match_number: u32,
+	pub match_category: MatchType,
+	pub time: u64,
+	pub auto_score: f32,
+	pub teleop_score: f32,
+	pub total_score: f32,
+	pub rolling_average: f32,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TeamTrend {
+	pub team_number: u32,
+	pub window: usize,
+	pub decay: f32,
+	pub points: Vec<TrendPoint>,
+	pub rolling_average: f32,
+	pub recency_weighted_average: f32,
+	pub trend_slope: f32,
+}
+
+// Sort key for putting matches in the order they were played: practice before qualification, then by match number and scouting time.
+pub fn match_order_key(match_info: &MatchInfo) -> (u8, u32, u64) {
+	let category = match match_info.match_category {
+		MatchType::Practice => 0,
+		MatchType::Qualification => 1,
+	};
+	(
+		category,
+		match_info.match_number,
+		match_info.last_modified_time,
+	)
+}
+
+// Weight of every match in an ordered list, where each team's most recent match is worth 1 and every match before it is worth `decay` times the next one.
+pub fn recency_weights(matches: &[MatchInfo], decay: Option<f32>) -> Vec<f32> {
+	let decay = match decay {
+		Some(decay) if decay.is_finite() => decay.clamp(0.0, 1.0),
+		_ => return vec![1.0; matches.len()],
+	};
+	let mut newer_matches = HashMap::new();
+	let mut weights = vec![0.0; matches.len()];
+	for (i, match_info) in matches.iter().enumerate().rev() {
+		let count = newer_matches.entry(match_info.team_number).or_insert(0);
+		weights[i] = decay.powi(*count);
+		*count += 1;
+	}
+	weights
+}
+
+// Exponentially decayed average, where the last value is the most recent.
+pub fn recency_weighted_average(values: &[f32], decay: f32) -> f32 {
+	let (mut total, mut weight_total, mut weight) = (0.0, 0.0, 1.0);
+	for value in values.iter().rev() {
+		total += value * weight;
+		weight_total += weight;
+		weight *= decay;
+	}
+	if weight_total > 0.0 {
+		total / weight_total
+	} else {
+		0.0
+	}
+}
+
+// Least squares slope of the values against the order they were played in, in points per match.
+pub fn trend_slope(values: &[f32]) -> f32 {
+	if values.len() < 2 {
+		return 0.0;
+	}
+	let count = values.len() as f32;
+	let mean_x = (count - 1.0) / 2.0;
+	let mean_y = values.iter().sum::<f32>() / count;
+	let (mut covariance, mut variance) = (0.0, 0.0);
+	for (x, y) in values.iter().enumerate() {
+		let dx = x as f32 - mean_x;
+		covariance += dx * (y - mean_y);
+		variance += dx * dx;
+	}
+	covariance / variance
+}
+
+pub fn calculate_trend(
+	team_number: u32,
+	mut matches: Vec<MatchInfo>,
+	options: &TrendOptions,
+) -> TeamTrend {
+	let window = options.window.unwrap_or(DEFAULT_ROLLING_WINDOW).max(1);
+	let decay = options
+		.decay
+		.filter(|decay| decay.is_finite())
+		.unwrap_or(DEFAULT_DECAY)
+		.clamp(0.0, 1.0);
+	matches.sort_by_key(match_order_key);
+
+	let mut totals = Vec::new();
+	let mut points = Vec::new();
+	for match_info in matches {
+		let auto_score = calculate_auto_score(&match_info);
+		let teleop_score = calculate_teleop_score(&match_info);
+		totals.push(auto_score + teleop_score);
+		let recent = &totals[totals.len().saturating_sub(window)..];
+		points.push(TrendPoint {
+			match_number: match_info.match_number,
+			match_category: match_info.match_category,
+			time: match_info.last_modified_time,
+			auto_score,
+			teleop_score,
+			total_score: auto_score + teleop_score,
+			rolling_average: recent.iter().sum::<f32>() / recent.len() as f32,
+		});
+	}
+
+	TeamTrend {
+		team_number,
+		window,
+		decay,
+		rolling_average: points.last().map(|p| p.rolling_average).unwrap_or(0.0),
+		recency_weighted_average: recency_weighted_average(&totals, decay),
+		trend_slope: trend_slope(&totals),
+		points,
+	}
+}
+
+pub fn get_team_trend(database: &Database, team_number: u32, options: &TrendOptions) -> TeamTrend {
+	let matches = database
+		.get_all_matches()
+		.filter_map(|m| m.ok())
+		.filter(|m| m.team_number == team_number)
+		.collect();
+	calculate_trend(team_number, matches, options)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn match_for(team_number: u32) -> MatchInfo {
+		MatchInfo {
+			team_number,
+			..MatchInfo::default()
+		}
+	}
+
+	#[test]
+	fn recency_weights_decay_per_team() {
+		let matches = [match_for(1), match_for(2), match_for(1), match_for(1)];
+		assert_eq!(
+			recency_weights(&matches, Some(0.5)),
+			vec![0.25, 1.0, 0.5, 1.0]
+		);
+		assert_eq!(recency_weights(&matches, None), vec![1.0; 4]);
+	}
+
+	#[test]
+	fn recency_weights_ignore_non_finite_decay() {
+		let matches = [match_for(1), match_for(1)];
+		assert_eq!(recency_weights(&matches, Some(f32::NAN)), vec![1.0; 2]);
+		assert_eq!(recency_weights(&matches, Some(f32::INFINITY)), vec![1.0; 2]);
+	}
+
+	#[test]
+	fn trend_slope_of_a_line() {
+		assert_eq!(trend_slope(&[10.0, 12.0, 14.0, 16.0]), 2.0);
+		assert_eq!(trend_slope(&[5.0]), 0.0);
+	}
+}